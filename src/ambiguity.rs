@@ -0,0 +1,207 @@
+use std::fmt;
+use std::ptr;
+use grammar::Grammar;
+use language::Bound;
+use parse_tree::{ParseTree, ParseTreeNode};
+use production::Production;
+use term::Term;
+
+/// A sentence of a `Grammar` with two distinct derivations
+#[derive(PartialEq, Debug, Clone)]
+pub struct Ambiguity<'gram> {
+    pub sentence: String,
+    pub first: ParseTree<'gram>,
+    pub second: ParseTree<'gram>,
+    /// Child indices leading from the roots of both trees to the outermost
+    /// derivations which differ, either in the alternative expanded or in
+    /// how the sentence is split among its terms
+    pub path: Vec<usize>,
+    /// The production of the nonterminal where the trees diverge
+    pub production: &'gram Production,
+}
+
+impl<'gram> fmt::Display for Ambiguity<'gram> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} is ambiguous, diverging at {}",
+            Term::Terminal(self.sentence.clone()),
+            self.production
+        )?;
+        write!(f, "{}{}", self.first, self.second)
+    }
+}
+
+/// Length in bytes of the text each child derives
+fn spans(tree: &ParseTree) -> Vec<usize> {
+    tree.children_iter()
+        .map(|child| match *child {
+            ParseTreeNode::Terminal(ref t) => t.len(),
+            ParseTreeNode::Nonterminal(ref t) => t.flatten().len(),
+        })
+        .collect()
+}
+
+/// Whether `first` and `second` expand the very same alternatives
+/// throughout. Alternatives are told apart by identity, as a production
+/// may repeat an alternative.
+fn same(first: &ParseTree, second: &ParseTree) -> bool {
+    ptr::eq(first.rhs, second.rhs)
+        && first
+            .children_iter()
+            .zip(second.children_iter())
+            .all(|pair| match pair {
+                (ParseTreeNode::Nonterminal(a), ParseTreeNode::Nonterminal(b)) => same(a, b),
+                (a, b) => a == b,
+            })
+}
+
+/// Path to the outermost derivations where `first` and `second` differ
+fn divergence(first: &ParseTree, second: &ParseTree) -> Vec<usize> {
+    let mut path = vec![];
+    let (mut first, mut second) = (first, second);
+    loop {
+        if !ptr::eq(first.rhs, second.rhs) || spans(first) != spans(second) {
+            return path;
+        }
+        let differing = first
+            .children_iter()
+            .zip(second.children_iter())
+            .enumerate()
+            .filter_map(|(i, pair)| match pair {
+                (ParseTreeNode::Nonterminal(a), ParseTreeNode::Nonterminal(b)) => Some((i, a, b)),
+                _ => None,
+            })
+            .find(|&(_, a, b)| !same(a, b));
+        match differing {
+            Some((i, a, b)) => {
+                path.push(i);
+                first = a;
+                second = b;
+            }
+            None => return path,
+        }
+    }
+}
+
+impl Grammar {
+    /// Find every sentence within `bound` which self derives from lhs of
+    /// first production in more than one way, along with two of its
+    /// derivations. Sentences are reported shortest first.
+    ///
+    /// Sentences are enumerated exhaustively, so an empty result proves the
+    /// grammar unambiguous only up to the bound, and for classes of many
+    /// chars only over those `sentences_bounded` enumerates. The number of
+    /// sentences grows quickly with the bound, so keep it modest.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Bound, Grammar};
+    ///
+    /// fn main() {
+    ///     let input = "<sum> ::= <sum> \"+\" <sum> | \"1\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     for ambiguity in grammar.find_ambiguities(Bound::Length(5)) {
+    ///         println!("{}", ambiguity);
+    ///     }
+    ///
+    ///     # let ambiguities = grammar.find_ambiguities(Bound::Length(5));
+    ///     # assert_eq!(ambiguities.len(), 1);
+    ///     # assert_eq!(ambiguities[0].sentence, "1+1+1");
+    /// }
+    /// ```
+    pub fn find_ambiguities(&self, bound: Bound) -> Vec<Ambiguity<'_>> {
+        let mut sentences = self.sentences_bounded(bound).collect::<Vec<_>>();
+        sentences.sort_by_key(|sentence| sentence.chars().count());
+
+        let mut ambiguities = vec![];
+        for sentence in sentences {
+            let mut trees = match self.parse_input_all(&sentence, 2) {
+                Ok(ref trees) if trees.len() < 2 => continue,
+                Ok(trees) => trees,
+                Err(_) => continue,
+            };
+            let second = trees.pop().expect("two trees should be parsed");
+            let first = trees.pop().expect("two trees should be parsed");
+            let path = divergence(&first, &second);
+            let lhs = first
+                .subtree(&path)
+                .expect("divergence should be within tree")
+                .lhs;
+            let production = self.production(
+                self.production_index(lhs)
+                    .expect("derived nonterminal should have production"),
+            );
+            ambiguities.push(Ambiguity {
+                sentence,
+                first,
+                second,
+                path,
+                production,
+            });
+        }
+        ambiguities
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ambiguous_split() {
+        let grammar = Grammar::from_str("<sum> ::= <sum> \"+\" <sum> | \"1\"").unwrap();
+        let ambiguities = grammar.find_ambiguities(Bound::Length(7));
+        let sentences = ambiguities
+            .iter()
+            .map(|a| a.sentence.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["1+1+1", "1+1+1+1"]);
+
+        let ambiguity = &ambiguities[0];
+        assert!(ambiguity.path.is_empty());
+        assert_eq!(ambiguity.production.lhs.to_string(), "<sum>");
+        assert_ne!(ambiguity.first, ambiguity.second);
+        assert_eq!(ambiguity.first.flatten(), "1+1+1");
+        assert_eq!(ambiguity.second.flatten(), "1+1+1");
+        assert!(ambiguity.to_string().starts_with(
+            "\"1+1+1\" is ambiguous, diverging at <sum> ::= <sum> \"+\" <sum> | \"1\"\n"
+        ));
+    }
+
+    #[test]
+    fn ambiguous_alternatives() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"(\" <t> \")\"
+            <t> ::= <a> | <b> | \"y\"
+            <a> ::= \"x\"
+            <b> ::= \"x\"",
+        ).unwrap();
+        let ambiguities = grammar.find_ambiguities(Bound::Depth(3));
+        assert_eq!(ambiguities.len(), 1);
+        assert_eq!(ambiguities[0].sentence, "(x)");
+        assert_eq!(ambiguities[0].path, vec![1]);
+        assert_eq!(ambiguities[0].production.lhs.to_string(), "<t>");
+        assert!(grammar.find_ambiguities(Bound::Depth(2)).is_empty());
+    }
+
+    #[test]
+    fn ambiguous_any() {
+        let grammar = Grammar::from_str("<s> ::= ANY ANY | \"ab\"").unwrap();
+        let ambiguities = grammar.find_ambiguities(Bound::Length(2));
+        assert_eq!(ambiguities.len(), 1);
+        assert_eq!(ambiguities[0].sentence, "ab");
+    }
+
+    #[test]
+    fn unambiguous() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        assert!(grammar.find_ambiguities(Bound::Length(4)).is_empty());
+        assert!(Grammar::new().find_ambiguities(Bound::Length(4)).is_empty());
+    }
+}
@@ -0,0 +1,1071 @@
+use std::char;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::iter;
+use rand::Rng;
+use class::{
+    after, before, enumerated, is_enumerable, merge, printable, sample, CharClass, CharRange, ANY,
+};
+use error::Error;
+use graph::strongly_connected;
+
+/// Add a transition, extending the last one instead if it goes to the same
+/// state and its range ends just before `lo`
+fn push_transition(transitions: &mut Vec<(char, char, usize)>, lo: char, hi: char, to: usize) {
+    if let Some(last) = transitions.last_mut() {
+        if last.2 == to && after(last.1) == lo as u32 {
+            last.1 = hi;
+            return;
+        }
+    }
+    transitions.push((lo, hi, to));
+}
+
+/// A nondeterministic finite automaton over chars. Transitions read a char
+/// within a range, or read nothing at all.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Nfa {
+    transitions: Vec<Vec<(Option<CharRange>, usize)>>,
+    accepting: Vec<bool>,
+    start: usize,
+}
+
+impl Nfa {
+    /// Construct an `Nfa` accepting whole inputs matched by `pattern`, by
+    /// Thompson's construction. The syntax is that `Dfa::to_regex` writes,
+    /// along with negated classes such as `[^0-9]`, bounded repetition
+    /// `{m}`, `{m,}` and `{m,n}`, and the escapes `\n`, `\r` and `\t`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Nfa;
+    ///
+    /// fn main() {
+    ///     let nfa = Nfa::from_regex("GET /[^ ]*( HTTP/1\\.[01])?").unwrap();
+    ///     assert!(nfa.accepts("GET /index.html HTTP/1.1"));
+    ///     assert!(!nfa.accepts("POST /"));
+    /// }
+    /// ```
+    pub fn from_regex(pattern: &str) -> Result<Nfa, Error> {
+        let mut nfa = Nfa::new();
+        let end = nfa.add_regex(0, pattern)?;
+        nfa.set_accepting(end);
+        Ok(nfa)
+    }
+
+    /// Construct an `Nfa` with a single start state, accepting nothing
+    pub(crate) fn new() -> Nfa {
+        Nfa {
+            transitions: vec![vec![]],
+            accepting: vec![false],
+            start: 0,
+        }
+    }
+
+    pub(crate) fn add_state(&mut self) -> usize {
+        self.transitions.push(vec![]);
+        self.accepting.push(false);
+        self.transitions.len() - 1
+    }
+
+    pub(crate) fn add_transition(&mut self, from: usize, label: Option<CharRange>, to: usize) {
+        self.transitions[from].push((label, to));
+    }
+
+    /// Add states reading `text` from state `from`, returning the last
+    pub(crate) fn add_text(&mut self, from: usize, text: &str) -> usize {
+        text.chars().fold(from, |state, c| {
+            let next = self.add_state();
+            self.add_transition(state, Some((c, c)), next);
+            next
+        })
+    }
+
+    /// Add states reading whatever `pattern` matches from state `from`,
+    /// returning the state reached after the match
+    pub(crate) fn add_regex(&mut self, from: usize, pattern: &str) -> Result<usize, Error> {
+        let mut parser = RegexParser {
+            nfa: self,
+            chars: pattern.chars().collect(),
+            position: 0,
+        };
+        let end = parser.alternation(from)?;
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unmatched )"));
+        }
+        Ok(end)
+    }
+
+    pub(crate) fn set_accepting(&mut self, state: usize) {
+        self.accepting[state] = true;
+    }
+
+    /// Get the state the automaton begins in
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Get the number of states
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Whether the automaton accepts an input on reaching `state`
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    /// Get the transitions out of `state`, each reading a char within its
+    /// range, or nothing if it has none
+    pub fn transitions(&self, state: usize) -> &[(Option<CharRange>, usize)] {
+        &self.transitions[state]
+    }
+
+    /// The states reachable from `states` without reading anything
+    fn closure(&self, mut states: Vec<usize>) -> BTreeSet<usize> {
+        let mut closure = BTreeSet::new();
+        while let Some(state) = states.pop() {
+            if closure.insert(state) {
+                for &(label, to) in &self.transitions[state] {
+                    if label.is_none() {
+                        states.push(to);
+                    }
+                }
+            }
+        }
+        closure
+    }
+
+    /// Whether the automaton accepts `input`
+    pub fn accepts(&self, input: &str) -> bool {
+        let mut states = self.closure(vec![self.start]);
+        for c in input.chars() {
+            let next = states
+                .iter()
+                .flat_map(|&state| self.transitions[state].iter())
+                .filter_map(|&(label, to)| match label {
+                    Some((lo, hi)) if lo <= c && c <= hi => Some(to),
+                    _ => None,
+                })
+                .collect();
+            states = self.closure(next);
+        }
+        states.iter().any(|&state| self.accepting[state])
+    }
+
+    /// Convert to the minimal `Dfa` accepting the same inputs, by subset
+    /// construction. Transitions of each subset are split at every range
+    /// boundary, so the ranges of the `Dfa` never overlap.
+    pub fn to_dfa(&self) -> Dfa {
+        let start = self.closure(vec![self.start]);
+        let mut subsets = vec![start.clone()];
+        let mut ids = HashMap::new();
+        ids.insert(start, 0);
+        let mut dfa = Dfa {
+            transitions: vec![],
+            accepting: vec![],
+        };
+
+        let mut next = 0;
+        while next < subsets.len() {
+            let subset = subsets[next].clone();
+            next += 1;
+
+            let ranges = subset
+                .iter()
+                .flat_map(|&state| self.transitions[state].iter())
+                .filter_map(|&(label, to)| label.map(|range| (range, to)))
+                .collect::<Vec<_>>();
+            let mut bounds = ranges
+                .iter()
+                .flat_map(|&((lo, hi), _)| vec![lo as u32, after(hi)])
+                .collect::<Vec<_>>();
+            bounds.sort();
+            bounds.dedup();
+
+            let mut transitions = vec![];
+            for pair in bounds.windows(2) {
+                let lo = char::from_u32(pair[0]).expect("range bound should be a char");
+                let targets = ranges
+                    .iter()
+                    .filter(|&&((l, h), _)| l <= lo && lo <= h)
+                    .map(|&(_, to)| to)
+                    .collect::<Vec<_>>();
+                if targets.is_empty() {
+                    continue;
+                }
+                let target = self.closure(targets);
+                let id = match ids.get(&target) {
+                    Some(&id) => id,
+                    None => {
+                        ids.insert(target.clone(), subsets.len());
+                        subsets.push(target);
+                        subsets.len() - 1
+                    }
+                };
+                push_transition(&mut transitions, lo, before(pair[1]), id);
+            }
+
+            dfa.accepting.push(subset.iter().any(|&state| self.accepting[state]));
+            dfa.transitions.push(transitions);
+        }
+
+        dfa.minimize()
+    }
+
+    /// Write a regular expression accepting the same inputs. See `Dfa::to_regex`.
+    pub fn to_regex(&self) -> String {
+        self.to_dfa().to_regex()
+    }
+}
+
+/// A deterministic finite automaton over chars, beginning in state 0.
+/// Transitions out of a state read disjoint ranges of chars, in order; a
+/// char no transition reads rejects the input.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Dfa {
+    transitions: Vec<Vec<(char, char, usize)>>,
+    accepting: Vec<bool>,
+}
+
+impl Dfa {
+    /// Construct the minimal `Dfa` accepting whole inputs matched by
+    /// `pattern`. See `Nfa::from_regex` for its syntax.
+    pub fn from_regex(pattern: &str) -> Result<Dfa, Error> {
+        Nfa::from_regex(pattern).map(|nfa| nfa.to_dfa())
+    }
+
+    /// Get the number of states
+    pub fn state_count(&self) -> usize {
+        self.transitions.len()
+    }
+
+    /// Whether the automaton accepts an input on reaching `state`
+    pub fn is_accepting(&self, state: usize) -> bool {
+        self.accepting[state]
+    }
+
+    /// Get the transitions out of `state`, each reading a char from its
+    /// first to its second inclusive
+    pub fn transitions(&self, state: usize) -> &[(char, char, usize)] {
+        &self.transitions[state]
+    }
+
+    /// Get the state reached from `state` by reading `c`, if any
+    pub fn next(&self, state: usize, c: char) -> Option<usize> {
+        let transitions = &self.transitions[state];
+        transitions
+            .binary_search_by(|&(lo, hi, _)| {
+                if hi < c {
+                    Ordering::Less
+                } else if lo > c {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()
+            .map(|i| transitions[i].2)
+    }
+
+    /// Whether the automaton accepts `input`
+    pub fn accepts(&self, input: &str) -> bool {
+        let mut state = 0;
+        for c in input.chars() {
+            state = match self.next(state, c) {
+                Some(next) => next,
+                None => return false,
+            };
+        }
+        self.accepting[state]
+    }
+
+    /// Per state, the fewest chars read from it to reach an accepting state,
+    /// if any
+    fn distances(&self) -> Vec<Option<usize>> {
+        let mut distances = self.accepting
+            .iter()
+            .map(|&accepting| if accepting { Some(0) } else { None })
+            .collect::<Vec<_>>();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (state, transitions) in self.transitions.iter().enumerate() {
+                let nearest = transitions
+                    .iter()
+                    .filter_map(|&(_, _, to)| distances[to])
+                    .min()
+                    .map(|distance| distance + 1);
+                let nearer = match (nearest, distances[state]) {
+                    (Some(nearest), Some(distance)) => nearest < distance,
+                    (nearest, None) => nearest.is_some(),
+                    (None, _) => false,
+                };
+                if nearer {
+                    distances[state] = nearest;
+                    changed = true;
+                }
+            }
+        }
+        distances
+    }
+
+    /// Get the shortest input the automaton accepts, if it accepts any.
+    /// Where a char of a range will do, printable ASCII is preferred.
+    pub fn shortest(&self) -> Option<String> {
+        let distances = self.distances();
+        let mut distance = distances[0]?;
+        let mut state = 0;
+        let mut shortest = String::new();
+        while distance > 0 {
+            let &(lo, hi, to) = self.transitions[state]
+                .iter()
+                .find(|&&(_, _, to)| distances[to] == Some(distance - 1))
+                .expect("state nearer to acceptance should be reachable");
+            shortest.push(printable(vec![(lo, hi)])[0].0);
+            state = to;
+            distance -= 1;
+        }
+        Some(shortest)
+    }
+
+    /// Whether the automaton accepts finitely many inputs
+    pub fn is_finite(&self) -> bool {
+        // states of a minimal automaton all reach acceptance, so any cycle
+        // repeats infinitely many ways
+        let adjacency = self.transitions
+            .iter()
+            .map(|transitions| transitions.iter().map(|&(_, _, to)| to).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let cyclic = strongly_connected(&adjacency)
+            .iter()
+            .any(|component| component.len() > 1 || adjacency[component[0]].contains(&component[0]));
+        !cyclic
+    }
+
+    /// Get every input of up to `length` chars the automaton accepts.
+    /// Ranges of more than 256 chars, such as that of `.`, only stand for
+    /// up to 256 of them, from printable ASCII where they have some.
+    pub fn inputs_within(&self, length: usize) -> BTreeSet<String> {
+        let mut inputs = BTreeSet::new();
+        let mut pending = vec![(0, String::new())];
+        while let Some((state, input)) = pending.pop() {
+            if self.accepting[state] {
+                inputs.insert(input.clone());
+            }
+            if input.chars().count() == length {
+                continue;
+            }
+            for &(lo, hi, to) in &self.transitions[state] {
+                for c in enumerated(vec![(lo, hi)]) {
+                    let mut input = input.clone();
+                    input.push(c);
+                    pending.push((to, input));
+                }
+            }
+        }
+        inputs
+    }
+
+    /// Whether `inputs_within` gives every input of the length it is given,
+    /// as no range of the automaton has more than 256 chars
+    pub(crate) fn is_enumerable(&self) -> bool {
+        self.transitions
+            .iter()
+            .flat_map(|transitions| transitions.iter())
+            .all(|&(lo, hi, _)| is_enumerable(&[(lo, hi)]))
+    }
+
+    /// Sample an input the automaton accepts, or `None` if it accepts none.
+    /// Beginning from the start state, the walk stops at an accepting state
+    /// or follows one of its transitions, all equally likely. After `limit`
+    /// chars, it finishes by the fewest chars it can, so repetition is
+    /// bounded. Chars of a range are sampled from printable ASCII where it
+    /// has some.
+    pub fn sample<R: Rng>(&self, rng: &mut R, limit: usize) -> Option<String> {
+        let distances = self.distances();
+        distances[0]?;
+        let mut state = 0;
+        let mut input = String::new();
+        let mut length = 0;
+        loop {
+            let distance = distances[state].expect("walk should stay able to accept");
+            let choices = self.transitions[state]
+                .iter()
+                .filter(|&&(_, _, to)| match distances[to] {
+                    Some(d) => length < limit || d + 1 == distance,
+                    None => false,
+                })
+                .collect::<Vec<_>>();
+            if self.accepting[state]
+                && (length >= limit || rng.gen_range(0, choices.len() + 1) == 0)
+            {
+                return Some(input);
+            }
+            let &&(lo, hi, to) = rng.choose(&choices).expect("walk should stay able to accept");
+            input.push(sample(rng, &printable(vec![(lo, hi)])).expect("range should have chars"));
+            state = to;
+            length += 1;
+        }
+    }
+
+    /// Remove states from which no input is accepted, and merge states
+    /// accepting the same inputs, by Moore's partition refinement
+    fn minimize(&self) -> Dfa {
+        let count = self.transitions.len();
+        let mut live = self.accepting.clone();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for state in 0..count {
+                if !live[state] && self.transitions[state].iter().any(|&(_, _, to)| live[to]) {
+                    live[state] = true;
+                    changed = true;
+                }
+            }
+        }
+        if !live[0] {
+            return Dfa {
+                transitions: vec![vec![]],
+                accepting: vec![false],
+            };
+        }
+
+        let mut class = self.accepting
+            .iter()
+            .map(|&accepting| accepting as usize)
+            .collect::<Vec<_>>();
+        let mut classes = 0;
+        loop {
+            let mut ids = HashMap::new();
+            let mut signatures = vec![];
+            let mut refined = vec![0; count];
+            for state in (0..count).filter(|&state| live[state]) {
+                let mut transitions = vec![];
+                for &(lo, hi, to) in &self.transitions[state] {
+                    if live[to] {
+                        push_transition(&mut transitions, lo, hi, class[to]);
+                    }
+                }
+                let signature = (class[state], transitions);
+                refined[state] = match ids.get(&signature) {
+                    Some(&id) => id,
+                    None => {
+                        ids.insert(signature.clone(), signatures.len());
+                        signatures.push(signature);
+                        signatures.len() - 1
+                    }
+                };
+            }
+            class = refined;
+            if signatures.len() == classes {
+                break;
+            }
+            classes = signatures.len();
+        }
+
+        // states of a class behave alike, so any one stands for them all;
+        // classes are numbered in order of their first state, so 0 stays first
+        let mut minimal = Dfa {
+            transitions: vec![vec![]; classes],
+            accepting: vec![false; classes],
+        };
+        let mut built = vec![false; classes];
+        for state in (0..count).filter(|&state| live[state]) {
+            let c = class[state];
+            if built[c] {
+                continue;
+            }
+            built[c] = true;
+            minimal.accepting[c] = self.accepting[state];
+            for &(lo, hi, to) in &self.transitions[state] {
+                if live[to] {
+                    push_transition(&mut minimal.transitions[c], lo, hi, class[to]);
+                }
+            }
+        }
+        minimal
+    }
+
+    /// Write a regular expression accepting the same inputs, by eliminating
+    /// states one by one. The syntax is that of most regex engines: `|`,
+    /// `*`, `+`, `?`, groups, `.` for any char and classes such as `[a-z]`,
+    /// with metacharacters escaped by `\`. The empty string is written `()`,
+    /// and an automaton accepting nothing is written `[]`.
+    pub fn to_regex(&self) -> String {
+        let count = self.transitions.len();
+        let (start, end) = (count, count + 1);
+        let mut edges: Vec<Vec<Option<Regex>>> = vec![vec![None; count + 2]; count + 2];
+        edges[start][0] = Some(Regex::Epsilon);
+        for (state, transitions) in self.transitions.iter().enumerate() {
+            if self.accepting[state] {
+                edges[state][end] = Some(Regex::Epsilon);
+            }
+            for &(lo, hi, to) in transitions {
+                let edge = edges[state][to].take();
+                edges[state][to] = alt(edge, Some(Regex::Class(vec![(lo, hi)])));
+            }
+        }
+
+        let mut remaining = (0..count).collect::<Vec<_>>();
+        while !remaining.is_empty() {
+            let incoming = |edges: &[Vec<Option<Regex>>], k: usize| {
+                (0..count + 2)
+                    .filter(|&i| i != k && edges[i][k].is_some())
+                    .collect::<Vec<_>>()
+            };
+            let outgoing = |edges: &[Vec<Option<Regex>>], k: usize| {
+                (0..count + 2)
+                    .filter(|&j| j != k && edges[k][j].is_some())
+                    .collect::<Vec<_>>()
+            };
+            // eliminate the state with fewest paths through it, to keep
+            // the expression small
+            let position = (0..remaining.len())
+                .min_by_key(|&p| {
+                    incoming(&edges, remaining[p]).len() * outgoing(&edges, remaining[p]).len()
+                })
+                .expect("remaining should not be empty");
+            let k = remaining.swap_remove(position);
+
+            let looped = edges[k][k].take().map(star).unwrap_or(Regex::Epsilon);
+            let (ins, outs) = (incoming(&edges, k), outgoing(&edges, k));
+            for &i in &ins {
+                for &j in &outs {
+                    let through = concat(vec![
+                        edges[i][k].clone().expect("incoming edge should exist"),
+                        looped.clone(),
+                        edges[k][j].clone().expect("outgoing edge should exist"),
+                    ]);
+                    let edge = edges[i][j].take();
+                    edges[i][j] = alt(edge, Some(through));
+                }
+            }
+            for row in &mut edges {
+                row[k] = None;
+            }
+            for edge in &mut edges[k] {
+                *edge = None;
+            }
+        }
+
+        match edges[start][end] {
+            Some(ref regex) => regex.to_string(),
+            None => String::from("[]"),
+        }
+    }
+}
+
+/// A regular expression, simplified as it is built up
+#[derive(PartialEq, Eq, Debug, Clone)]
+enum Regex {
+    Epsilon,
+    /// Any char of the ranges, kept sorted and merged
+    Class(Vec<CharRange>),
+    Concat(Vec<Regex>),
+    Alt(Vec<Regex>),
+    Star(Box<Regex>),
+}
+
+/// Either of two expressions, where `None` matches nothing. Alternative
+/// classes are merged into one.
+fn alt(first: Option<Regex>, second: Option<Regex>) -> Option<Regex> {
+    let (first, second) = match (first, second) {
+        (None, regex) | (regex, None) => return regex,
+        (Some(first), Some(second)) => (first, second),
+    };
+
+    let mut members = vec![];
+    let mut ranges = vec![];
+    for regex in iter::once(first).chain(iter::once(second)) {
+        let flattened = match regex {
+            Regex::Alt(members) => members,
+            regex => vec![regex],
+        };
+        for member in flattened {
+            match member {
+                Regex::Class(r) => ranges.extend(r),
+                member => {
+                    if !members.contains(&member) {
+                        members.push(member);
+                    }
+                }
+            }
+        }
+    }
+    if !ranges.is_empty() {
+        members.insert(0, Regex::Class(merge(ranges)));
+    }
+
+    if members.len() == 1 {
+        members.pop()
+    } else {
+        Some(Regex::Alt(members))
+    }
+}
+
+/// Each of `parts` in turn
+fn concat(parts: Vec<Regex>) -> Regex {
+    let mut flattened = vec![];
+    for part in parts {
+        match part {
+            Regex::Epsilon => (),
+            Regex::Concat(inner) => flattened.extend(inner),
+            part => flattened.push(part),
+        }
+    }
+    match flattened.len() {
+        0 => Regex::Epsilon,
+        1 => flattened.pop().expect("one part should remain"),
+        _ => Regex::Concat(flattened),
+    }
+}
+
+/// Any number of repetitions of `regex`
+fn star(regex: Regex) -> Regex {
+    match regex {
+        Regex::Epsilon => Regex::Epsilon,
+        Regex::Star(_) => regex,
+        Regex::Alt(members) => {
+            let mut members = members
+                .into_iter()
+                .filter(|member| *member != Regex::Epsilon)
+                .collect::<Vec<_>>();
+            match members.len() {
+                0 => Regex::Epsilon,
+                1 => star(members.pop().expect("one member should remain")),
+                _ => Regex::Star(Box::new(Regex::Alt(members))),
+            }
+        }
+        regex => Regex::Star(Box::new(regex)),
+    }
+}
+
+impl Regex {
+    /// Whether self is an alternative with the empty string, written with `?`
+    fn is_optional(&self) -> bool {
+        match *self {
+            Regex::Alt(ref members) => members.contains(&Regex::Epsilon),
+            _ => false,
+        }
+    }
+
+    /// Write self so that a postfix operator may follow it
+    fn fmt_atom(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Regex::Epsilon | Regex::Class(_) => write!(f, "{}", self),
+            _ => write!(f, "({})", self),
+        }
+    }
+}
+
+fn escape(c: char) -> String {
+    const METACHARACTERS: &str = "\\.|*+?()[]{}^$";
+    if METACHARACTERS.contains(c) {
+        format!("\\{}", c)
+    } else {
+        c.to_string()
+    }
+}
+
+impl fmt::Display for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Regex::Epsilon => write!(f, "()"),
+            Regex::Class(ref ranges) => match ranges[..] {
+                [range] if range == ANY => write!(f, "."),
+                [(lo, hi)] if lo == hi => write!(f, "{}", escape(lo)),
+                _ => {
+                    // two adjacent chars read better listed than as a range
+                    let listed = ranges
+                        .iter()
+                        .flat_map(|&(lo, hi)| {
+                            if after(lo) == hi as u32 {
+                                vec![(lo, lo), (hi, hi)]
+                            } else {
+                                vec![(lo, hi)]
+                            }
+                        })
+                        .collect();
+                    write!(f, "{}", CharClass::new(listed, false))
+                }
+            },
+            Regex::Concat(ref parts) => {
+                let mut i = 0;
+                while i < parts.len() {
+                    let repeated = match parts.get(i + 1) {
+                        Some(Regex::Star(inner)) => **inner == parts[i],
+                        _ => false,
+                    };
+                    match parts[i] {
+                        Regex::Alt(_) if !parts[i].is_optional() => write!(f, "({})", parts[i])?,
+                        ref part if repeated => {
+                            part.fmt_atom(f)?;
+                            write!(f, "+")?;
+                            i += 1;
+                        }
+                        ref part => write!(f, "{}", part)?,
+                    }
+                    i += 1;
+                }
+                Ok(())
+            }
+            Regex::Alt(ref members) => {
+                let others = members
+                    .iter()
+                    .filter(|member| **member != Regex::Epsilon)
+                    .collect::<Vec<_>>();
+                match others[..] {
+                    [member] if self.is_optional() => {
+                        member.fmt_atom(f)?;
+                        write!(f, "?")
+                    }
+                    _ => {
+                        let alternatives = others
+                            .iter()
+                            .map(|member| member.to_string())
+                            .collect::<Vec<_>>()
+                            .join("|");
+                        if self.is_optional() {
+                            write!(f, "({})?", alternatives)
+                        } else {
+                            write!(f, "{}", alternatives)
+                        }
+                    }
+                }
+            }
+            Regex::Star(ref inner) => {
+                inner.fmt_atom(f)?;
+                write!(f, "*")
+            }
+        }
+    }
+}
+
+/// Recursive descent parser of regular expressions, adding states to an
+/// `Nfa` to read what they match. Each method reads from a given state and
+/// returns the state reached after the match.
+struct RegexParser<'nfa> {
+    nfa: &'nfa mut Nfa,
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl<'nfa> RegexParser<'nfa> {
+    fn error(&self, message: &str) -> Error {
+        Error::ParseError(format!("Regex error: {} at char {}", message, self.position))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Result<char, Error> {
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    /// Alternatives separated by `|`
+    fn alternation(&mut self, from: usize) -> Result<usize, Error> {
+        let exit = self.nfa.add_state();
+        loop {
+            let entry = self.nfa.add_state();
+            self.nfa.add_transition(from, None, entry);
+            let end = self.concatenation(entry)?;
+            self.nfa.add_transition(end, None, exit);
+            if self.peek() != Some('|') {
+                return Ok(exit);
+            }
+            self.position += 1;
+        }
+    }
+
+    fn concatenation(&mut self, from: usize) -> Result<usize, Error> {
+        let mut state = from;
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            state = self.repetition(state)?;
+        }
+        Ok(state)
+    }
+
+    /// An atom, repeated as a quantifier following it says
+    fn repetition(&mut self, from: usize) -> Result<usize, Error> {
+        let atom = self.position;
+        let entry = self.nfa.add_state();
+        let end = self.atom(entry)?;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => self.bounds()?,
+            _ => {
+                self.nfa.add_transition(from, None, entry);
+                return Ok(end);
+            }
+        };
+        // the quantifier, or the closing brace of bounds
+        self.position += 1;
+        if let Some(c) = self.peek() {
+            if "*+?{".contains(c) {
+                return Err(self.error("quantifier repeated"));
+            }
+        }
+
+        // the atom read once already is the first copy, and it is read
+        // again from the start for every other copy
+        let after = self.position;
+        let mut copies = vec![(entry, end)];
+        let mut copy = |parser: &mut RegexParser, n: usize| -> Result<(usize, usize), Error> {
+            while copies.len() <= n {
+                parser.position = atom;
+                let entry = parser.nfa.add_state();
+                let end = parser.atom(entry)?;
+                copies.push((entry, end));
+            }
+            parser.position = after;
+            Ok(copies[n])
+        };
+
+        let mut state = from;
+        for n in 0..min {
+            let (entry, end) = copy(self, n)?;
+            self.nfa.add_transition(state, None, entry);
+            state = end;
+        }
+        match max {
+            None => {
+                let (entry, end) = copy(self, min)?;
+                let repeat = self.nfa.add_state();
+                self.nfa.add_transition(state, None, repeat);
+                self.nfa.add_transition(repeat, None, entry);
+                self.nfa.add_transition(end, None, repeat);
+                state = repeat;
+            }
+            Some(max) => {
+                for n in min..max {
+                    let (entry, end) = copy(self, n)?;
+                    let skip = self.nfa.add_state();
+                    self.nfa.add_transition(state, None, entry);
+                    self.nfa.add_transition(end, None, skip);
+                    self.nfa.add_transition(state, None, skip);
+                    state = skip;
+                }
+            }
+        }
+        Ok(state)
+    }
+
+    /// The bounds of `{m}`, `{m,}` or `{m,n}`, leaving the closing brace unread
+    fn bounds(&mut self) -> Result<(usize, Option<usize>), Error> {
+        self.position += 1;
+        let min = self.number()?.ok_or_else(|| self.error("expected repetition count"))?;
+        let max = if self.peek() == Some(',') {
+            self.position += 1;
+            self.number()?
+        } else {
+            Some(min)
+        };
+        if self.peek() != Some('}') {
+            return Err(self.error("expected }"));
+        }
+        match max {
+            Some(max) if max < min => Err(self.error("repetition bounds out of order")),
+            _ => Ok((min, max)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Option<usize>, Error> {
+        let start = self.position;
+        while let Some('0'..='9') = self.peek() {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Ok(None);
+        }
+        let digits = self.chars[start..self.position].iter().collect::<String>();
+        digits
+            .parse()
+            .map(Some)
+            .map_err(|_| self.error("repetition count too large"))
+    }
+
+    /// A char following `\`
+    fn escaped(&mut self) -> Result<char, Error> {
+        Ok(match self.next()? {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            c => c,
+        })
+    }
+
+    fn atom(&mut self, from: usize) -> Result<usize, Error> {
+        let ranges = match self.next()? {
+            '(' => {
+                let end = self.alternation(from)?;
+                if self.next()? != ')' {
+                    return Err(self.error("expected )"));
+                }
+                return Ok(end);
+            }
+            '[' => self.class()?,
+            '.' => vec![ANY],
+            '\\' => {
+                let c = self.escaped()?;
+                vec![(c, c)]
+            }
+            '*' | '+' | '?' | '{' => {
+                self.position -= 1;
+                return Err(self.error("nothing to repeat"));
+            }
+            c => vec![(c, c)],
+        };
+        let end = self.nfa.add_state();
+        for range in ranges {
+            self.nfa.add_transition(from, Some(range), end);
+        }
+        Ok(end)
+    }
+
+    /// The chars of a class, after its opening bracket
+    fn class(&mut self) -> Result<Vec<CharRange>, Error> {
+        let (class, read) = CharClass::parse(&self.chars[self.position..])
+            .map_err(|message| self.error(&message))?;
+        self.position += read;
+        Ok(class.matched())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An automaton accepting `ab*` or `c`, with redundant states
+    fn nfa() -> Nfa {
+        let mut nfa = Nfa::new();
+        let a = nfa.add_text(0, "a");
+        let b = nfa.add_state();
+        nfa.add_transition(a, None, b);
+        nfa.add_transition(b, Some(('b', 'b')), b);
+        nfa.set_accepting(b);
+        let c = nfa.add_text(0, "c");
+        let also_c = nfa.add_text(0, "c");
+        nfa.set_accepting(c);
+        nfa.set_accepting(also_c);
+        nfa.add_text(0, "dead end");
+        nfa
+    }
+
+    #[test]
+    fn determinize_and_minimize() {
+        let nfa = nfa();
+        let dfa = nfa.to_dfa();
+        assert_eq!(dfa.state_count(), 3);
+        for &input in &["a", "abbb", "c"] {
+            assert!(nfa.accepts(input) && dfa.accepts(input), "{}", input);
+        }
+        for &input in &["", "b", "cc", "ac", "dead end"] {
+            assert!(!nfa.accepts(input) && !dfa.accepts(input), "{}", input);
+        }
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        let mut nfa = Nfa::new();
+        let letter = nfa.add_state();
+        nfa.add_transition(0, Some(('a', 'z')), letter);
+        nfa.set_accepting(letter);
+        let digits = nfa.add_state();
+        nfa.add_transition(0, Some(('0', 'f')), digits);
+        nfa.add_transition(digits, Some(('0', '9')), digits);
+        nfa.set_accepting(digits);
+        nfa.add_transition(0, Some(ANY), 0);
+
+        let dfa = nfa.to_dfa();
+        assert!(dfa.accepts("!!b"));
+        assert!(dfa.accepts("%e123"));
+        assert!(!dfa.accepts("z!"));
+        assert!(dfa.accepts("\u{10FFFF}\u{E000}5"));
+        assert_eq!(dfa.next(0, '\u{D7FF}'), Some(0));
+    }
+
+    #[test]
+    fn regexes() {
+        assert_eq!(nfa().to_regex(), "c|ab*");
+        assert_eq!(Nfa::new().to_regex(), "[]");
+
+        let mut nfa = Nfa::new();
+        nfa.set_accepting(0);
+        assert_eq!(nfa.to_regex(), "()");
+
+        let mut nfa = Nfa::new();
+        let digit = nfa.add_state();
+        for &c in &['3', '1', '2', '0', '5'] {
+            nfa.add_transition(0, Some((c, c)), digit);
+        }
+        nfa.add_transition(digit, None, 0);
+        let dot = nfa.add_text(digit, ".(");
+        nfa.set_accepting(dot);
+        nfa.set_accepting(digit);
+        let regex = nfa.to_regex();
+        assert_eq!(regex, "[0-35]+(\\.\\()?");
+    }
+
+    #[test]
+    fn parse_regexes() {
+        let accepts = |pattern: &str, input: &str| Dfa::from_regex(pattern).unwrap().accepts(input);
+        assert!(accepts("ab|c", "c"));
+        assert!(accepts("a(b|c)*d", "abcbd"));
+        assert!(!accepts("a(b|c)*d", "abc"));
+        assert!(accepts("x{3}", "xxx") && !accepts("x{3}", "xx") && !accepts("x{3}", "xxxx"));
+        assert!(accepts("x{2,}", "xxxxx") && !accepts("x{2,}", "x"));
+        assert!(accepts("(ab){0,2}", "") && accepts("(ab){0,2}", "abab"));
+        assert!(!accepts("(ab){0,2}", "ababab"));
+        assert!(accepts(".{0,3}", "\u{1F643}ab") && !accepts(".{0,3}", "abcd"));
+        assert!(accepts("[^a-c\\]]+", "xyz!") && !accepts("[^a-c\\]]+", "xbz"));
+        assert!(!accepts("[^a-c\\]]+", "]"));
+        assert!(accepts("[a-]", "-"));
+        assert!(accepts("\\.\\n", ".\n") && !accepts("\\.", "x"));
+        assert!(!accepts("[]", "") && !accepts("[]", "a"));
+        assert!(accepts("()", ""));
+
+        for &pattern in &["(a", "a)", "*a", "a**", "a{2,1}", "[b-a]", "a{", "a\\"] {
+            assert!(Nfa::from_regex(pattern).is_err(), "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn regex_round_trip() {
+        for &pattern in &["[0-9]+(\\.[0-9]+)?", "(a|bc)*d?", "[^x]{1,3}", "[]", "()|\\*"] {
+            let dfa = Dfa::from_regex(pattern).unwrap();
+            let written = dfa.to_regex();
+            let round_trip = Dfa::from_regex(&written).unwrap();
+            assert_eq!(dfa, round_trip, "{} written as {}", pattern, written);
+        }
+    }
+
+    #[test]
+    fn regex_languages() {
+        let number = Dfa::from_regex("[0-9]+(\\.[0-9]+)?").unwrap();
+        assert_eq!(number.shortest(), Some(String::from("0")));
+        assert!(!number.is_finite());
+        let small = Dfa::from_regex("ab?|c").unwrap();
+        assert!(small.is_finite());
+        let within = small.inputs_within(1);
+        assert_eq!(within.into_iter().collect::<Vec<_>>(), vec!["a", "c"]);
+        assert_eq!(Dfa::from_regex("[]").unwrap().shortest(), None);
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..20 {
+            let sentence = number.sample(&mut rng, 8).unwrap();
+            assert!(number.accepts(&sentence), "{}", sentence);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use std::char;
+use std::fmt;
+use std::slice;
+use rand::Rng;
+
+/// An inclusive range of chars
+pub type CharRange = (char, char);
+
+/// Every char, as `.` matches in a regular expression
+pub(crate) const ANY: CharRange = ('\0', char::MAX);
+
+/// Printable ASCII, which negated classes are sampled from where they can be
+const PRINTABLE: CharRange = (' ', '~');
+
+/// Most chars of a range or class enumerated where every text of a
+/// grammar is, so that `ANY` does not stand for over a million texts
+pub(crate) const ENUMERATED_CHARS: usize = 256;
+
+/// Code point following `c`, skipping surrogates, which are not chars
+pub(crate) fn after(c: char) -> u32 {
+    match c as u32 {
+        0xD7FF => 0xE000,
+        code => code + 1,
+    }
+}
+
+/// Char preceding code point `code`, skipping surrogates
+pub(crate) fn before(code: u32) -> char {
+    match code - 1 {
+        0xD800..=0xDFFF => '\u{D7FF}',
+        code => char::from_u32(code).expect("code point should be a char"),
+    }
+}
+
+/// Sort `ranges` and merge those overlapping or adjacent
+pub(crate) fn merge(mut ranges: Vec<CharRange>) -> Vec<CharRange> {
+    ranges.sort();
+    let mut merged: Vec<CharRange> = vec![];
+    for (lo, hi) in ranges {
+        match merged.last_mut() {
+            Some(last) if lo as u32 <= after(last.1) => last.1 = last.1.max(hi),
+            _ => merged.push((lo, hi)),
+        }
+    }
+    merged
+}
+
+/// The chars in none of `ranges`, as sorted disjoint ranges
+pub(crate) fn complement(ranges: Vec<CharRange>) -> Vec<CharRange> {
+    let mut complement = vec![];
+    let mut next = ANY.0 as u32;
+    for (lo, hi) in merge(ranges) {
+        if next < lo as u32 {
+            let start = char::from_u32(next).expect("code point should be a char");
+            complement.push((start, before(lo as u32)));
+        }
+        next = after(hi);
+    }
+    if let Some(start) = char::from_u32(next) {
+        complement.push((start, ANY.1));
+    }
+    complement
+}
+
+/// The chars in both `first` and `second`, as sorted disjoint ranges
+pub(crate) fn intersect(first: &[CharRange], second: &[CharRange]) -> Vec<CharRange> {
+    let mut intersection = vec![];
+    for &(lo, hi) in &merge(first.to_vec()) {
+        for &(l, h) in &merge(second.to_vec()) {
+            if lo.max(l) <= hi.min(h) {
+                intersection.push((lo.max(l), hi.min(h)));
+            }
+        }
+    }
+    intersection
+}
+
+/// Number of chars within `range`
+fn size((lo, hi): CharRange) -> usize {
+    let surrogates = if (lo as u32) < 0xD800 && hi as u32 > 0xDFFF {
+        0x800
+    } else {
+        0
+    };
+    (hi as u32 - lo as u32 + 1 - surrogates) as usize
+}
+
+/// The `n`th char within `range`
+fn nth((lo, _): CharRange, n: usize) -> char {
+    let code = lo as u32 + n as u32;
+    let code = if (lo as u32) < 0xD800 && code >= 0xD800 {
+        code + 0x800
+    } else {
+        code
+    };
+    char::from_u32(code).expect("code point should be a char")
+}
+
+/// The printable ASCII chars of `ranges` where they have some, as other
+/// chars are mostly unassigned, or else all of `ranges`
+pub(crate) fn printable(ranges: Vec<CharRange>) -> Vec<CharRange> {
+    let printable = intersect(&ranges, &[PRINTABLE]);
+    if printable.is_empty() {
+        ranges
+    } else {
+        printable
+    }
+}
+
+/// The chars standing for sorted disjoint `ranges` where every text is
+/// enumerated: all of them if there are at most `ENUMERATED_CHARS`, or else
+/// up to that many, from printable ASCII where they have some
+pub(crate) fn enumerated(ranges: Vec<CharRange>) -> Vec<char> {
+    let ranges = if is_enumerable(&ranges) {
+        ranges
+    } else {
+        printable(ranges)
+    };
+    ranges
+        .into_iter()
+        .flat_map(|range| (0..size(range)).map(move |n| nth(range, n)))
+        .take(ENUMERATED_CHARS)
+        .collect()
+}
+
+/// Whether `enumerated` gives every char of `ranges`
+pub(crate) fn is_enumerable(ranges: &[CharRange]) -> bool {
+    ranges.iter().map(|&range| size(range)).sum::<usize>() <= ENUMERATED_CHARS
+}
+
+/// Sample a char within sorted disjoint `ranges` uniformly, or `None` if
+/// they have none
+pub(crate) fn sample<R: Rng>(rng: &mut R, ranges: &[CharRange]) -> Option<char> {
+    let total = ranges.iter().map(|&range| size(range)).sum::<usize>();
+    if total == 0 {
+        return None;
+    }
+    let mut n = rng.gen_range(0, total);
+    for &range in ranges {
+        if n < size(range) {
+            return Some(nth(range, n));
+        }
+        n -= size(range);
+    }
+    None
+}
+
+/// Read a char of a class body at `position`, unescaping it, or `None` at
+/// the closing bracket
+fn read(chars: &[char], position: &mut usize) -> Result<Option<char>, String> {
+    let c = *chars.get(*position).ok_or("unterminated class")?;
+    *position += 1;
+    match c {
+        ']' => Ok(None),
+        '\\' => {
+            let escaped = *chars.get(*position).ok_or("unterminated class")?;
+            *position += 1;
+            Ok(Some(match escaped {
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                c => c,
+            }))
+        }
+        c => Ok(Some(c)),
+    }
+}
+
+/// A set of chars, as written between brackets such as `[a-z0-9_]`, or
+/// `[^"]` for every char but those listed. A `Term::Class` matches any one
+/// char of its class.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CharClass {
+    ranges: Vec<CharRange>,
+    negated: bool,
+}
+
+impl CharClass {
+    /// Construct a `CharClass` of the chars within `ranges`, or of every
+    /// other char if `negated`
+    pub fn new(ranges: Vec<CharRange>, negated: bool) -> CharClass {
+        CharClass { ranges, negated }
+    }
+
+    /// Parse the body of a class following its opening bracket, up to and
+    /// including its closing bracket. Returns the class along with the
+    /// number of chars read.
+    pub(crate) fn parse(chars: &[char]) -> Result<(CharClass, usize), String> {
+        let negated = chars.first() == Some(&'^');
+        let mut position = negated as usize;
+        let mut ranges = vec![];
+        while let Some(lo) = read(chars, &mut position)? {
+            let range = match chars.get(position..position + 2) {
+                Some(&[dash, hi]) => dash == '-' && hi != ']',
+                _ => false,
+            };
+            if !range {
+                ranges.push((lo, lo));
+                continue;
+            }
+            position += 1;
+            let hi = read(chars, &mut position)?.ok_or("unterminated class")?;
+            if hi < lo {
+                return Err(format!("class range {}-{} out of order", lo, hi));
+            }
+            ranges.push((lo, hi));
+        }
+        Ok((CharClass { ranges, negated }, position))
+    }
+
+    /// Get iterator of the ranges listed in the class
+    pub fn ranges_iter(&self) -> slice::Iter<'_, CharRange> {
+        self.ranges.iter()
+    }
+
+    /// Whether the class matches the chars not listed in it
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+
+    /// Whether `c` is a char of the class
+    pub fn contains(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+
+    /// Get the chars of the class, as sorted disjoint ranges
+    pub fn matched(&self) -> Vec<CharRange> {
+        if self.negated {
+            complement(self.ranges.clone())
+        } else {
+            merge(self.ranges.clone())
+        }
+    }
+
+    /// Whether the class has no char at all, as `[]` does
+    pub fn is_empty(&self) -> bool {
+        self.matched().is_empty()
+    }
+
+    /// Get the number of chars of the class
+    pub fn len(&self) -> usize {
+        self.matched().into_iter().map(size).sum()
+    }
+
+    /// The chars sampled from: those of the class, limited to printable
+    /// ASCII for negated classes where they have some
+    fn alphabet(&self) -> Vec<CharRange> {
+        if self.negated {
+            printable(self.matched())
+        } else {
+            self.matched()
+        }
+    }
+
+    /// Sample a char of the class uniformly, or `None` if it has none.
+    /// Negated classes are sampled from printable ASCII where they have
+    /// some of it, as their other chars are mostly unassigned.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<char> {
+        sample(rng, &self.alphabet())
+    }
+
+    /// The char standing for the class where any one will do, such as in
+    /// the shortest sentence of a grammar: the first char it is sampled from
+    pub(crate) fn representative(&self) -> Option<char> {
+        self.alphabet().first().map(|&(lo, _)| lo)
+    }
+
+    /// The chars standing for the class where every text is enumerated,
+    /// in order: see `enumerated`
+    pub(crate) fn enumerated(&self) -> Vec<char> {
+        enumerated(self.matched())
+    }
+}
+
+/// A predefined class of chars, which grammars use by name without
+/// defining it, such as `<number> ::= DIGIT | DIGIT <number>`
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum Builtin {
+    /// Any char at all
+    Any,
+    /// An ASCII digit, `[0-9]`
+    Digit,
+    /// An ASCII letter, `[A-Za-z]`
+    Alpha,
+    /// An ASCII whitespace char: space, tab, line feed, form feed or
+    /// carriage return
+    Whitespace,
+    /// A line feed. Lines ending in `\r\n` are matched by `"\r" NEWLINE`.
+    Newline,
+}
+
+impl Builtin {
+    /// Every builtin, in order
+    pub fn all() -> [Builtin; 5] {
+        [
+            Builtin::Any,
+            Builtin::Digit,
+            Builtin::Alpha,
+            Builtin::Whitespace,
+            Builtin::Newline,
+        ]
+    }
+
+    /// Get the builtin a grammar refers to by `name`, if any
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        Builtin::all()
+            .iter()
+            .cloned()
+            .find(|builtin| builtin.name() == name)
+    }
+
+    /// Get the name grammars refer to the builtin by
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Builtin::Any => "ANY",
+            Builtin::Digit => "DIGIT",
+            Builtin::Alpha => "ALPHA",
+            Builtin::Whitespace => "WHITESPACE",
+            Builtin::Newline => "NEWLINE",
+        }
+    }
+
+    /// Get the chars the builtin matches
+    pub fn class(&self) -> CharClass {
+        let ranges = match *self {
+            Builtin::Any => return CharClass::new(vec![], true),
+            Builtin::Digit => vec![('0', '9')],
+            Builtin::Alpha => vec![('A', 'Z'), ('a', 'z')],
+            Builtin::Whitespace => vec![('\t', '\n'), ('\x0C', '\r'), (' ', ' ')],
+            Builtin::Newline => vec![('\n', '\n')],
+        };
+        CharClass::new(ranges, false)
+    }
+}
+
+impl fmt::Display for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+fn escape(c: char) -> String {
+    match c {
+        '\\' | ']' | '[' | '^' | '-' => format!("\\{}", c),
+        '\n' => String::from("\\n"),
+        '\r' => String::from("\\r"),
+        '\t' => String::from("\\t"),
+        c => c.to_string(),
+    }
+}
+
+impl fmt::Display for CharClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[")?;
+        if self.negated {
+            write!(f, "^")?;
+        }
+        for &(lo, hi) in &self.ranges {
+            write!(f, "{}", escape(lo))?;
+            if lo != hi {
+                write!(f, "-{}", escape(hi))?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::rng_from_seed;
+
+    fn parse(class: &str) -> CharClass {
+        let chars = class.chars().collect::<Vec<_>>();
+        let (class, read) = CharClass::parse(&chars).unwrap();
+        assert_eq!(read, chars.len());
+        class
+    }
+
+    #[test]
+    fn parse_and_display() {
+        let class = parse("a-z0-9_]");
+        assert_eq!(class.ranges_iter().count(), 3);
+        assert!(class.contains('q') && class.contains('_') && !class.contains('-'));
+        assert_eq!(class.len(), 37);
+        assert_eq!(class.to_string(), "[a-z0-9_]");
+
+        let class = parse("^\\]\\n-]");
+        assert!(class.is_negated());
+        assert!(class.contains('a') && !class.contains(']') && !class.contains('\n'));
+        assert!(!class.contains('-'));
+        assert_eq!(class.to_string(), "[^\\]\\n\\-]");
+
+        assert!(parse("]").is_empty());
+        assert!(parse("^]").contains('\u{10FFFF}'));
+        assert_eq!(parse("^]").len(), 0x110000 - 0x800);
+        assert!(parse("α-ω]").contains('λ'));
+
+        for &bad in &["a-", "z-a]", "\\"] {
+            let chars = bad.chars().collect::<Vec<_>>();
+            assert!(CharClass::parse(&chars).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn builtin_classes() {
+        for &builtin in Builtin::all().iter() {
+            assert_eq!(Builtin::from_name(builtin.name()), Some(builtin));
+        }
+        assert_eq!(Builtin::from_name("any"), None);
+        assert!(Builtin::Any.class().contains('\u{10FFFF}'));
+        assert_eq!(Builtin::Digit.class().len(), 10);
+        assert_eq!(Builtin::Alpha.class().len(), 52);
+        let whitespace = Builtin::Whitespace.class();
+        assert!((0..128u8).all(|b| whitespace.contains(b as char) == b.is_ascii_whitespace()));
+    }
+
+    #[test]
+    fn sample_chars() {
+        let mut rng = rng_from_seed(3);
+        let class = parse("a-c\u{D7FE}-\u{E001}]");
+        assert_eq!(class.enumerated().len(), 7);
+        for _ in 0..100 {
+            assert!(class.contains(class.sample(&mut rng).unwrap()));
+        }
+
+        let negated = parse("^ -}]");
+        for _ in 0..100 {
+            assert_eq!(negated.sample(&mut rng), Some('~'));
+        }
+        assert_eq!(negated.representative(), Some('~'));
+        assert!(parse("^\u{0}-~]").sample(&mut rng).unwrap() > '~');
+        assert_eq!(parse("]").sample(&mut rng), None);
+    }
+
+    #[test]
+    fn enumerated_chars() {
+        assert_eq!(Builtin::Digit.class().enumerated().len(), 10);
+        let any = Builtin::Any.class().enumerated();
+        assert_eq!(any.len(), 95);
+        assert_eq!((any[0], any[94]), (' ', '~'));
+        assert_eq!(parse("^a]").enumerated().len(), 94);
+
+        let wide = parse("\u{100}-\u{FFFF}]").enumerated();
+        assert_eq!(wide.len(), ENUMERATED_CHARS);
+        assert_eq!(wide[0], '\u{100}');
+    }
+}
@@ -0,0 +1,495 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::slice;
+use rand::Rng;
+use expression::Expression;
+use generate::{random_rng, Generator, Tracer};
+use grammar::{Alternative, Grammar, Step};
+use term::Term;
+use error::Error;
+
+/// Number of consecutive generation attempts which exercise nothing new
+/// before coverage guided generation gives up on the remaining targets
+const STALE_ATTEMPTS_LIMIT: usize = 64;
+
+/// What coverage guided generation should aim to exercise
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CoverageGoal {
+    /// Every `Expression` alternative of every reachable `Production`
+    Alternatives,
+    /// Every alternative, and every pairing of an alternative with each
+    /// alternative of a nonterminal it references (2-path coverage)
+    Pairs,
+}
+
+/// An `Expression` alternative of a `Production`'s right hand side,
+/// and how many of the reported sentences exercised it
+#[derive(PartialEq, Debug, Clone)]
+pub struct CoveredAlternative {
+    pub lhs: Term,
+    pub expression: Expression,
+    pub hits: usize,
+}
+
+/// An alternative expanded directly within the expansion of another,
+/// and how many of the reported sentences exercised the pairing
+#[derive(PartialEq, Debug, Clone)]
+pub struct CoveredPair {
+    pub parent: CoveredAlternative,
+    pub child: CoveredAlternative,
+    pub hits: usize,
+}
+
+/// Result of coverage guided generation: a minimal set of sentences and
+/// the coverage they achieve together
+#[derive(PartialEq, Debug, Clone)]
+pub struct CoverageReport {
+    goal: CoverageGoal,
+    sentences: Vec<String>,
+    alternatives: Vec<CoveredAlternative>,
+    pairs: Vec<CoveredPair>,
+}
+
+impl CoverageReport {
+    /// Get the goal the report was generated for
+    pub fn goal(&self) -> CoverageGoal {
+        self.goal
+    }
+
+    /// Get iterator of the generated sentences
+    pub fn sentences_iter(&self) -> slice::Iter<'_, String> {
+        self.sentences.iter()
+    }
+
+    /// Get iterator of every reachable alternative with its hit count
+    pub fn alternatives_iter(&self) -> slice::Iter<'_, CoveredAlternative> {
+        self.alternatives.iter()
+    }
+
+    /// Get iterator of every tracked alternative pairing with its hit count.
+    /// Empty unless the goal is `CoverageGoal::Pairs`.
+    pub fn pairs_iter(&self) -> slice::Iter<'_, CoveredPair> {
+        self.pairs.iter()
+    }
+
+    /// Get iterator of alternatives no sentence exercised
+    pub fn uncovered_alternatives(&self) -> impl Iterator<Item = &CoveredAlternative> {
+        self.alternatives.iter().filter(|a| a.hits == 0)
+    }
+
+    /// Get iterator of alternative pairings no sentence exercised
+    pub fn uncovered_pairs(&self) -> impl Iterator<Item = &CoveredPair> {
+        self.pairs.iter().filter(|p| p.hits == 0)
+    }
+
+    /// Number of coverage targets exercised, and the total number of targets
+    pub fn covered(&self) -> (usize, usize) {
+        let covered = self.alternatives.iter().filter(|a| a.hits > 0).count()
+            + self.pairs.iter().filter(|p| p.hits > 0).count();
+        (covered, self.alternatives.len() + self.pairs.len())
+    }
+
+    /// Whether every coverage target was exercised
+    pub fn is_complete(&self) -> bool {
+        let (covered, total) = self.covered();
+        covered == total
+    }
+}
+
+impl fmt::Display for CoveredAlternative {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ::= {}", self.lhs, self.expression)
+    }
+}
+
+impl fmt::Display for CoverageReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let covered = self.alternatives.iter().filter(|a| a.hits > 0).count();
+        writeln!(
+            f,
+            "alternatives: {}/{} covered by {} sentences",
+            covered,
+            self.alternatives.len(),
+            self.sentences.len()
+        )?;
+        for alt in &self.alternatives {
+            writeln!(f, "  {} ({} hits)", alt, alt.hits)?;
+        }
+
+        if self.goal == CoverageGoal::Pairs {
+            let covered = self.pairs.iter().filter(|p| p.hits > 0).count();
+            writeln!(f, "pairs: {}/{} covered", covered, self.pairs.len())?;
+            for pair in &self.pairs {
+                writeln!(
+                    f,
+                    "  {} => {} ({} hits)",
+                    pair.parent, pair.child, pair.hits
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Something coverage guided generation tries to exercise
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+enum Target {
+    Alternative(Alternative),
+    Pair(Alternative, Alternative),
+}
+
+/// Enumerate the alternatives reachable from the start rule, and the
+/// pairings of alternatives reachable from one another
+fn targets(grammar: &Grammar, start: usize, goal: CoverageGoal) -> Vec<Target> {
+    let productions = grammar.productions_iter().collect::<Vec<_>>();
+    let mut alternatives = vec![];
+    let mut pairs = vec![];
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+    seen.insert(start);
+
+    while let Some(index) = stack.pop() {
+        for (e, expression) in productions[index].rhs_iter().enumerate() {
+            alternatives.push((index, e));
+            let mut referenced = HashSet::new();
+            for term in expression.terms_iter().flat_map(|term| term.leaves()) {
+                let child = match grammar.production_index(term) {
+                    Some(child) => child,
+                    None => continue,
+                };
+
+                if referenced.insert(child) && goal == CoverageGoal::Pairs {
+                    for c in 0..productions[child].rhs_iter().count() {
+                        pairs.push(Target::Pair((index, e), (child, c)));
+                    }
+                }
+                if seen.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+    }
+
+    alternatives.sort();
+    pairs.sort_by_key(|p| match *p {
+        Target::Pair(parent, child) => (parent, child),
+        Target::Alternative(a) => (a, a),
+    });
+
+    alternatives
+        .into_iter()
+        .map(Target::Alternative)
+        .chain(pairs)
+        .collect()
+}
+
+/// Coverage targets exercised by a generation trace. Steps outside the
+/// targets, such as expansions of layout whitespace, are left out.
+fn exercised(trace: &[Step], goal: CoverageGoal, targets: &HashSet<Target>) -> HashSet<Target> {
+    let mut hit = HashSet::new();
+    for &(parent, alternative) in trace {
+        hit.insert(Target::Alternative(alternative));
+        if let (Some(parent), CoverageGoal::Pairs) = (parent, goal) {
+            hit.insert(Target::Pair(parent, alternative));
+        }
+    }
+    hit.retain(|t| targets.contains(t));
+    hit
+}
+
+impl Grammar {
+    fn covered_alternative(&self, alternative: Alternative, hits: usize) -> CoveredAlternative {
+        let production = self.productions_iter()
+            .nth(alternative.0)
+            .expect("alternative should reference a production in grammar");
+        CoveredAlternative {
+            lhs: production.lhs.clone(),
+            expression: production
+                .rhs_iter()
+                .nth(alternative.1)
+                .expect("alternative should reference an expression in production")
+                .clone(),
+            hits,
+        }
+    }
+
+    /// Generate sentences from self until every `Expression` alternative
+    /// reachable from the first production has been exercised, preferring
+    /// unexercised alternatives whenever there is a choice. Returns a minimal
+    /// set of the sentences which together achieve the coverage, along with
+    /// the coverage achieved.
+    ///
+    /// Alternatives which can never complete a sentence, such as the only
+    /// alternative of `<PATTERN> ::= <PATTERN>`, are reported as uncovered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// extern crate rand;
+    /// use rand::{SeedableRng, StdRng};
+    /// use bnf::{CoverageGoal, Grammar};
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let seed: &[_] = &[1,2,3,4];
+    ///     let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     let report = grammar
+    ///         .generate_covering_seeded(&mut rng, CoverageGoal::Alternatives)
+    ///         .unwrap();
+    ///
+    ///     for sentence in report.sentences_iter() {
+    ///         println!("{}", sentence);
+    ///     }
+    ///     println!("{}", report);
+    ///
+    ///     # assert!(report.is_complete());
+    /// }
+    /// ```
+    pub fn generate_covering_seeded<R: Rng>(
+        &self,
+        rng: &mut R,
+        goal: CoverageGoal,
+    ) -> Result<CoverageReport, Error> {
+        let start_term = Term::Nonterminal(self.start_rule()?);
+        let start = self.production_index(&start_term)
+            .expect("start rule should have a production");
+        let targets = targets(self, start, goal);
+        let target_set: HashSet<Target> = targets.iter().cloned().collect();
+
+        let generator = Generator::new(self);
+        let shortest = generator.shortest();
+        let mut covered: HashSet<Target> = HashSet::new();
+        let mut candidates: Vec<(String, HashSet<Target>)> = vec![];
+        let mut stale = 0;
+
+        while covered.len() < target_set.len() && stale < STALE_ATTEMPTS_LIMIT {
+            // targets chosen during this attempt count as covered, otherwise
+            // an uncovered recursive alternative would be chosen forever
+            let mut attempt = covered.clone();
+            let mut choose = |rng: &mut R, parent: Option<Alternative>, index, count| {
+                // alternatives which cannot complete a sentence are only
+                // chosen when there is nothing else
+                let mut usable = shortest.productive(index).to_vec();
+                if usable.is_empty() {
+                    usable = (0..count).collect();
+                }
+
+                let fresh = usable
+                    .iter()
+                    .cloned()
+                    .filter(|&e| {
+                        let alternative = Target::Alternative((index, e));
+                        let pair = parent.map(|p| Target::Pair(p, (index, e)));
+                        let pair_fresh = match pair {
+                            Some(pair) if target_set.contains(&pair) => !attempt.contains(&pair),
+                            _ => false,
+                        };
+                        !attempt.contains(&alternative) || pair_fresh
+                    })
+                    .collect::<Vec<_>>();
+
+                let e = match rng.choose(&fresh) {
+                    Some(&e) => e,
+                    None => *rng.choose(&usable).expect("production should have alternatives"),
+                };
+                attempt.insert(Target::Alternative((index, e)));
+                if let Some(parent) = parent {
+                    attempt.insert(Target::Pair(parent, (index, e)));
+                }
+                e
+            };
+
+            let mut tracer = Tracer::new();
+            let derivation = generator.derive_with(rng, &start_term, &mut choose, &mut tracer);
+            match derivation {
+                Ok(()) => {
+                    let Tracer { sentence, trace } = tracer;
+                    let hit = exercised(&trace, goal, &target_set);
+                    if hit.iter().any(|t| !covered.contains(t)) {
+                        covered.extend(hit.iter().cloned());
+                        candidates.push((sentence, hit));
+                        stale = 0;
+                    } else {
+                        stale += 1;
+                    }
+                }
+                Err(Error::RecursionLimit(_)) => stale += 1,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // greedily pick the sentences covering the most targets left uncovered
+        let mut sentences = vec![];
+        let mut remaining = covered;
+        while !remaining.is_empty() {
+            let best = candidates
+                .iter()
+                .enumerate()
+                .max_by_key(|&(i, c)| (c.1.intersection(&remaining).count(), !i))
+                .map(|(i, _)| i)
+                .expect("covered targets should come from a candidate");
+            let (sentence, hit) = candidates.swap_remove(best);
+            remaining = remaining.difference(&hit).cloned().collect();
+            sentences.push((sentence, hit));
+        }
+
+        let mut hits: HashMap<Target, usize> = HashMap::new();
+        for (_, hit) in &sentences {
+            for target in hit {
+                *hits.entry(*target).or_insert(0) += 1;
+            }
+        }
+
+        let hits_of = |t: &Target| hits.get(t).cloned().unwrap_or(0);
+        let mut alternatives = vec![];
+        let mut pairs = vec![];
+        for target in &targets {
+            match *target {
+                Target::Alternative(a) => {
+                    alternatives.push(self.covered_alternative(a, hits_of(target)))
+                }
+                Target::Pair(parent, child) => pairs.push(CoveredPair {
+                    parent: self.covered_alternative(parent, hits_of(&Target::Alternative(parent))),
+                    child: self.covered_alternative(child, hits_of(&Target::Alternative(child))),
+                    hits: hits_of(target),
+                }),
+            }
+        }
+
+        Ok(CoverageReport {
+            goal,
+            sentences: sentences.into_iter().map(|(s, _)| s).collect(),
+            alternatives,
+            pairs,
+        })
+    }
+
+    /// Generate a minimal set of sentences from self which together exercise
+    /// every reachable `Expression` alternative.
+    /// See `generate_covering_seeded` for details.
+    pub fn generate_covering(&self, goal: CoverageGoal) -> Result<CoverageReport, Error> {
+        self.generate_covering_seeded(&mut random_rng(), goal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+    use layout::Layout;
+
+    fn dna_grammar() -> Grammar {
+        Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap()
+    }
+
+    #[test]
+    fn covers_every_alternative() {
+        let report = dna_grammar()
+            .generate_covering(CoverageGoal::Alternatives)
+            .unwrap();
+        assert!(report.is_complete(), "{}", report);
+        assert_eq!(report.covered(), (6, 6));
+        assert_eq!(report.pairs_iter().count(), 0);
+
+        // every base must appear somewhere in the sentences
+        let all = report.sentences_iter().cloned().collect::<String>();
+        for base in ["A", "C", "G", "T"].iter() {
+            assert!(all.contains(base), "{} missing from {:?}", base, all);
+        }
+    }
+
+    #[test]
+    fn covers_every_pair() {
+        let report = dna_grammar()
+            .generate_covering(CoverageGoal::Pairs)
+            .unwrap();
+        // <dna> alternatives reference <base> (4 alternatives each)
+        // and the second references <dna> (2 alternatives)
+        assert_eq!(report.pairs_iter().count(), 10);
+        assert!(report.is_complete(), "{}", report);
+    }
+
+    #[test]
+    fn sentences_are_minimal() {
+        let grammar = Grammar::from_str("<start> ::= \"a\" | \"b\" | \"c\"").unwrap();
+        let report = grammar
+            .generate_covering(CoverageGoal::Alternatives)
+            .unwrap();
+        let mut sentences = report.sentences_iter().cloned().collect::<Vec<_>>();
+        sentences.sort();
+        assert_eq!(sentences, vec!["a", "b", "c"]);
+        assert!(report.alternatives_iter().all(|a| a.hits == 1));
+    }
+
+    #[test]
+    fn reports_uncoverable() {
+        let grammar = Grammar::from_str(
+            "<start> ::= \"a\" | <loop>
+            <loop> ::= <loop>
+            <unreachable> ::= \"b\"",
+        ).unwrap();
+        let report = grammar
+            .generate_covering(CoverageGoal::Alternatives)
+            .unwrap();
+        assert!(!report.is_complete());
+        assert_eq!(report.covered(), (1, 3));
+
+        let uncovered = report
+            .uncovered_alternatives()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(uncovered, vec!["<start> ::= <loop>", "<loop> ::= <loop>"]);
+    }
+
+    #[test]
+    fn avoids_unproductive_groups() {
+        let grammar = Grammar::from_str(
+            "<start> ::= \"a\" | (<loop> \"b\")+ | \"c\" <start>
+            <loop> ::= <loop>",
+        ).unwrap();
+        let report = grammar
+            .generate_covering(CoverageGoal::Alternatives)
+            .unwrap();
+        assert_eq!(report.covered(), (2, 4));
+
+        let uncovered = report
+            .uncovered_alternatives()
+            .map(|a| a.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(uncovered, vec!["<start> ::= (<loop> \"b\")+", "<loop> ::= <loop>"]);
+    }
+
+    #[test]
+    fn ignores_layout_expansions() {
+        let mut grammar = Grammar::from_str(
+            "<s> ::= \"a\" \"b\" | \"x\" <t>
+            <t> ::= \"0\" | \"1\" | \"2\" | \"3\" | \"4\" | \"5\" | \"6\" | \"7\"
+            <ws> ::= \" \" | \"  \" | \"   \" | \"    \" | \"     \" | \"      \" | \"       \" | \"        \"",
+        ).unwrap();
+        grammar.set_layout(Some(Layout::new(Term::Nonterminal(String::from("ws")))));
+
+        for seed in 0..50 {
+            let seed: &[_] = &[seed];
+            let mut rng: StdRng = SeedableRng::from_seed(seed);
+            let report = grammar
+                .generate_covering_seeded(&mut rng, CoverageGoal::Alternatives)
+                .unwrap();
+            assert!(report.is_complete(), "{}", report);
+            assert_eq!(report.covered(), (10, 10));
+        }
+    }
+
+    #[test]
+    fn no_productions() {
+        let report = Grammar::new().generate_covering(CoverageGoal::Alternatives);
+        assert!(report.is_err(), "{:?} should be error", report);
+    }
+}
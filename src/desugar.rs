@@ -0,0 +1,298 @@
+use std::collections::{HashMap, HashSet};
+use expression::Expression;
+use grammar::Grammar;
+use production::Production;
+use term::Term;
+
+/// Lowers the groups and repeats of a grammar into productions of fresh
+/// nonterminals
+struct Desugar {
+    /// Names of the nonterminals in use, which fresh ones must differ from
+    names: HashSet<String>,
+    /// The nonterminal each group or repeat lowered so far stands for
+    lowered: HashMap<Term, Term>,
+    productions: Vec<Production>,
+}
+
+impl Desugar {
+    fn new(grammar: &Grammar) -> Desugar {
+        let mut names = HashSet::new();
+        for production in grammar.productions_iter() {
+            let terms = production
+                .rhs_iter()
+                .flat_map(|rhs| rhs.terms_iter())
+                .flat_map(|term| term.leaves());
+            for term in Some(&production.lhs).into_iter().chain(terms) {
+                if let Term::Nonterminal(ref name) = *term {
+                    names.insert(name.clone());
+                }
+            }
+        }
+        Desugar {
+            names,
+            lowered: HashMap::new(),
+            productions: vec![],
+        }
+    }
+
+    /// A nonterminal named after `base` which is not yet in use
+    fn fresh(&mut self, base: &str) -> Term {
+        let mut n = 1;
+        loop {
+            let name = format!("{}~{}", base, n);
+            if self.names.insert(name.clone()) {
+                return Term::Nonterminal(name);
+            }
+            n += 1;
+        }
+    }
+
+    fn expression(&mut self, base: &str, expression: &Expression) -> Expression {
+        Expression::from_parts(
+            expression
+                .terms_iter()
+                .map(|term| self.term(base, term))
+                .collect(),
+        )
+    }
+
+    /// Lower between 1 and `count` repetitions of `original`, lowered to
+    /// `repeated`, to a chain of nonterminals each deriving one more than
+    /// the next: `<n> ::= a | a <m>`, so as to grow linearly with `count`
+    fn chain(&mut self, base: &str, original: &Term, repeated: &Term, count: usize) -> Term {
+        let mut more = repeated.clone();
+        for n in 2..=count {
+            let term = Term::Repeat(Box::new(original.clone()), 1, Some(n));
+            more = match self.lowered.get(&term) {
+                Some(lowered) => lowered.clone(),
+                None => {
+                    let nonterm = self.fresh(base);
+                    let rhs = vec![
+                        Expression::from_parts(vec![repeated.clone()]),
+                        Expression::from_parts(vec![repeated.clone(), more]),
+                    ];
+                    self.productions.push(Production::from_parts(nonterm.clone(), rhs));
+                    self.lowered.insert(term, nonterm.clone());
+                    nonterm
+                }
+            };
+        }
+        more
+    }
+
+    /// Lower `term`, found in a production of `base`, to a term which is
+    /// neither a group nor a repeat
+    fn term(&mut self, base: &str, term: &Term) -> Term {
+        if let Some(lowered) = self.lowered.get(term) {
+            return lowered.clone();
+        }
+
+        let (nonterm, rhs) = match *term {
+            Term::Group(ref alternatives) => {
+                let rhs = alternatives
+                    .iter()
+                    .map(|expression| self.expression(base, expression))
+                    .collect();
+                (self.fresh(base), rhs)
+            }
+            Term::Repeat(ref original, min, max) => {
+                let repeated = self.term(base, original);
+                // the optional repetitions past the minimum are one more term,
+                // so that whitespace goes only between repetitions
+                let more = match max {
+                    Some(max) if max > min => {
+                        Some(self.chain(base, original, &repeated, max - min))
+                    }
+                    None if min == 0 => {
+                        Some(self.term(base, &Term::Repeat(original.clone(), 1, None)))
+                    }
+                    _ => None,
+                };
+                let nonterm = self.fresh(base);
+                let times = |count| match count {
+                    0 => Expression::from_parts(vec![Term::Terminal(String::new())]),
+                    _ => Expression::from_parts(vec![repeated.clone(); count]),
+                };
+                let followed = |count, more| {
+                    let mut terms = vec![repeated.clone(); count];
+                    terms.push(more);
+                    Expression::from_parts(terms)
+                };
+                let rhs = match (max, more) {
+                    (Some(max), _) if max < min => vec![],
+                    (_, Some(more)) => vec![times(min), followed(min, more)],
+                    (Some(_), None) => vec![times(min)],
+                    (None, None) => vec![times(min), followed(1, nonterm.clone())],
+                };
+                (nonterm, rhs)
+            }
+            _ => return term.clone(),
+        };
+
+        self.productions.push(Production::from_parts(nonterm.clone(), rhs));
+        self.lowered.insert(term.clone(), nonterm.clone());
+        nonterm
+    }
+}
+
+impl Grammar {
+    /// Lower the groups and repeats of the grammar into plain productions,
+    /// for algorithms which only handle those. Each group or repeat is
+    /// replaced by a fresh nonterminal, named after the production it is
+    /// found in, whose production follows those of the grammar:
+    ///
+    /// * a group `(a | b)` becomes `<n> ::= a | b`
+    /// * a bounded repeat `a{1,3}` becomes `<n> ::= a | a <m>` with
+    ///   `<m> ::= a | a a`, a chain growing by a nonterminal per optional
+    ///   repetition
+    /// * an unbounded repeat `a{2,}` becomes `<n> ::= a a | a <n>`, so `a+`
+    ///   becomes `<n> ::= a | a <n>`, and `a*` becomes `<m> ::= "" | <n>`
+    ///
+    /// Identical groups and repeats share a nonterminal. The desugared
+    /// grammar derives the same sentences as the grammar, and keeps its
+    /// `Layout`, under which the groups and repeats of lexical productions
+    /// are lexical too.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str("<number> ::= (\"+\" | \"-\")? DIGIT+").unwrap();
+    ///     let desugared = grammar.desugar();
+    ///     println!("{}", desugared);
+    ///
+    ///     # let expected = Grammar::from_str(
+    ///     #     "<number> ::= <number~2> <number~3>
+    ///     #     <number~1> ::= \"+\" | \"-\"
+    ///     #     <number~2> ::= \"\" | <number~1>
+    ///     #     <number~3> ::= DIGIT | DIGIT <number~3>",
+    ///     # ).unwrap();
+    ///     # assert_eq!(desugared, expected);
+    /// }
+    /// ```
+    pub fn desugar(&self) -> Grammar {
+        let mut desugar = Desugar::new(self);
+        let mut layout = self.layout.clone();
+        let mut productions = self
+            .productions_iter()
+            .map(|production| {
+                let base = match production.lhs {
+                    Term::Nonterminal(ref name) => name.clone(),
+                    ref lhs => lhs.to_string(),
+                };
+                let lowered = desugar.productions.len();
+                let rhs = production
+                    .rhs_iter()
+                    .map(|expression| desugar.expression(&base, expression))
+                    .collect();
+                if let Some(ref mut layout) = layout {
+                    if layout.is_lexical(&production.lhs) {
+                        for aux in &desugar.productions[lowered..] {
+                            layout.add_lexical(aux.lhs.clone());
+                        }
+                    }
+                }
+                Production::from_parts(production.lhs.clone(), rhs)
+            })
+            .collect::<Vec<_>>();
+        productions.append(&mut desugar.productions);
+        let mut grammar = Grammar::from_parts(productions);
+        grammar.set_layout(layout);
+        grammar
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::Generator;
+    use layout::Layout;
+
+    #[test]
+    fn desugar_repeats() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <item> (\",\" <item>)* | \"\"
+            <item> ::= \"x\"{2} | \"y\"{1,2} | \"z\"{2,}",
+        ).unwrap();
+        let expected = Grammar::from_str(
+            "<list> ::= <item> <list~3> | \"\"
+            <item> ::= <item~1> | <item~2> | <item~3>
+            <list~1> ::= \",\" <item>
+            <list~2> ::= <list~1> | <list~1> <list~2>
+            <list~3> ::= \"\" | <list~2>
+            <item~1> ::= \"x\" \"x\"
+            <item~2> ::= \"y\" | \"y\" \"y\"
+            <item~3> ::= \"z\" \"z\" | \"z\" <item~3>",
+        ).unwrap();
+        assert_eq!(grammar.desugar(), expected);
+    }
+
+    #[test]
+    fn desugar_bounded_repeats_to_chains() {
+        let grammar = Grammar::from_str("<s> ::= \"a\"{0,3} | \"a\"{1,3} | \"b\"{2,4}").unwrap();
+        let expected = Grammar::from_str(
+            "<s> ::= <s~3> | <s~2> | <s~5>
+            <s~1> ::= \"a\" | \"a\" \"a\"
+            <s~2> ::= \"a\" | \"a\" <s~1>
+            <s~3> ::= \"\" | <s~2>
+            <s~4> ::= \"b\" | \"b\" \"b\"
+            <s~5> ::= \"b\" \"b\" | \"b\" \"b\" <s~4>",
+        ).unwrap();
+        assert_eq!(grammar.desugar(), expected);
+
+        let long = Grammar::from_str("<s> ::= \"a\"{0,3000}").unwrap().desugar();
+        assert_eq!(long.productions_iter().count(), 3001);
+        assert!(long.productions_iter().all(|p| p.rhs_iter().all(|e| e.terms_iter().count() <= 2)));
+    }
+
+    #[test]
+    fn desugar_keeps_layout() {
+        let mut grammar = Grammar::from_str(
+            "<list> ::= <item> (\",\" <item>)*
+            <item> ::= ALPHA+
+            <ws> ::= \" \"*",
+        ).unwrap();
+        let mut layout = Layout::new(Term::Nonterminal(String::from("ws")));
+        layout.add_lexical(Term::Nonterminal(String::from("item")));
+        grammar.set_layout(Some(layout));
+
+        let desugared = grammar.desugar();
+        let layout = desugared.layout().unwrap();
+        assert!(layout.is_lexical(&Term::Nonterminal(String::from("item~1"))));
+        assert!(!layout.is_lexical(&Term::Nonterminal(String::from("list~1"))));
+        assert!(desugared.recognizes("ab , c,d"));
+        assert!(!desugared.recognizes("a b"));
+    }
+
+    #[test]
+    fn desugar_avoids_names_in_use() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"a\"? <s~1> \"a\"?
+            <s~1> ::= \"b\"",
+        ).unwrap();
+        let desugared = grammar.desugar();
+        let expected = Grammar::from_str(
+            "<s> ::= <s~2> <s~1> <s~2>
+            <s~1> ::= \"b\"
+            <s~2> ::= \"\" | \"a\"",
+        ).unwrap();
+        assert_eq!(desugared, expected);
+        assert_eq!(desugared.desugar(), desugared);
+    }
+
+    #[test]
+    fn desugared_derives_same_sentences() {
+        let grammar = Grammar::from_str(
+            "<s> ::= (\"a\" | \"b\" <s>){0,2} \"c\"+",
+        ).unwrap();
+        let desugared = grammar.desugar();
+        let generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(desugared.recognizes(&sentence), "{}", sentence);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::fmt;
+use expression::Expression;
+use grammar::Grammar;
+use production::Production;
+use term::Term;
+
+/// How the productions of one nonterminal differ between two `Grammar`s
+#[derive(PartialEq, Debug, Clone)]
+pub enum ProductionDiff {
+    /// Only the second grammar has productions of the nonterminal
+    Added(Production),
+    /// Only the first grammar has productions of the nonterminal
+    Removed(Production),
+    /// Both grammars have productions of `lhs`, with different alternatives
+    Changed {
+        lhs: Term,
+        added: Vec<Expression>,
+        removed: Vec<Expression>,
+    },
+}
+
+impl ProductionDiff {
+    /// Get the nonterminal whose productions differ
+    pub fn lhs(&self) -> &Term {
+        match *self {
+            ProductionDiff::Added(ref production) | ProductionDiff::Removed(ref production) => {
+                &production.lhs
+            }
+            ProductionDiff::Changed { ref lhs, .. } => lhs,
+        }
+    }
+}
+
+impl fmt::Display for ProductionDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ProductionDiff::Added(ref production) => writeln!(f, "+ {}", production),
+            ProductionDiff::Removed(ref production) => writeln!(f, "- {}", production),
+            ProductionDiff::Changed {
+                ref lhs,
+                ref added,
+                ref removed,
+            } => {
+                writeln!(f, "~ {}", lhs)?;
+                for expression in removed {
+                    writeln!(f, "  - {}", expression)?;
+                }
+                for expression in added {
+                    writeln!(f, "  + {}", expression)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The differences between two `Grammar`s, production by production.
+/// Removed and changed productions come in order of the first grammar,
+/// followed by added productions in order of the second.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct GrammarDiff {
+    pub productions: Vec<ProductionDiff>,
+}
+
+impl GrammarDiff {
+    /// Whether both grammars have the same alternatives for each nonterminal
+    pub fn is_empty(&self) -> bool {
+        self.productions.is_empty()
+    }
+}
+
+impl fmt::Display for GrammarDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for production in &self.productions {
+            write!(f, "{}", production)?;
+        }
+        Ok(())
+    }
+}
+
+/// Each nonterminal with a production, in order of its first production,
+/// along with its distinct alternatives across all of its productions
+fn alternatives(grammar: &Grammar) -> Vec<(&Term, Vec<&Expression>)> {
+    let mut alternatives: Vec<(&Term, Vec<&Expression>)> = vec![];
+    for production in grammar.productions_iter() {
+        let position = alternatives
+            .iter()
+            .position(|&(lhs, _)| *lhs == production.lhs);
+        let index = match position {
+            Some(index) => index,
+            None => {
+                alternatives.push((&production.lhs, vec![]));
+                alternatives.len() - 1
+            }
+        };
+        for expression in production.rhs_iter() {
+            if !alternatives[index].1.contains(&expression) {
+                alternatives[index].1.push(expression);
+            }
+        }
+    }
+    alternatives
+}
+
+fn production(lhs: &Term, expressions: &[&Expression]) -> Production {
+    Production::from_parts(lhs.clone(), expressions.iter().cloned().cloned().collect())
+}
+
+impl Grammar {
+    /// Compare self with `other` production by production, ignoring the
+    /// order of productions and of their alternatives, and the way they are
+    /// laid out. Productions are matched by lhs, so alternatives split over
+    /// several productions of one nonterminal are compared together.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let before = Grammar::from_str(
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+    ///     ).unwrap();
+    ///     let after = Grammar::from_str(
+    ///         "<base> ::= \"T\" | \"G\" | \"C\" | \"A\"
+    ///         <dna> ::= <base> <dna> | <base>",
+    ///     ).unwrap();
+    ///     assert!(before.diff(&after).is_empty());
+    ///
+    ///     let rna = Grammar::from_str(
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"U\"",
+    ///     ).unwrap();
+    ///     print!("{}", before.diff(&rna));
+    ///
+    ///     # assert_eq!(
+    ///     #     before.diff(&rna).to_string(),
+    ///     #     "~ <base>\n  - \"T\"\n  + \"U\"\n"
+    ///     # );
+    /// }
+    /// ```
+    pub fn diff(&self, other: &Grammar) -> GrammarDiff {
+        let left = alternatives(self);
+        let right = alternatives(other);
+        let find = |alternatives: &[(&Term, Vec<&Expression>)], lhs: &Term| {
+            alternatives
+                .iter()
+                .position(|&(l, _)| l == lhs)
+        };
+
+        let mut productions = vec![];
+        for &(lhs, ref expressions) in &left {
+            match find(&right, lhs) {
+                None => productions.push(ProductionDiff::Removed(production(lhs, expressions))),
+                Some(index) => {
+                    let others = &right[index].1;
+                    let removed = expressions
+                        .iter()
+                        .filter(|expression| !others.contains(expression))
+                        .map(|&expression| expression.clone())
+                        .collect::<Vec<_>>();
+                    let added = others
+                        .iter()
+                        .filter(|expression| !expressions.contains(expression))
+                        .map(|&expression| expression.clone())
+                        .collect::<Vec<_>>();
+                    if !added.is_empty() || !removed.is_empty() {
+                        productions.push(ProductionDiff::Changed {
+                            lhs: lhs.clone(),
+                            added,
+                            removed,
+                        });
+                    }
+                }
+            }
+        }
+        for &(lhs, ref expressions) in &right {
+            if find(&left, lhs).is_none() {
+                productions.push(ProductionDiff::Added(production(lhs, expressions)));
+            }
+        }
+
+        GrammarDiff { productions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn added_removed_changed() {
+        let before = Grammar::from_str(
+            "<s> ::= <a> | <b>
+            <a> ::= \"a\"
+            <b> ::= \"b\"",
+        ).unwrap();
+        let after = Grammar::from_str(
+            "<c> ::= \"c\" | \"cc\"
+            <s> ::= <a> | <c>
+            <a> ::= \"a\"",
+        ).unwrap();
+        let diff = before.diff(&after);
+
+        let s = Term::Nonterminal(String::from("s"));
+        let b = Production::from_str("<b> ::= \"b\"").unwrap();
+        let c = Production::from_str("<c> ::= \"c\" | \"cc\"").unwrap();
+        assert_eq!(
+            diff.productions,
+            vec![
+                ProductionDiff::Changed {
+                    lhs: s.clone(),
+                    added: vec![Expression::from_str("<c>").unwrap()],
+                    removed: vec![Expression::from_str("<b>").unwrap()],
+                },
+                ProductionDiff::Removed(b),
+                ProductionDiff::Added(c),
+            ]
+        );
+        assert_eq!(diff.productions[0].lhs(), &s);
+        assert_eq!(
+            diff.to_string(),
+            "~ <s>\n  - <b>\n  + <c>\n- <b> ::= \"b\"\n+ <c> ::= \"c\" | \"cc\"\n"
+        );
+    }
+
+    #[test]
+    fn split_productions() {
+        let before = Grammar::from_str(
+            "<s> ::= \"a\" | \"b\"
+            <s> ::= \"a\"",
+        ).unwrap();
+        let after = Grammar::from_str("<s> ::= \"b\" | \"a\"").unwrap();
+        assert!(before.diff(&after).is_empty());
+        assert!(after.diff(&before).is_empty());
+        assert_eq!(before.diff(&Grammar::new()).productions.len(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use expression::Expression;
+use grammar::Grammar;
+use railroad::{anchor, escape};
+use term::{quantifier, Term};
+
+/// Styles of the sections of a reference page
+const STYLE: &str = "section.production { margin-bottom: 2em; }
+section.production pre { background: #f6f6f6; padding: 0.5em; }
+section.production a { color: #03c; }";
+
+/// Render `term` as HTML, linking each nonterminal with a production to it
+fn term_html(term: &Term, indices: &HashMap<&Term, usize>) -> String {
+    match *term {
+        Term::Nonterminal(_) => {
+            let text = escape(&term.to_string());
+            match indices.get(term) {
+                Some(&index) => format!("<a href=\"#{}\">{}</a>", anchor(index), text),
+                None => text,
+            }
+        }
+        Term::Group(ref alternatives) => {
+            let alternatives = alternatives
+                .iter()
+                .map(|expression| expression_html(expression, indices))
+                .collect::<Vec<_>>();
+            format!("({})", alternatives.join(" | "))
+        }
+        Term::Repeat(ref term, min, max) => match **term {
+            Term::Repeat(..) => {
+                format!("({}){}", term_html(term, indices), escape(&quantifier(min, max)))
+            }
+            _ => format!("{}{}", term_html(term, indices), escape(&quantifier(min, max))),
+        },
+        _ => escape(&term.to_string()),
+    }
+}
+
+/// Render `expression` as HTML, as `term_html` does its terms
+fn expression_html(expression: &Expression, indices: &HashMap<&Term, usize>) -> String {
+    expression
+        .terms_iter()
+        .map(|term| term_html(term, indices))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A section of the reference of a grammar
+struct Section {
+    index: usize,
+    title: String,
+    comment: Option<String>,
+    /// The production as BNF in HTML, an alternative per line
+    bnf: String,
+    /// The index and nonterminal of each production using this one
+    used_by: Vec<(usize, String)>,
+}
+
+impl Grammar {
+    /// The sections of the reference of the grammar, in production order
+    fn sections(&self) -> Vec<Section> {
+        let indices = self.production_indices();
+        let graph = self.dependency_graph();
+        self.productions_iter()
+            .enumerate()
+            .map(|(index, production)| {
+                let lhs = production.lhs.to_string();
+                let indent = " ".repeat(lhs.chars().count() + 1);
+                let bnf = production
+                    .rhs_iter()
+                    .map(|expression| expression_html(expression, &indices))
+                    .collect::<Vec<_>>()
+                    .join(&format!("\n{}| ", indent));
+
+                // a nonterminal expands by its first production, so only
+                // that one is used by others
+                let mut used_by: Vec<(usize, String)> = vec![];
+                if indices[&production.lhs] == index {
+                    for (user, successors) in graph.adjacency().iter().enumerate() {
+                        if successors.contains(&index) {
+                            let user = indices[graph.nonterminals()[user]];
+                            if used_by.iter().all(|&(seen, _)| seen != user) {
+                                used_by.push((user, graph.nonterminals()[user].to_string()));
+                            }
+                        }
+                    }
+                }
+
+                Section {
+                    index,
+                    title: lhs.clone(),
+                    comment: production.comment.clone(),
+                    bnf: format!("{} ::= {}", escape(&lhs), bnf),
+                    used_by,
+                }
+            })
+            .collect()
+    }
+
+    /// Generate a reference of the grammar in Markdown: a section per
+    /// production with any comment on it, its BNF with each nonterminal
+    /// linked to the section defining it, and the productions using it.
+    ///
+    /// Comments are copied as is, so may be written in Markdown. The BNF is
+    /// a `<pre>` block of HTML, for its links.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "# A sum of *terms*
+    ///         <expr> ::= <term> | <term> \"+\" <expr>
+    ///         <term> ::= DIGIT+",
+    ///     ).unwrap();
+    ///     let docs = grammar.to_markdown_docs();
+    ///     println!("{}", docs);
+    ///
+    ///     # assert!(docs.contains("A sum of *terms*"));
+    ///     # assert!(docs.contains("<a href=\"#production-1\">&lt;term&gt;</a>"));
+    ///     # assert!(docs.contains("Used by: [`<expr>`](#production-0)"));
+    /// }
+    /// ```
+    pub fn to_markdown_docs(&self) -> String {
+        let mut markdown = String::from("# Grammar\n");
+        for section in self.sections() {
+            markdown.push_str(&format!(
+                "\n<a id=\"{}\"></a>\n## `{}`\n\n",
+                anchor(section.index),
+                section.title
+            ));
+            if let Some(comment) = section.comment {
+                markdown.push_str(&comment);
+                markdown.push_str("\n\n");
+            }
+            markdown.push_str(&format!("<pre>\n{}\n</pre>\n", section.bnf));
+            if !section.used_by.is_empty() {
+                let links = section
+                    .used_by
+                    .iter()
+                    .map(|&(index, ref lhs)| format!("[`{}`](#{})", lhs, anchor(index)))
+                    .collect::<Vec<_>>();
+                markdown.push_str(&format!("\nUsed by: {}\n", links.join(", ")));
+            }
+        }
+        markdown
+    }
+
+    /// Generate a reference of the grammar as an HTML page, with the
+    /// sections of `to_markdown_docs`. Comments are escaped, a paragraph per
+    /// blank-line separated block.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "# A sum of terms
+    ///         <expr> ::= <term> | <term> \"+\" <expr>
+    ///         <term> ::= DIGIT+",
+    ///     ).unwrap();
+    ///     let html = grammar.to_html_docs();
+    ///     println!("{}", html);
+    ///
+    ///     # assert!(html.contains("<section class=\"production\" id=\"production-1\">"));
+    ///     # assert!(html.contains("<p>A sum of terms</p>"));
+    /// }
+    /// ```
+    pub fn to_html_docs(&self) -> String {
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Grammar</title>\n<style>\n{}\n</style>\n</head>\n<body>\n\
+             <h1>Grammar</h1>\n",
+            STYLE
+        );
+        for section in self.sections() {
+            html.push_str(&format!(
+                "<section class=\"production\" id=\"{}\">\n<h2>{}</h2>\n",
+                anchor(section.index),
+                escape(&section.title)
+            ));
+            if let Some(comment) = section.comment {
+                for paragraph in comment.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                    html.push_str(&format!("<p>{}</p>\n", escape(paragraph.trim())));
+                }
+            }
+            html.push_str(&format!("<pre>{}</pre>\n", section.bnf));
+            if !section.used_by.is_empty() {
+                let links = section
+                    .used_by
+                    .iter()
+                    .map(|&(index, ref lhs)| {
+                        format!("<a href=\"#{}\">{}</a>", anchor(index), escape(lhs))
+                    })
+                    .collect::<Vec<_>>();
+                html.push_str(&format!("<p>Used by: {}</p>\n", links.join(", ")));
+            }
+            html.push_str("</section>\n");
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar() -> Grammar {
+        Grammar::from_str(
+            "# A list of items,
+            #
+            # separated by commas
+            <list> ::= <item> | <item> \",\" <list>
+            # An item
+            <item> ::= (<word> | \"<\" <list> \">\")+
+            <word> ::= <letter>{1,8} | <undefined>
+            <letter> ::= [a-z]
+            <item> ::= \"shadowed\"",
+        ).unwrap()
+    }
+
+    #[test]
+    fn markdown_docs() {
+        let expected = "# Grammar
+
+<a id=\"production-0\"></a>
+## `<list>`
+
+A list of items,
+
+separated by commas
+
+<pre>
+&lt;list&gt; ::= <a href=\"#production-1\">&lt;item&gt;</a>
+       | <a href=\"#production-1\">&lt;item&gt;</a> &quot;,&quot; <a href=\"#production-0\">&lt;list&gt;</a>
+</pre>
+
+Used by: [`<list>`](#production-0), [`<item>`](#production-1)
+
+<a id=\"production-1\"></a>
+## `<item>`
+
+An item
+
+<pre>
+&lt;item&gt; ::= (<a href=\"#production-2\">&lt;word&gt;</a> | &quot;&lt;&quot; <a href=\"#production-0\">&lt;list&gt;</a> &quot;&gt;&quot;)+
+</pre>
+
+Used by: [`<list>`](#production-0)
+
+<a id=\"production-2\"></a>
+## `<word>`
+
+<pre>
+&lt;word&gt; ::= <a href=\"#production-3\">&lt;letter&gt;</a>{1,8}
+       | &lt;undefined&gt;
+</pre>
+
+Used by: [`<item>`](#production-1)
+
+<a id=\"production-3\"></a>
+## `<letter>`
+
+<pre>
+&lt;letter&gt; ::= [a-z]
+</pre>
+
+Used by: [`<word>`](#production-2)
+
+<a id=\"production-4\"></a>
+## `<item>`
+
+<pre>
+&lt;item&gt; ::= &quot;shadowed&quot;
+</pre>
+";
+        assert_eq!(grammar().to_markdown_docs(), expected);
+        assert_eq!(Grammar::new().to_markdown_docs(), "# Grammar\n");
+    }
+
+    #[test]
+    fn html_docs() {
+        let html = grammar().to_html_docs();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.ends_with("</body>\n</html>\n"));
+        assert_eq!(html.matches("<section class=\"production\"").count(), 5);
+        assert!(html.contains("<h2>&lt;list&gt;</h2>\n<p>A list of items,</p>\n<p>separated by commas</p>\n"));
+        assert!(html.contains(
+            "<pre>&lt;letter&gt; ::= [a-z]</pre>\n\
+             <p>Used by: <a href=\"#production-2\">&lt;word&gt;</a></p>\n</section>"
+        ));
+        assert!(html.contains("<section class=\"production\" id=\"production-4\">\n<h2>&lt;item&gt;</h2>\n<pre>"));
+    }
+}
@@ -0,0 +1,137 @@
+use grammar::Grammar;
+
+/// Quote `text` as a DOT string
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '\n' => quoted.push_str("\\n"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl Grammar {
+    /// Render the `DependencyGraph` of the grammar in the DOT language of
+    /// Graphviz, with a node for each production labelled by its
+    /// nonterminal, and an edge to each nonterminal it uses.
+    ///
+    /// The start symbol is drawn with a double border, and nonterminals
+    /// unreachable from it are dashed and grey. Recursive nonterminals are
+    /// drawn in a red cluster per strongly connected component, along with
+    /// the edges of their cycles.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "<expr> ::= <term> | <term> \"+\" <expr>
+    ///         <term> ::= DIGIT+ | \"(\" <expr> \")\"
+    ///         <unused> ::= <term>",
+    ///     ).unwrap();
+    ///     let dot = grammar.to_dot();
+    ///     println!("{}", dot);
+    ///
+    ///     # assert!(dot.starts_with("digraph grammar {"));
+    ///     # assert!(dot.contains("n0 -> n1 [color=red];"));
+    ///     # assert!(dot.contains("n2 [label=\"<unused>\", style=dashed, color=grey, fontcolor=grey];"));
+    /// }
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let graph = self.dependency_graph();
+        let reachable = graph.reachable();
+        let node = |vertex: usize| {
+            let label = quote(&graph.nonterminals()[vertex].to_string());
+            let mut attributes = vec![format!("label={}", label)];
+            if vertex == 0 {
+                attributes.push(String::from("peripheries=2"));
+            }
+            if !reachable[vertex] {
+                attributes.push(String::from("style=dashed, color=grey, fontcolor=grey"));
+            }
+            format!("n{} [{}];\n", vertex, attributes.join(", "))
+        };
+
+        let mut dot = String::from("digraph grammar {\n    node [shape=box];\n");
+        // nodes follow the order of productions, each cluster placed at its
+        // first member
+        for vertex in 0..graph.nonterminals().len() {
+            if !graph.is_recursive(vertex) {
+                dot.push_str("    ");
+                dot.push_str(&node(vertex));
+                continue;
+            }
+            let c = graph.component(vertex);
+            let mut members = graph.components()[c].clone();
+            members.sort();
+            if members[0] == vertex {
+                dot.push_str(&format!("    subgraph cluster_{} {{\n", c));
+                dot.push_str("        color=red;\n");
+                for member in members {
+                    dot.push_str("        ");
+                    dot.push_str(&node(member));
+                }
+                dot.push_str("    }\n");
+            }
+        }
+        for (vertex, successors) in graph.adjacency().iter().enumerate() {
+            for &successor in successors {
+                let cycle = graph.component(vertex) == graph.component(successor)
+                    && graph.is_recursive(vertex);
+                let color = if cycle { " [color=red]" } else { "" };
+                dot.push_str(&format!("    n{} -> n{}{};\n", vertex, successor, color));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot_graph() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <a> (<b> | \"x\")*
+            <a> ::= <a> \"a\" | \"\"
+            <b> ::= <c>
+            <c> ::= <b>
+            <\"quoted\"> ::= <s>",
+        ).unwrap();
+        let expected = "digraph grammar {
+    node [shape=box];
+    n0 [label=\"<s>\", peripheries=2];
+    subgraph cluster_0 {
+        color=red;
+        n1 [label=\"<a>\"];
+    }
+    subgraph cluster_1 {
+        color=red;
+        n2 [label=\"<b>\"];
+        n3 [label=\"<c>\"];
+    }
+    n4 [label=\"<\\\"quoted\\\">\", style=dashed, color=grey, fontcolor=grey];
+    n0 -> n1;
+    n0 -> n2;
+    n1 -> n1 [color=red];
+    n2 -> n3 [color=red];
+    n3 -> n2 [color=red];
+    n4 -> n0;
+}
+";
+        assert_eq!(grammar.to_dot(), expected);
+        assert_eq!(Grammar::new().to_dot(), "digraph grammar {\n    node [shape=box];\n}\n");
+    }
+}
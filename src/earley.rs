@@ -0,0 +1,1476 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use automaton::Dfa;
+use class::CharClass;
+use expression::Expression;
+use grammar::Grammar;
+use lexer::Token;
+use parse_tree::{ParseTree, ParseTreeNode};
+use term::Term;
+use error::Error;
+
+/// How the parser sees a `Term` of an `Expression`
+#[derive(PartialEq, Debug, Clone)]
+enum Symbol {
+    /// Text which must appear literally in the input. Nonterminals without
+    /// a production are literal too, as generation emits them as is.
+    Literal(String),
+    /// Any one char of a class
+    Class(CharClass),
+    /// Text a regex matches, by index of its automaton
+    Regex(usize),
+    /// A nonterminal, by index of its `Production`
+    Nonterminal(usize),
+    /// A token of this kind, when parsing tokens. Terminals, and
+    /// nonterminals without a production, are kinds of tokens.
+    Kind(Term),
+}
+
+/// What the parser reads: text by the byte, or tokens a `Lexer` made
+#[derive(Clone, Copy)]
+enum Input<'input> {
+    Text(&'input str),
+    Tokens(&'input [Token]),
+}
+
+impl<'input> Input<'input> {
+    /// Positions of the input, in bytes or tokens
+    fn len(&self) -> usize {
+        match *self {
+            Input::Text(text) => text.len(),
+            Input::Tokens(tokens) => tokens.len(),
+        }
+    }
+}
+
+/// A `Grammar` prepared for parsing. Groups and repeats are parsed as
+/// productions of their own, following those of the grammar, which are
+/// inline: their derivations are spliced into the derivations using them.
+/// The whitespace nonterminal of a `Layout` is parsed between the symbols
+/// of productions which are not lexical, as if written there.
+struct Rules<'gram> {
+    lhs: Vec<&'gram Term>,
+    /// Per production, its alternatives, which inline productions lack
+    expressions: Vec<Vec<&'gram Expression>>,
+    symbols: Vec<Vec<Vec<Symbol>>>,
+    inline: Vec<bool>,
+    /// The inline production of each group and repeat, and whether it is
+    /// laid out
+    inline_indices: HashMap<(&'gram Term, bool), usize>,
+    regexes: Vec<Dfa>,
+    /// Whether terminals and undefined nonterminals are read as kinds of tokens
+    tokens: bool,
+    /// For nonterminals which can derive the empty string, the alternative
+    /// to derive it with. Chosen so that empty derivations never cycle.
+    nullable: Vec<Option<usize>>,
+    start: usize,
+}
+
+impl<'gram> Rules<'gram> {
+    fn new(grammar: &'gram Grammar, tokens: bool) -> Result<Rules<'gram>, Error> {
+        let start_rule = Term::Nonterminal(grammar.start_rule()?);
+        let indices = grammar.production_indices();
+        let mut rules = Rules {
+            lhs: vec![],
+            expressions: vec![],
+            symbols: vec![],
+            inline: vec![],
+            inline_indices: HashMap::new(),
+            regexes: vec![],
+            tokens,
+            nullable: vec![],
+            start: indices[&start_rule],
+        };
+
+        for production in grammar.productions_iter() {
+            rules.lhs.push(&production.lhs);
+            rules.expressions.push(production.rhs_iter().collect());
+            rules.symbols.push(vec![]);
+            rules.inline.push(false);
+        }
+        let whitespace = match grammar.layout() {
+            Some(layout) if !tokens => match indices.get(&layout.whitespace) {
+                Some(&index) => Some(index),
+                None => {
+                    return Err(Error::ParseError(format!(
+                        "No production found for {} to lay out sentences with",
+                        layout.whitespace
+                    )))
+                }
+            },
+            _ => None,
+        };
+        for (index, production) in grammar.productions_iter().enumerate() {
+            let layout = whitespace.filter(|_| grammar.layout_between(index).is_some());
+            let mut symbols = vec![];
+            for expression in production.rhs_iter() {
+                symbols.push(rules.alternative(&indices, expression, layout)?);
+            }
+            rules.symbols[index] = symbols;
+        }
+        rules.nullable = vec![None; rules.symbols.len()];
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in 0..rules.symbols.len() {
+                if rules.nullable[production].is_some() {
+                    continue;
+                }
+                let empty = rules.symbols[production].iter().position(|symbols| {
+                    symbols.iter().all(|symbol| match *symbol {
+                        Symbol::Literal(ref t) => t.is_empty(),
+                        Symbol::Class(_) | Symbol::Kind(_) => false,
+                        Symbol::Regex(r) => rules.regexes[r].is_accepting(0),
+                        Symbol::Nonterminal(n) => rules.nullable[n].is_some(),
+                    })
+                });
+                if empty.is_some() {
+                    rules.nullable[production] = empty;
+                    changed = true;
+                }
+            }
+        }
+
+        Ok(rules)
+    }
+
+    /// Symbols of `expression`, separated by the whitespace production
+    /// `layout` if any
+    fn alternative(
+        &mut self,
+        indices: &HashMap<&'gram Term, usize>,
+        expression: &'gram Expression,
+        layout: Option<usize>,
+    ) -> Result<Vec<Symbol>, Error> {
+        let mut symbols = vec![];
+        for term in expression.terms_iter() {
+            symbols.push(self.symbol(indices, term, layout)?);
+        }
+        Ok(laid_out(symbols, layout))
+    }
+
+    fn symbol(
+        &mut self,
+        indices: &HashMap<&'gram Term, usize>,
+        term: &'gram Term,
+        layout: Option<usize>,
+    ) -> Result<Symbol, Error> {
+        if let Some(&index) = self.inline_indices.get(&(term, layout.is_some())) {
+            return Ok(Symbol::Nonterminal(index));
+        }
+
+        Ok(match *term {
+            Term::Terminal(ref t) if self.tokens && !t.is_empty() => Symbol::Kind(term.clone()),
+            Term::Terminal(ref t) => Symbol::Literal(t.clone()),
+            Term::Nonterminal(_) => match indices.get(term) {
+                Some(&index) => Symbol::Nonterminal(index),
+                None if self.tokens => Symbol::Kind(term.clone()),
+                None => Symbol::Literal(term.to_string()),
+            },
+            Term::Class(ref class) => Symbol::Class(class.clone()),
+            Term::Builtin(builtin) => Symbol::Class(builtin.class()),
+            Term::Regex(ref pattern) => {
+                self.regexes.push(Dfa::from_regex(pattern)?);
+                Symbol::Regex(self.regexes.len() - 1)
+            }
+            Term::Group(ref alternatives) => {
+                let index = self.add_inline(term);
+                self.inline_indices.insert((term, layout.is_some()), index);
+                let mut symbols = vec![];
+                for expression in alternatives {
+                    symbols.push(self.alternative(indices, expression, layout)?);
+                }
+                self.symbols[index] = symbols;
+                Symbol::Nonterminal(index)
+            }
+            Term::Repeat(ref repeated, min, max) => {
+                let index = self.add_inline(term);
+                self.inline_indices.insert((term, layout.is_some()), index);
+                let repeated = self.symbol(indices, repeated, layout)?;
+                let times = |count| laid_out(vec![repeated.clone(); count], layout);
+                match (max, layout) {
+                    (Some(max), _) if max < min => {}
+                    (Some(max), _) => {
+                        // each optional repetition past the minimum is one
+                        // more in a chain of productions, rather than each
+                        // count being an alternative of its own
+                        let mut more = repeated.clone();
+                        for _ in 1..max - min {
+                            let once = self.add_inline(term);
+                            self.symbols[once] = vec![
+                                vec![repeated.clone()],
+                                laid_out(vec![repeated.clone(), more], layout),
+                            ];
+                            more = Symbol::Nonterminal(once);
+                        }
+                        let mut symbols = vec![times(min)];
+                        if max > min {
+                            let mut required = vec![repeated.clone(); min];
+                            required.push(more);
+                            symbols.push(laid_out(required, layout));
+                        }
+                        self.symbols[index] = symbols;
+                    }
+                    (None, None) => {
+                        self.symbols[index] =
+                            vec![times(min), vec![repeated.clone(), Symbol::Nonterminal(index)]]
+                    }
+                    // whitespace goes only between repetitions, so more are
+                    // added on the right of a production repeating at least once
+                    (None, Some(_)) => {
+                        let more = if min == 0 { self.add_inline(term) } else { index };
+                        let again = vec![Symbol::Nonterminal(more), repeated.clone()];
+                        self.symbols[more] = vec![times(min.max(1)), laid_out(again, layout)];
+                        if more != index {
+                            self.symbols[index] = vec![vec![], vec![Symbol::Nonterminal(more)]];
+                        }
+                    }
+                }
+                Symbol::Nonterminal(index)
+            }
+        })
+    }
+
+    /// Add an inline production for the group or repeat `term`, with its
+    /// alternatives to follow
+    fn add_inline(&mut self, term: &'gram Term) -> usize {
+        let index = self.symbols.len();
+        self.lhs.push(term);
+        self.expressions.push(vec![]);
+        self.symbols.push(vec![]);
+        self.inline.push(true);
+        index
+    }
+
+    /// The empty derivation of nullable `production`, as the nodes it adds
+    /// to the children of its parent
+    fn empty(&self, production: usize) -> Vec<ParseTreeNode<'gram>> {
+        let expression = self.nullable[production].expect("production should be nullable");
+        let children = self.symbols[production][expression]
+            .iter()
+            .flat_map(|symbol| match *symbol {
+                Symbol::Nonterminal(n) => self.empty(n),
+                _ => vec![ParseTreeNode::Terminal(String::new())],
+            })
+            .collect();
+        if self.inline[production] {
+            return children;
+        }
+        vec![ParseTreeNode::Nonterminal(ParseTree::new(
+            self.lhs[production],
+            self.expressions[production][expression],
+            children,
+        ))]
+    }
+}
+
+/// Separate `symbols` by the whitespace production `layout`, if any
+fn laid_out(symbols: Vec<Symbol>, layout: Option<usize>) -> Vec<Symbol> {
+    match layout {
+        Some(whitespace) if symbols.len() > 1 => {
+            let mut spaced = Vec::with_capacity(2 * symbols.len() - 1);
+            for (i, symbol) in symbols.into_iter().enumerate() {
+                if i > 0 {
+                    spaced.push(Symbol::Nonterminal(whitespace));
+                }
+                spaced.push(symbol);
+            }
+            spaced
+        }
+        _ => symbols,
+    }
+}
+
+/// An Earley item: an alternative, how many of its terms have been
+/// recognized, and the input position its recognition began at
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone, Copy)]
+struct Item {
+    production: usize,
+    expression: usize,
+    dot: usize,
+    origin: usize,
+}
+
+impl Item {
+    fn advance(self) -> Item {
+        Item {
+            dot: self.dot + 1,
+            ..self
+        }
+    }
+
+    fn retreat(self) -> Item {
+        Item {
+            dot: self.dot - 1,
+            ..self
+        }
+    }
+}
+
+/// The Earley sets for every position of an input. Items are kept in the
+/// order they were added, which `Parser::tree` relies on.
+struct Chart {
+    sets: Vec<Vec<Item>>,
+    order: Vec<HashMap<Item, usize>>,
+    /// Items of each set waiting on a nonterminal, by its production
+    waiting: Vec<HashMap<usize, Vec<Item>>>,
+    /// Completed items of each set, by production
+    completed: Vec<HashMap<usize, Vec<Item>>>,
+    /// Per set, the topmost item a completion of each production from
+    /// there advances, if it is the last symbol of the only item waiting
+    /// on it, and so on up: Leo's transitive items
+    transitive: Vec<HashMap<usize, Option<Item>>>,
+    /// Per set, each completed item which was advanced straight to a
+    /// transitive item, and that item, skipping the completions between
+    skipped: Vec<Vec<(Item, Item)>>,
+}
+
+impl Chart {
+    fn new(positions: usize) -> Chart {
+        Chart {
+            sets: vec![vec![]; positions],
+            order: vec![HashMap::new(); positions],
+            waiting: vec![HashMap::new(); positions],
+            completed: vec![HashMap::new(); positions],
+            transitive: vec![HashMap::new(); positions],
+            skipped: vec![vec![]; positions],
+        }
+    }
+
+    /// Add `item` to the set at `position`, unless already there.
+    /// Returns whether it was added.
+    fn add(&mut self, position: usize, item: Item) -> bool {
+        if self.order[position].contains_key(&item) {
+            return false;
+        }
+        self.order[position].insert(item, self.sets[position].len());
+        self.sets[position].push(item);
+        true
+    }
+
+    /// Order `item` was added to the set at `position`, if it is there
+    fn order(&self, position: usize, item: &Item) -> Option<usize> {
+        self.order[position].get(item).cloned()
+    }
+}
+
+/// A derivation in progress while `Parser::tree` or `Parser::derivation`
+/// walks an item right to left
+struct Frame<'gram> {
+    /// The completed item derived, and where it ends
+    whole: (Item, usize),
+    item: Item,
+    end: usize,
+    order: usize,
+    /// Which derivation of the prefix of `item` to build, when building
+    /// one of many
+    rank: usize,
+    children: Vec<ParseTreeNode<'gram>>,
+}
+
+/// A way the last term before the dot of an item may be derived, after
+/// the item before it: by a completed item, or a terminal scanned from a
+/// position
+#[derive(Clone, Copy)]
+enum Step {
+    Nonterminal(Item),
+    Terminal(usize),
+}
+
+/// A count in progress while `Parser::count` walks items right to left
+struct Tally {
+    /// The item counted, and whether it is counted as a whole completed
+    /// item, cut if it is already being counted within itself
+    key: (Item, usize, bool),
+    /// The completed items being derived around the item over its span
+    around: Vec<Item>,
+    steps: Vec<Step>,
+    next: usize,
+    /// Derivations of the last symbol of the current step, once counted
+    last: Option<usize>,
+    total: usize,
+}
+
+/// Counts of derivations, up to `limit`, for building up to that many
+/// derivations without building those they are made of more than once.
+///
+/// A completed item is not derived within itself over the same span, as
+/// cyclic grammars would otherwise derive infinitely many trees: such a
+/// derivation is cut. Everything derived within an item spans part of its
+/// span, so it may only be cut by the items being derived around it over
+/// exactly that span, and counts are kept by those items.
+struct Counts {
+    limit: usize,
+    known: HashMap<(Item, usize, bool, Vec<Item>), usize>,
+    /// Completed items being derived, by the span they are derived over
+    active: HashMap<(usize, usize), Vec<Item>>,
+}
+
+impl Counts {
+    fn new(limit: usize) -> Counts {
+        Counts {
+            limit,
+            known: HashMap::new(),
+            active: HashMap::new(),
+        }
+    }
+
+    /// Whether the completed `item` ending at `end` is being derived
+    fn is_active(&self, item: &Item, end: usize) -> bool {
+        match self.active.get(&(item.origin, end)) {
+            Some(items) => items.contains(item),
+            None => false,
+        }
+    }
+
+    /// The completed items being derived from `origin` to `end`, in order
+    fn around(&self, origin: usize, end: usize) -> Vec<Item> {
+        let mut items = self.active.get(&(origin, end)).cloned().unwrap_or_default();
+        items.sort();
+        items
+    }
+
+    /// Begin deriving the completed `item` ending at `end`
+    fn enter(&mut self, item: Item, end: usize) {
+        self.active.entry((item.origin, end)).or_default().push(item);
+    }
+
+    /// Finish deriving the completed `item` ending at `end`
+    fn leave(&mut self, item: &Item, end: usize) {
+        if let Some(items) = self.active.get_mut(&(item.origin, end)) {
+            items.retain(|active| active != item);
+        }
+    }
+}
+
+/// An Earley parser of inputs against a `Grammar`.
+///
+/// Right recursion is recognized with Leo's transitive items, so the chart
+/// holds a linear number of items for it rather than a quadratic one. The
+/// completions this skips are found again from the chart as derivations
+/// need them.
+struct Parser<'gram, 'input> {
+    rules: Rules<'gram>,
+    input: Input<'input>,
+    chart: Chart,
+    /// Per set with skipped completions, each completed item they were
+    /// skipped below, linked to the completed items of its last symbol
+    links: RefCell<HashMap<usize, HashMap<Item, Vec<Item>>>>,
+}
+
+impl<'gram, 'input> Parser<'gram, 'input> {
+    fn new(grammar: &'gram Grammar, input: Input<'input>) -> Result<Parser<'gram, 'input>, Error> {
+        let tokens = match input {
+            Input::Text(_) => false,
+            Input::Tokens(_) => true,
+        };
+        let mut parser = Parser {
+            rules: Rules::new(grammar, tokens)?,
+            input,
+            chart: Chart::new(input.len() + 1),
+            links: RefCell::new(HashMap::new()),
+        };
+        parser.recognize();
+        Ok(parser)
+    }
+
+    fn symbols(&self, item: &Item) -> &[Symbol] {
+        &self.rules.symbols[item.production][item.expression]
+    }
+
+    fn add(&mut self, position: usize, item: Item) {
+        if self.chart.add(position, item) {
+            let (index, production) = match self.symbols(&item).get(item.dot) {
+                Some(&Symbol::Nonterminal(n)) => (&mut self.chart.waiting[position], n),
+                Some(_) => return,
+                None => (&mut self.chart.completed[position], item.production),
+            };
+            index.entry(production).or_insert_with(Vec::new).push(item);
+        }
+    }
+
+    fn predict(&mut self, position: usize, production: usize) {
+        for expression in 0..self.rules.symbols[production].len() {
+            self.add(
+                position,
+                Item {
+                    production,
+                    expression,
+                    dot: 0,
+                    origin: position,
+                },
+            );
+        }
+    }
+
+    fn recognize(&mut self) {
+        let start = self.rules.start;
+        self.predict(0, start);
+
+        for position in 0..self.chart.sets.len() {
+            let mut next = 0;
+            while next < self.chart.sets[position].len() {
+                let item = self.chart.sets[position][next];
+                next += 1;
+
+                let symbol = self.symbols(&item).get(item.dot).cloned();
+                match symbol {
+                    None => self.complete(position, item),
+                    Some(Symbol::Nonterminal(n)) => {
+                        self.predict(position, n);
+                        // nullable nonterminals may complete before they are
+                        // predicted again, so step over them right away
+                        if self.rules.nullable[n].is_some() {
+                            self.add(position, item.advance());
+                        }
+                    }
+                    Some(ref symbol) => {
+                        for end in self.scan(symbol, position) {
+                            self.add(end, item.advance());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Advance every item waiting on the production of the completed
+    /// `item`, or straight to the transitive item above them if any
+    fn complete(&mut self, position: usize, item: Item) {
+        // the set an empty recognition began in is still growing
+        if item.origin < position {
+            if let Some(top) = self.transitive(item.origin, item.production) {
+                let parent = self.penultimate(item.origin, item.production);
+                if parent.map(Item::advance) != Some(top) {
+                    self.chart.skipped[position].push((item, top));
+                }
+                self.add(position, top);
+                return;
+            }
+        }
+
+        let mut next = 0;
+        while let Some(&parent) = self.chart.waiting[item.origin]
+            .get(&item.production)
+            .and_then(|waiting| waiting.get(next))
+        {
+            next += 1;
+            self.add(position, parent.advance());
+        }
+    }
+
+    /// The only item of the set at `position` waiting on `production`, if
+    /// there is one and the production is its last symbol
+    fn penultimate(&self, position: usize, production: usize) -> Option<Item> {
+        match self.chart.waiting[position].get(&production) {
+            Some(waiting) if waiting.len() == 1 => {
+                let parent = waiting[0];
+                if parent.dot + 1 == self.symbols(&parent).len() {
+                    Some(parent)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The transitive item for a completion of `production` from the
+    /// finished set at `position`: the penultimate item waiting on it
+    /// advanced, or the transitive item above that. The chain stops below
+    /// items which began in the set they wait in.
+    fn transitive(&mut self, position: usize, production: usize) -> Option<Item> {
+        let mut chain = vec![];
+        let (mut position, mut production) = (position, production);
+        let mut top = loop {
+            if let Some(&known) = self.chart.transitive[position].get(&production) {
+                break known;
+            }
+            match self.penultimate(position, production) {
+                Some(parent) => {
+                    chain.push((position, production, parent));
+                    if parent.origin == position {
+                        break None;
+                    }
+                    position = parent.origin;
+                    production = parent.production;
+                }
+                None => {
+                    self.chart.transitive[position].insert(production, None);
+                    break None;
+                }
+            }
+        };
+        while let Some((position, production, parent)) = chain.pop() {
+            let item = top.unwrap_or_else(|| parent.advance());
+            self.chart.transitive[position].insert(production, Some(item));
+            top = Some(item);
+        }
+        top
+    }
+
+    /// Completed items of the last nonterminal of the completed `item`
+    /// ending at `end` which were skipped for a transitive item
+    fn skipped(&self, item: &Item, end: usize) -> Vec<Item> {
+        // skipped items are only reached through the transitive items above
+        // them, or by accepting them
+        if !self.links.borrow().contains_key(&end)
+            && !self.chart.skipped[end].iter().any(|&(_, top)| top == *item)
+        {
+            return vec![];
+        }
+        self.links
+            .borrow_mut()
+            .entry(end)
+            .or_insert_with(|| self.chains(end))
+            .get(item)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Link the items of each chain of completions skipped at `end`, from
+    /// each completed item to the one below it
+    fn chains(&self, end: usize) -> HashMap<Item, Vec<Item>> {
+        let mut links: HashMap<Item, Vec<Item>> = HashMap::new();
+        for &(bottom, top) in &self.chart.skipped[end] {
+            let mut child = bottom;
+            while child != top {
+                let parent = self.penultimate(child.origin, child.production)
+                    .expect("skipped item should have a transitive item above it")
+                    .advance();
+                let children = links.entry(parent).or_default();
+                // the chain above is already linked
+                if children.contains(&child) {
+                    break;
+                }
+                children.push(child);
+                child = parent;
+            }
+        }
+        links
+    }
+
+    /// Completed items of nonterminal `n` ending at `end`, which may be the
+    /// last symbol of `item`: those in the chart, followed by any skipped
+    fn completions(&self, item: &Item, n: usize, end: usize) -> Vec<Item> {
+        let mut completions = self.chart.completed[end].get(&n).cloned().unwrap_or_default();
+        if item.dot == self.symbols(item).len() {
+            for child in self.skipped(item, end) {
+                if !completions.contains(&child) {
+                    completions.push(child);
+                }
+            }
+        }
+        completions
+    }
+
+    /// Completed recognitions of the start rule spanning the whole input,
+    /// in the chart or skipped below a transitive item
+    fn accepted(&self) -> Vec<Item> {
+        let end = self.input.len();
+        let mut skipped = vec![];
+        if !self.chart.skipped[end].is_empty() {
+            let mut links = self.links.borrow_mut();
+            let links = links.entry(end).or_insert_with(|| self.chains(end));
+            skipped.extend(links.values().flat_map(|children| children.iter()));
+        }
+        let mut accepted = vec![];
+        for item in self.chart.sets[end].iter().chain(skipped.iter()) {
+            if item.production == self.rules.start && item.origin == 0
+                && item.dot == self.symbols(item).len() && !accepted.contains(item)
+            {
+                accepted.push(*item);
+            }
+        }
+        accepted
+    }
+
+    /// Position of the furthest non-empty Earley set, where recognition stopped
+    fn furthest(&self) -> usize {
+        self.chart
+            .sets
+            .iter()
+            .rposition(|set| !set.is_empty())
+            .unwrap_or(0)
+    }
+
+    /// The error for an input which is not a sentence
+    fn rejection(&self) -> Error {
+        let unit = match self.input {
+            Input::Text(_) => "byte",
+            Input::Tokens(_) => "token",
+        };
+        Error::ParseError(format!(
+            "Parsing error: input is not derivable from {}, failed at {} {}",
+            self.rules.lhs[self.rules.start],
+            unit,
+            self.furthest()
+        ))
+    }
+
+    /// Positions where texts regex `r` matches from `position` end
+    fn matches(&self, r: usize, text: &str, position: usize) -> Vec<usize> {
+        let dfa = &self.rules.regexes[r];
+        let mut ends = vec![];
+        let mut state = 0;
+        if dfa.is_accepting(state) {
+            ends.push(position);
+        }
+        for (offset, c) in text[position..].char_indices() {
+            state = match dfa.next(state, c) {
+                Some(next) => next,
+                None => break,
+            };
+            if dfa.is_accepting(state) {
+                ends.push(position + offset + c.len_utf8());
+            }
+        }
+        ends
+    }
+
+    /// Positions where a literal, class, regex or token scanned from
+    /// `position` may end. A token scanned by a literal, class or regex
+    /// must match its text as a whole.
+    fn scan(&self, symbol: &Symbol, position: usize) -> Vec<usize> {
+        let tokens = match self.input {
+            Input::Text(text) => {
+                return match *symbol {
+                    Symbol::Literal(ref t) if text[position..].starts_with(t.as_str()) => {
+                        vec![position + t.len()]
+                    }
+                    Symbol::Class(ref class) => text[position..]
+                        .chars()
+                        .next()
+                        .filter(|&c| class.contains(c))
+                        .map(|c| position + c.len_utf8())
+                        .into_iter()
+                        .collect(),
+                    Symbol::Regex(r) => self.matches(r, text, position),
+                    _ => vec![],
+                }
+            }
+            Input::Tokens(tokens) => tokens,
+        };
+
+        let token = match (symbol, tokens.get(position)) {
+            (Symbol::Literal(t), _) if t.is_empty() => return vec![position],
+            (_, Some(token)) => token,
+            (_, None) => return vec![],
+        };
+        let scanned = match *symbol {
+            Symbol::Kind(ref kind) => token.kind == *kind,
+            Symbol::Literal(ref t) => token.text == *t,
+            Symbol::Class(ref class) => {
+                let mut chars = token.text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => class.contains(c),
+                    _ => false,
+                }
+            }
+            Symbol::Regex(r) => self.rules.regexes[r].accepts(&token.text),
+            Symbol::Nonterminal(_) => false,
+        };
+        if scanned {
+            vec![position + 1]
+        } else {
+            vec![]
+        }
+    }
+
+    /// Positions from which a literal, class, regex or token may have been
+    /// scanned to end at `end`, nearest first
+    fn starts(&self, symbol: &Symbol, end: usize) -> Vec<usize> {
+        let text = match self.input {
+            Input::Text(text) => text,
+            Input::Tokens(_) => {
+                return (end.saturating_sub(1)..end + 1)
+                    .rev()
+                    .filter(|&start| self.scan(symbol, start).contains(&end))
+                    .collect()
+            }
+        };
+        let before = &text[..end];
+        match *symbol {
+            Symbol::Literal(ref t) if before.ends_with(t.as_str()) => vec![end - t.len()],
+            Symbol::Class(ref class) => before
+                .chars()
+                .next_back()
+                .filter(|&c| class.contains(c))
+                .map(|c| end - c.len_utf8())
+                .into_iter()
+                .collect(),
+            Symbol::Regex(r) => (0..end + 1)
+                .rev()
+                .filter(|&start| {
+                    text.is_char_boundary(start) && self.rules.regexes[r].accepts(&text[start..end])
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The text read from `start` to `end`
+    fn text(&self, start: usize, end: usize) -> String {
+        match self.input {
+            Input::Text(text) => text[start..end].to_string(),
+            Input::Tokens(tokens) => tokens[start..end]
+                .iter()
+                .map(|token| token.text.as_str())
+                .collect(),
+        }
+    }
+
+    /// Build a derivation of the completed `item` ending at `end`.
+    ///
+    /// Items are walked right to left. Each step only moves to items which
+    /// end at an earlier position, or which were added to the same Earley
+    /// set earlier, or down a chain of skipped completions, so the walk
+    /// never cycles and never needs to backtrack.
+    fn tree(&self, item: Item, end: usize) -> ParseTree<'gram> {
+        // an item skipped below a transitive item follows those in its set
+        let order = self.chart.order(end, &item).unwrap_or(self.chart.sets[end].len());
+        let mut stack = vec![Frame {
+            whole: (item, end),
+            item,
+            end,
+            order,
+            rank: 0,
+            children: vec![],
+        }];
+
+        loop {
+            let (item, end, order) = {
+                let frame = stack.last().expect("stack should not be empty");
+                (frame.item, frame.end, frame.order)
+            };
+
+            if item.dot == 0 {
+                let mut frame = stack.pop().expect("stack should not be empty");
+                if self.rules.inline[frame.item.production] {
+                    // children of both are gathered right to left
+                    let parent = stack.last_mut().expect("inline production should have parent");
+                    parent.children.append(&mut frame.children);
+                    continue;
+                }
+                frame.children.reverse();
+                let tree = ParseTree::new(
+                    self.rules.lhs[frame.item.production],
+                    self.rules.expressions[frame.item.production][frame.item.expression],
+                    frame.children,
+                );
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(ParseTreeNode::Nonterminal(tree)),
+                    None => return tree,
+                }
+                continue;
+            }
+
+            let prefix = item.retreat();
+            // order of the prefix item in the set at `start`, if it may be used
+            let usable = |start: usize| match self.chart.order(start, &prefix) {
+                Some(o) if start < end || o < order => Some(o),
+                _ => None,
+            };
+
+            match self.symbols(&item)[item.dot - 1] {
+                Symbol::Nonterminal(n) => {
+                    // a skipped completion continues a chain down, which
+                    // leads to the chart; otherwise prefer a completed
+                    // recognition of the nonterminal
+                    let skipped = self.skipped(&item, end)
+                        .into_iter()
+                        .filter_map(|c| {
+                            let child_order = self.chart.order(end, &c).unwrap_or(order);
+                            usable(c.origin).map(|p| (child_order, c, p))
+                        })
+                        .next();
+                    let completed = skipped.or_else(|| {
+                        self.chart.completed[end]
+                            .get(&n)
+                            .into_iter()
+                            .flat_map(|items| items.iter())
+                            .map(|c| (self.chart.order(end, c).expect("item should be in chart"), *c))
+                            .take_while(|&(o, _)| o < order)
+                            .filter_map(|(o, c)| usable(c.origin).map(|p| (o, c, p)))
+                            .next()
+                    });
+
+                    match completed {
+                        Some((child_order, child, prefix_order)) => {
+                            {
+                                let frame = stack.last_mut().expect("stack should not be empty");
+                                frame.item = prefix;
+                                frame.end = child.origin;
+                                frame.order = prefix_order;
+                            }
+                            stack.push(Frame {
+                                whole: (child, end),
+                                item: child,
+                                end,
+                                order: child_order,
+                                rank: 0,
+                                children: vec![],
+                            });
+                        }
+                        None => {
+                            // otherwise the nonterminal was stepped over as nullable
+                            let prefix_order = usable(end).expect("nonterminal should be derivable");
+                            let empty = self.rules.empty(n);
+                            let frame = stack.last_mut().expect("stack should not be empty");
+                            frame.children.extend(empty.into_iter().rev());
+                            frame.item = prefix;
+                            frame.order = prefix_order;
+                        }
+                    }
+                }
+                ref symbol => {
+                    let (start, prefix_order) = self.starts(symbol, end)
+                        .into_iter()
+                        .filter_map(|start| usable(start).map(|o| (start, o)))
+                        .next()
+                        .expect("scanned terminal should have prefix");
+                    let text = self.text(start, end);
+                    let frame = stack.last_mut().expect("stack should not be empty");
+                    frame.children.push(ParseTreeNode::Terminal(text));
+                    frame.item = prefix;
+                    frame.end = start;
+                    frame.order = prefix_order;
+                }
+            }
+        }
+    }
+
+    /// The ways the last term before the dot of `item` ending at `end` may
+    /// be derived, after a recognition of the item before it
+    fn steps(&self, item: &Item, end: usize) -> Vec<Step> {
+        let prefix = item.retreat();
+        match self.symbols(item)[item.dot - 1] {
+            Symbol::Nonterminal(n) => self.completions(item, n, end)
+                .into_iter()
+                .filter(|child| self.chart.order(child.origin, &prefix).is_some())
+                .map(Step::Nonterminal)
+                .collect(),
+            ref symbol => self.starts(symbol, end)
+                .into_iter()
+                .filter(|&start| self.chart.order(start, &prefix).is_some())
+                .map(Step::Terminal)
+                .collect(),
+        }
+    }
+
+    /// Begin counting the derivations of `item` ending at `end`, as a whole
+    /// completed item or the terms before its dot. Returns the count if it
+    /// is known already, otherwise pushes a tally to find it.
+    fn open(
+        &self,
+        counts: &mut Counts,
+        tallies: &mut Vec<Tally>,
+        item: Item,
+        end: usize,
+        whole: bool,
+    ) -> Option<usize> {
+        if whole && counts.is_active(&item, end) {
+            return Some(0);
+        }
+        if item.dot == 0 {
+            return Some(if item.origin == end { 1 } else { 0 });
+        }
+        let around = counts.around(item.origin, end);
+        if let Some(&count) = counts.known.get(&(item, end, whole, around.clone())) {
+            return Some(count);
+        }
+        if whole {
+            counts.enter(item, end);
+        }
+        tallies.push(Tally {
+            key: (item, end, whole),
+            around,
+            steps: self.steps(&item, end),
+            next: 0,
+            last: None,
+            total: 0,
+        });
+        None
+    }
+
+    /// Count the derivations of `item` ending at `end`, as a whole completed
+    /// item or the terms before its dot, up to the limit of `counts`.
+    fn count(&self, counts: &mut Counts, item: Item, end: usize, whole: bool) -> usize {
+        let mut tallies = vec![];
+        let mut found = self.open(counts, &mut tallies, item, end, whole);
+        while let Some(mut tally) = tallies.pop() {
+            if let Some(count) = found.take() {
+                match tally.last {
+                    // the item before a step without derivations is not counted
+                    None if count == 0 => tally.next += 1,
+                    None => tally.last = Some(count),
+                    Some(last) => {
+                        let more = last.saturating_mul(count);
+                        tally.total = cmp::min(counts.limit, tally.total.saturating_add(more));
+                        tally.next += 1;
+                        tally.last = None;
+                    }
+                }
+            }
+
+            let (item, end, whole) = tally.key;
+            let step = match tally.steps.get(tally.next) {
+                Some(&step) => step,
+                None => {
+                    if whole {
+                        counts.leave(&item, end);
+                    }
+                    counts.known.insert((item, end, whole, tally.around), tally.total);
+                    found = Some(tally.total);
+                    continue;
+                }
+            };
+            let counted = tally.last.is_some();
+            tallies.push(tally);
+            found = match (step, counted) {
+                (Step::Terminal(_), false) => Some(1),
+                (Step::Nonterminal(child), false) => {
+                    self.open(counts, &mut tallies, child, end, true)
+                }
+                (Step::Terminal(start), true) => {
+                    self.open(counts, &mut tallies, item.retreat(), start, false)
+                }
+                (Step::Nonterminal(child), true) => {
+                    self.open(counts, &mut tallies, item.retreat(), child.origin, false)
+                }
+            };
+        }
+        found.expect("count should be found")
+    }
+
+    /// Build derivation `rank` of the completed `item` ending at `end`,
+    /// counting from zero in the order `count` counts them.
+    ///
+    /// Items are walked right to left as `tree` walks them, choosing at each
+    /// step the derivations of the last term and of the item before it which
+    /// the rank falls within.
+    fn derivation(&self, counts: &mut Counts, item: Item, end: usize, rank: usize) -> ParseTree<'gram> {
+        counts.enter(item, end);
+        let mut stack = vec![Frame {
+            whole: (item, end),
+            item,
+            end,
+            order: 0,
+            rank,
+            children: vec![],
+        }];
+
+        loop {
+            let (item, end, mut rank) = {
+                let frame = stack.last().expect("stack should not be empty");
+                (frame.item, frame.end, frame.rank)
+            };
+
+            if item.dot == 0 {
+                let mut frame = stack.pop().expect("stack should not be empty");
+                counts.leave(&frame.whole.0, frame.whole.1);
+                if self.rules.inline[frame.item.production] {
+                    let parent = stack.last_mut().expect("inline production should have parent");
+                    parent.children.append(&mut frame.children);
+                    continue;
+                }
+                frame.children.reverse();
+                let tree = ParseTree::new(
+                    self.rules.lhs[frame.item.production],
+                    self.rules.expressions[frame.item.production][frame.item.expression],
+                    frame.children,
+                );
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(ParseTreeNode::Nonterminal(tree)),
+                    None => return tree,
+                }
+                continue;
+            }
+
+            let prefix = item.retreat();
+            let mut chosen = None;
+            for step in self.steps(&item, end) {
+                let (last, start) = match step {
+                    Step::Nonterminal(child) => (self.count(counts, child, end, true), child.origin),
+                    Step::Terminal(start) => (1, start),
+                };
+                let before = self.count(counts, prefix, start, false);
+                let block = cmp::min(counts.limit, last.saturating_mul(before));
+                if rank < block {
+                    chosen = Some((step, start, rank / last, rank % last));
+                    break;
+                }
+                rank -= block;
+            }
+            let (step, start, prefix_rank, last_rank) = chosen.expect("derivation should be counted");
+
+            {
+                let frame = stack.last_mut().expect("stack should not be empty");
+                frame.item = prefix;
+                frame.end = start;
+                frame.rank = prefix_rank;
+                if let Step::Terminal(_) = step {
+                    frame.children.push(ParseTreeNode::Terminal(self.text(start, end)));
+                }
+            }
+            if let Step::Nonterminal(child) = step {
+                counts.enter(child, end);
+                stack.push(Frame {
+                    whole: (child, end),
+                    item: child,
+                    end,
+                    order: 0,
+                    rank: last_rank,
+                    children: vec![],
+                });
+            }
+        }
+    }
+}
+
+impl Grammar {
+    /// Parse `input` as a sentence of self, beginning from lhs of first
+    /// production, and return its derivation. If the grammar is ambiguous,
+    /// one of the derivations is returned.
+    ///
+    /// Nonterminals without a production match their own text, such as
+    /// `<identifier>`, just as generation emits them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     match grammar.parse_input("GATTACA") {
+    ///         Ok(tree) => println!("{}", tree),
+    ///         Err(e) => println!("not DNA: {}", e),
+    ///     }
+    ///
+    ///     # assert_eq!(grammar.parse_input("GATTACA").unwrap().flatten(), "GATTACA");
+    ///     # assert!(grammar.parse_input("GATTXCA").is_err());
+    /// }
+    /// ```
+    pub fn parse_input<'gram>(&'gram self, input: &str) -> Result<ParseTree<'gram>, Error> {
+        let parser = Parser::new(self, Input::Text(input))?;
+        match parser.accepted().first() {
+            Some(&item) => Ok(parser.tree(item, input.len())),
+            None => Err(parser.rejection()),
+        }
+    }
+
+    /// Parse `input` as a sentence of self, beginning from lhs of first
+    /// production, and return up to `limit` of its distinct derivations.
+    /// More than one means the grammar is ambiguous.
+    ///
+    /// Derivations which recognize some nonterminal within itself over the
+    /// same part of the input are left out, as a grammar such as
+    /// `<s> ::= <s> | "a"` would otherwise derive `"a"` in infinitely many
+    /// ways. Otherwise every derivation is found, given a high enough `limit`.
+    pub fn parse_input_all<'gram>(
+        &'gram self,
+        input: &str,
+        limit: usize,
+    ) -> Result<Vec<ParseTree<'gram>>, Error> {
+        let parser = Parser::new(self, Input::Text(input))?;
+        let accepted = parser.accepted();
+        if accepted.is_empty() {
+            return Err(parser.rejection());
+        }
+
+        let mut counts = Counts::new(limit);
+        let mut trees = vec![];
+        for item in accepted {
+            let count = parser.count(&mut counts, item, input.len(), true);
+            for rank in 0..cmp::min(count, limit - trees.len()) {
+                trees.push(parser.derivation(&mut counts, item, input.len(), rank));
+            }
+        }
+        Ok(trees)
+    }
+
+    /// Whether `input` is a sentence of self, beginning from lhs of first
+    /// production. A grammar without a start rule recognizes nothing.
+    pub fn recognizes(&self, input: &str) -> bool {
+        match Parser::new(self, Input::Text(input)) {
+            Ok(parser) => !parser.accepted().is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    /// Parse `tokens` as a sentence of self, beginning from lhs of first
+    /// production, and return its derivation, with a terminal leaf holding
+    /// the text of each token. See `split_tokens` to make the tokens.
+    ///
+    /// A token of some kind is read by a terminal, or a nonterminal without
+    /// a production, which is that kind. Classes and regex terminals read
+    /// a token whose text they match as a whole. Any `Layout` is ignored,
+    /// as whitespace is skipped by the `Lexer`.
+    pub fn parse_tokens<'gram>(&'gram self, tokens: &[Token]) -> Result<ParseTree<'gram>, Error> {
+        let parser = Parser::new(self, Input::Tokens(tokens))?;
+        match parser.accepted().first() {
+            Some(&item) => Ok(parser.tree(item, tokens.len())),
+            None => Err(parser.rejection()),
+        }
+    }
+
+    /// Whether `tokens` are a sentence of self, beginning from lhs of first
+    /// production. See `parse_tokens`.
+    pub fn recognizes_tokens(&self, tokens: &[Token]) -> bool {
+        match Parser::new(self, Input::Tokens(tokens)) {
+            Ok(parser) => !parser.accepted().is_empty(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::Generator;
+    use production::Production;
+
+    fn parses_to_itself(grammar: &Grammar, input: &str) {
+        let tree = grammar.parse_input(input);
+        assert!(tree.is_ok(), "{:?} should parse: {:?}", input, tree);
+        assert_eq!(tree.unwrap().flatten(), input);
+    }
+
+    #[test]
+    fn parse_dna() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        parses_to_itself(&grammar, "A");
+        parses_to_itself(&grammar, "GATTACA");
+        assert!(grammar.recognizes("GATTACA"));
+        assert!(!grammar.recognizes("GATTACA!"));
+
+        let tree = grammar.parse_input("GA").unwrap();
+        assert_eq!(tree.rhs.to_string(), "<base> <dna>");
+        assert!(grammar.parse_input("").is_err());
+        assert!(grammar.parse_input("GAX").is_err());
+    }
+
+    #[test]
+    fn parse_long_right_recursion() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        let input = "GATTACA".chars().cycle().take(20_000).collect::<String>();
+        assert!(grammar.recognizes(&input));
+        let tree = grammar.parse_input(&input).unwrap();
+        assert_eq!(tree.flatten(), input);
+        assert_eq!(grammar.parse_input_all(&input, 2).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_failure_position() {
+        let grammar = Grammar::from_str("<start> ::= \"ab\" \"cd\"").unwrap();
+        match grammar.parse_input("abce") {
+            Err(Error::ParseError(ref s)) => assert!(s.ends_with("failed at byte 2"), "{}", s),
+            r => panic!("should be Error::ParseError: {:?}", r),
+        };
+    }
+
+    #[test]
+    fn parse_left_recursive() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <list> \",\" <item> | <item>
+            <item> ::= \"x\" | \"yy\"",
+        ).unwrap();
+        parses_to_itself(&grammar, "x,yy,x");
+        assert!(grammar.parse_input("x,,x").is_err());
+        assert!(!grammar.recognizes("x,,x"));
+    }
+
+    #[test]
+    fn parse_nullable_and_cyclic() {
+        let grammar = Grammar::from_str(
+            "<start> ::= <opt> <start> <opt> | <start> | \"x\"
+            <opt> ::= \"\" | <opt> <opt> | \"-\"",
+        ).unwrap();
+        parses_to_itself(&grammar, "x");
+        parses_to_itself(&grammar, "-x");
+        parses_to_itself(&grammar, "--x-");
+        assert!(grammar.parse_input("--").is_err());
+    }
+
+    #[test]
+    fn parse_empty_input() {
+        let grammar = Grammar::from_str("<start> ::= <a> <a>\n<a> ::= \"\"").unwrap();
+        let tree = grammar.parse_input("").unwrap();
+        assert_eq!(tree.children_iter().count(), 2);
+    }
+
+    #[test]
+    fn parse_undefined_nonterminal() {
+        let grammar = Grammar::from_str("<start> ::= \"a\" <b>").unwrap();
+        parses_to_itself(&grammar, "a<b>");
+    }
+
+    #[test]
+    fn parse_generated() {
+        let grammar = Grammar::from_str(
+            "<expr> ::= <term> | <expr> \"+\" <term>
+            <term> ::= <factor> | <term> \"*\" <factor>
+            <factor> ::= \"(\" <expr> \")\" | <digit> | <digit> <factor>
+            <digit> ::= \"0\" | \"1\" | \"2\"",
+        ).unwrap();
+        let mut generator = Generator::new(&grammar);
+        generator.max_expansions = 1_000;
+        for seed in 0..50 {
+            if let Ok(sentence) = generator.generate_from_seed(seed) {
+                parses_to_itself(&grammar, &sentence);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_unicode() {
+        let grammar = Grammar::from_str("<🙃> ::= \"😵\" <🙃> | \"😋\"").unwrap();
+        parses_to_itself(&grammar, "😵😵😋");
+        assert!(grammar.parse_input("😵😵").is_err());
+    }
+
+    #[test]
+    fn parse_classes() {
+        let grammar = Grammar::from_str(
+            "<id> ::= [a-zA-Z_] <rest>
+            <rest> ::= [a-zA-Z0-9_] <rest> | \"\"",
+        ).unwrap();
+        parses_to_itself(&grammar, "_x9");
+        assert!(grammar.parse_input("9x").is_err());
+
+        let grammar = Grammar::from_str("<s> ::= [^a] | [α-ω] \"!\"").unwrap();
+        parses_to_itself(&grammar, "😋");
+        parses_to_itself(&grammar, "λ!");
+        assert!(grammar.parse_input("a").is_err());
+        let trees = grammar.parse_input_all("λ", 5).unwrap();
+        assert_eq!(trees.len(), 1);
+    }
+
+    #[test]
+    fn parse_regexes() {
+        let grammar = Grammar::from_str(
+            "<sum> ::= <number> | <number> \"+\" <sum>
+            <number> ::= /[0-9]+(\\.[0-9]+)?/ <space>
+            <space> ::= / */",
+        ).unwrap();
+        parses_to_itself(&grammar, "1.5 +22+3  ");
+        assert!(grammar.parse_input("1. + 2").is_err());
+
+        // the regex may match more or less of the input than is derived
+        let grammar = Grammar::from_str("<s> ::= /a*/ /a*b?/").unwrap();
+        parses_to_itself(&grammar, "aaab");
+        parses_to_itself(&grammar, "");
+        assert_eq!(grammar.parse_input_all("aa", 10).unwrap().len(), 3);
+
+        let invalid = Grammar::from_parts(vec![
+            Production::from_parts(
+                Term::Nonterminal(String::from("s")),
+                vec![Expression::from_parts(vec![Term::Regex(String::from("("))])],
+            ),
+        ]);
+        assert!(invalid.parse_input("").is_err());
+    }
+
+    #[test]
+    fn parse_groups_and_repeats() {
+        let grammar = Grammar::from_str(
+            "<list> ::= \"[\" (<item> (\",\" <item>)*)? \"]\"
+            <item> ::= DIGIT{1,2} | <list>",
+        ).unwrap();
+        parses_to_itself(&grammar, "[]");
+        parses_to_itself(&grammar, "[1,23,[4,[]]]");
+        assert!(grammar.parse_input("[123]").is_err());
+        assert!(grammar.parse_input("[1,]").is_err());
+
+        // groups and repeats derive in place, among the children of their parent
+        let tree = grammar.parse_input("[1,[],3]").unwrap();
+        let children = tree.children_iter()
+            .map(|child| match *child {
+                ParseTreeNode::Terminal(ref t) => t.clone(),
+                ParseTreeNode::Nonterminal(ref t) => t.lhs.to_string(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(children, ["[", "<item>", ",", "<item>", ",", "<item>", "]"]);
+
+        let nullable = Grammar::from_str("<s> ::= \"a\"* (<t> | \"b\") \"c\"
+            <t> ::= \"\"").unwrap();
+        parses_to_itself(&nullable, "c");
+        parses_to_itself(&nullable, "aabc");
+        assert_eq!(nullable.parse_input_all("c", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_long_bounded_repeats() {
+        let grammar = Grammar::from_str("<s> ::= \"a\"{0,3000} \"b\"{2,4}").unwrap();
+        parses_to_itself(&grammar, "bb");
+        parses_to_itself(&grammar, "aaabbbb");
+        assert!(!grammar.recognizes("ab"));
+        assert!(!grammar.recognizes("abbbbb"));
+        assert_eq!(grammar.parse_input_all("aabbb", 10).unwrap().len(), 1);
+
+        let input = "a".repeat(3000) + "bb";
+        parses_to_itself(&grammar, &input);
+        assert!(!grammar.recognizes(&("a".repeat(3001) + "bb")));
+    }
+
+    #[test]
+    fn parse_all_derivations() {
+        let grammar = Grammar::from_str("<s> ::= <s> <s> | \"a\"").unwrap();
+        let trees = grammar.parse_input_all("aaa", 10).unwrap();
+        assert_eq!(trees.len(), 2);
+        assert_ne!(trees[0], trees[1]);
+        assert!(trees.iter().all(|tree| tree.flatten() == "aaa"));
+        assert_eq!(grammar.parse_input_all("aaaa", 10).unwrap().len(), 5);
+        assert_eq!(grammar.parse_input_all("aaaa", 3).unwrap().len(), 3);
+        assert!(grammar.parse_input_all("ab", 10).is_err());
+
+        let dna = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        assert_eq!(dna.parse_input_all("GATTACA", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn parse_all_cyclic_derivations() {
+        let grammar = Grammar::from_str("<s> ::= <s> | \"a\"").unwrap();
+        assert_eq!(grammar.parse_input_all("a", 10).unwrap().len(), 2);
+
+        let grammar = Grammar::from_str(
+            "<s> ::= <e> \"a\" <e>
+            <e> ::= \"\" | <e> <e>",
+        ).unwrap();
+        let trees = grammar.parse_input_all("a", 100).unwrap();
+        assert!(trees.len() > 1);
+        assert!(trees.iter().all(|tree| tree.flatten() == "a"));
+    }
+
+    #[test]
+    fn parse_all_nested_cyclic_derivations() {
+        let grammar = Grammar::from_str(
+            "<A> ::= <A> | \"\" <C> \"\"
+            <B> ::= \"a\" <B> \"\" | <A> <B> <A> | \"\"
+            <C> ::= \"\" | \"\" <B> \"\"",
+        ).unwrap();
+        for n in 1..10 {
+            let input = "a".repeat(n);
+            let trees = grammar.parse_input_all(&input, 2).unwrap();
+            assert_eq!(trees.len(), 2);
+            assert_ne!(trees[0], trees[1]);
+            assert!(trees.iter().all(|tree| tree.flatten() == input));
+        }
+    }
+}
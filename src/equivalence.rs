@@ -0,0 +1,168 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use rand::Rng;
+use error::Error;
+use generate::Generator;
+use grammar::Grammar;
+use language::Bound;
+use term::Term;
+
+/// Sentences of the languages of two `Grammar`s which the other does not
+/// derive, each shortest first
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct LanguageDiff {
+    /// Sentences only the first grammar derives
+    pub only_left: Vec<String>,
+    /// Sentences only the second grammar derives
+    pub only_right: Vec<String>,
+}
+
+impl LanguageDiff {
+    /// Whether no counterexample was found in either direction
+    pub fn is_empty(&self) -> bool {
+        self.only_left.is_empty() && self.only_right.is_empty()
+    }
+}
+
+impl fmt::Display for LanguageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for sentence in &self.only_left {
+            writeln!(f, "- {}", Term::Terminal(sentence.clone()))?;
+        }
+        for sentence in &self.only_right {
+            writeln!(f, "+ {}", Term::Terminal(sentence.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// The sentences of `sentences` which `grammar` does not recognize,
+/// shortest first
+fn unrecognized<I: IntoIterator<Item = String>>(grammar: &Grammar, sentences: I) -> Vec<String> {
+    let mut unrecognized = sentences
+        .into_iter()
+        .filter(|sentence| !grammar.recognizes(sentence))
+        .collect::<Vec<_>>();
+    unrecognized.sort_by_key(|sentence| sentence.chars().count());
+    unrecognized
+}
+
+/// Up to `samples` distinct sentences generated from `grammar`. Generations
+/// which hit the recursion limit are skipped.
+fn sample<R: Rng>(grammar: &Grammar, rng: &mut R, samples: usize) -> Result<BTreeSet<String>, Error> {
+    let generator = Generator::new(grammar);
+    let mut sentences = BTreeSet::new();
+    for _ in 0..samples {
+        match generator.generate_seeded(rng) {
+            Ok(sentence) => {
+                sentences.insert(sentence);
+            }
+            Err(Error::RecursionLimit(_)) => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(sentences)
+}
+
+impl Grammar {
+    /// Compare the languages of self and `other` on every sentence either
+    /// derives within `bound`, finding those the other does not derive.
+    ///
+    /// An empty diff shows the languages agree up to the bound, which makes
+    /// a regression test for rewriting a grammar, such as by left factoring.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Bound, Grammar};
+    ///
+    /// fn main() {
+    ///     let before = Grammar::from_str(
+    ///         "<list> ::= <item> | <item> \",\" <list>
+    ///         <item> ::= \"a\" | \"b\"",
+    ///     ).unwrap();
+    ///     let after = Grammar::from_str(
+    ///         "<list> ::= <item> <rest>
+    ///         <rest> ::= \"\" | \",\" <list>
+    ///         <item> ::= \"a\" | \"b\"",
+    ///     ).unwrap();
+    ///
+    ///     let diff = before.language_diff(&after, Bound::Length(7));
+    ///     assert!(diff.is_empty(), "{}", diff);
+    /// }
+    /// ```
+    pub fn language_diff(&self, other: &Grammar, bound: Bound) -> LanguageDiff {
+        LanguageDiff {
+            only_left: unrecognized(other, self.sentences_bounded(bound)),
+            only_right: unrecognized(self, other.sentences_bounded(bound)),
+        }
+    }
+
+    /// Whether self and `other` derive the same sentences within `bound`.
+    /// See `language_diff`.
+    pub fn equivalent_within(&self, other: &Grammar, bound: Bound) -> bool {
+        self.language_diff(other, bound).is_empty()
+    }
+
+    /// Compare the languages of self and `other` on `samples` sentences
+    /// randomly generated from each, finding those the other does not
+    /// derive. Sampling reaches sentences beyond any practical bound for
+    /// `language_diff`, but cannot show the languages agree.
+    pub fn sample_language_diff<R: Rng>(
+        &self,
+        other: &Grammar,
+        rng: &mut R,
+        samples: usize,
+    ) -> Result<LanguageDiff, Error> {
+        let left = sample(self, rng, samples)?;
+        let right = sample(other, rng, samples)?;
+        Ok(LanguageDiff {
+            only_left: unrecognized(other, left),
+            only_right: unrecognized(self, right),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::rng_from_seed;
+
+    #[test]
+    fn counterexamples_both_ways() {
+        let left = Grammar::from_str("<s> ::= \"a\" <s> | \"a\" | \"b\"").unwrap();
+        let right = Grammar::from_str("<s> ::= \"a\" <s> | \"\"").unwrap();
+        let diff = left.language_diff(&right, Bound::Length(3));
+
+        assert_eq!(diff.only_left, vec!["b", "ab", "aab"]);
+        assert_eq!(diff.only_right, vec![""]);
+        assert!(!diff.is_empty());
+        assert!(!left.equivalent_within(&right, Bound::Depth(2)));
+        assert_eq!(diff.to_string(), "- \"b\"\n- \"ab\"\n- \"aab\"\n+ \"\"\n");
+    }
+
+    #[test]
+    fn equivalent_refactoring() {
+        let left = Grammar::from_str("<e> ::= <e> \"+\" \"1\" | \"1\"").unwrap();
+        let right = Grammar::from_str(
+            "<e> ::= \"1\" <tail>
+            <tail> ::= \"+\" \"1\" <tail> | \"\"",
+        ).unwrap();
+        assert!(left.equivalent_within(&right, Bound::Length(9)));
+        assert!(left.equivalent_within(&right, Bound::Depth(4)));
+        assert_eq!(left.language_diff(&right, Bound::Length(9)), LanguageDiff::default());
+    }
+
+    #[test]
+    fn sampled_counterexamples() {
+        let left = Grammar::from_str("<s> ::= \"x\" <s> | \"y\"").unwrap();
+        let right = Grammar::from_str("<s> ::= \"x\" <s> | \"y\" | \"z\"").unwrap();
+        let diff = left
+            .sample_language_diff(&right, &mut rng_from_seed(7), 50)
+            .unwrap();
+        assert!(diff.only_left.is_empty());
+        assert!(!diff.only_right.is_empty());
+        assert!(diff.only_right.iter().all(|sentence| sentence.ends_with('z')));
+    }
+}
@@ -27,7 +27,7 @@ impl error::Error for Error {
     }
 }
 
-impl<'a> From<Err<&'a [u8]>> for Error {
+impl From<Err<&[u8]>> for Error {
     fn from(err: Err<&[u8]>) -> Self {
         let string = match err {
             Err::Code(_) => String::from("Parsing error: Unknown origin"),
@@ -58,10 +58,16 @@ impl<'a> From<Err<&'a [u8]>> for Error {
     }
 }
 
+impl From<fmt::Error> for Error {
+    fn from(_: fmt::Error) -> Self {
+        Error::GenerateError(String::from("Failed writing generated sentence!"))
+    }
+}
+
 impl From<Needed> for Error {
     fn from(needed: Needed) -> Self {
         let string = match needed {
-            Needed::Unknown => format!("Data error: insufficient size, expectation unknown"),
+            Needed::Unknown => String::from("Data error: insufficient size, expectation unknown"),
             Needed::Size(s) => format!("Data error: insufficient size, expected {} bytes", s),
         };
 
@@ -86,21 +92,14 @@ mod tests {
     #[test]
     fn gets_error_error() {
         let nom_result = give_error_kind("12340".as_bytes());
-        let nom_error;
-        match nom_result {
-            IResult::Error(e) => nom_error = e,
+        let nom_error = match nom_result {
+            IResult::Error(e) => e,
             _ => panic!("gets_error_error should result in IResult::Error"),
-        }
-
-        let bnf_error: Result<String, Error> = Err(Error::from(nom_error));
+        };
 
-        assert!(
-            bnf_error.is_err(),
-            "production result should be error {:?}",
-            bnf_error
-        );
+        let bnf_error = Error::from(nom_error);
 
-        match bnf_error.unwrap_err() {
+        match bnf_error {
             Error::ParseError(_) => (),
             e => panic!("production error should be error parsing: {:?}", e),
         }
@@ -109,20 +108,14 @@ mod tests {
     #[test]
     fn gets_error_incomplete() {
         let nom_result = give_error_kind("".as_bytes());
-        let nom_error;
-        match nom_result {
-            IResult::Incomplete(e) => nom_error = e,
+        let nom_error = match nom_result {
+            IResult::Incomplete(e) => e,
             _ => panic!("gets_error_error should result in IResult::Error"),
-        }
+        };
 
-        let bnf_error: Result<String, Error> = Err(Error::from(nom_error));
+        let bnf_error = Error::from(nom_error);
 
-        assert!(
-            bnf_error.is_err(),
-            "production result should be error {:?}",
-            bnf_error
-        );
-        match bnf_error.unwrap_err() {
+        match bnf_error {
             Error::ParseIncomplete(_) => (),
             e => panic!("production error should be incomplete: {:?}", e),
         }
@@ -7,7 +7,8 @@ use parsers;
 use error::Error;
 
 /// An Expression is comprised of any number of Terms
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Expression {
     terms: Vec<Term>,
 }
@@ -24,6 +25,7 @@ impl Expression {
     }
 
     // Get `Expression` by parsing a string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match parsers::expression_complete(s.as_bytes()) {
             IResult::Done(_, o) => Ok(o),
@@ -69,12 +71,12 @@ impl Expression {
     }
 
     /// Get iterator of `Term`s within `Expression`
-    pub fn terms_iter(&self) -> Iter {
+    pub fn terms_iter(&self) -> Iter<'_> {
         Iter { iterator: self.terms.iter() }
     }
 
     /// Get mutable iterator of `Term`s within `Expression`
-    pub fn terms_iter_mut(&mut self) -> IterMut {
+    pub fn terms_iter_mut(&mut self) -> IterMut<'_> {
         IterMut { iterator: self.terms.iter_mut() }
     }
 }
@@ -134,10 +136,10 @@ mod tests {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let mut terms = Vec::<Term>::arbitrary(g);
             // expressions must always have atleast one term
-            if terms.len() < 1 {
+            if terms.is_empty() {
                 terms.push(Term::arbitrary(g));
             }
-            Expression { terms: terms }
+            Expression { terms }
         }
     }
 
@@ -155,6 +157,21 @@ mod tests {
         QuickCheck::new().quickcheck(prop_to_string_and_back as fn(Expression) -> TestResult)
     }
 
+    #[cfg(feature = "serde")]
+    fn prop_serde_round_trip(expr: Expression) -> TestResult {
+        let json = ::serde_json::to_string(&expr).expect("expr should serialize");
+        match ::serde_json::from_str::<Expression>(&json) {
+            Ok(from_json) => TestResult::from_bool(from_json == expr),
+            Err(e) => TestResult::error(format!("{} to JSON and back should be safe: {}", json, e)),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        QuickCheck::new().quickcheck(prop_serde_round_trip as fn(Expression) -> TestResult)
+    }
+
     #[test]
     fn new_expressions() {
         let t1: Term = Term::Terminal(String::from("terminal"));
@@ -0,0 +1,855 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::vec;
+use rand::{thread_rng, Isaac64Rng, Rng, SeedableRng};
+use class::{Builtin, CharClass};
+use expression::Expression;
+use grammar::{Alternative, Grammar, Step};
+use parse_tree::{ParseTree, ParseTreeNode};
+use shortest::Shortest;
+use term::Term;
+use error::Error;
+
+/// Default for `Generator::max_expansions`
+pub const DEFAULT_MAX_EXPANSIONS: usize = 100_000;
+
+/// Default for `Generator::max_regex_length`
+pub const DEFAULT_MAX_REGEX_LENGTH: usize = 16;
+
+/// Default for `Generator::max_repetitions`
+pub const DEFAULT_MAX_REPETITIONS: usize = 4;
+
+/// Construct the rng unseeded generation uses, from `seed`.
+/// A given seed produces the same rng, and so the same generated output,
+/// on every platform.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate bnf;
+/// use bnf::{Generator, Grammar};
+///
+/// fn main() {
+///     let input =
+///         "<dna> ::= <base> | <base> <dna>
+///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+///     let grammar = Grammar::from_str(input).unwrap();
+///     let generator = Generator::new(&grammar);
+///     let seed = 1234;
+///     let tree = generator.generate_tree_seeded(&mut bnf::rng_from_seed(seed)).unwrap();
+///     let sentence = generator.generate_from_seed(seed).unwrap();
+///
+///     # assert_eq!(tree.flatten(), sentence);
+/// }
+/// ```
+pub fn rng_from_seed(seed: u64) -> Isaac64Rng {
+    Isaac64Rng::from_seed(&[seed])
+}
+
+/// Pick a seed for unseeded generation
+fn random_seed() -> u64 {
+    thread_rng().gen()
+}
+
+/// Sample a char of `alphabet` to generate for `term`
+fn sample<R: Rng>(rng: &mut R, term: &Term, alphabet: &CharClass) -> Result<String, Error> {
+    match alphabet.sample(rng) {
+        Some(c) => Ok(c.to_string()),
+        None => Err(Error::GenerateError(format!(
+            "{} has no char to generate",
+            term
+        ))),
+    }
+}
+
+/// Construct a randomly seeded rng for unseeded generation
+pub(crate) fn random_rng() -> Isaac64Rng {
+    rng_from_seed(random_seed())
+}
+
+/// A Generator produces random sentences from a `Grammar`.
+///
+/// Derivations are walked with an explicit work stack, so deep derivations
+/// do not depend on the thread's stack size. Instead, a derivation fails with
+/// `Error::RecursionLimit` once it expands more than `max_expansions`
+/// nonterminals, which stops productions such as `<PATTERN> ::= <PATTERN>`.
+///
+/// Alternatives which can never complete a sentence are not chosen while
+/// others can be. Once half of `max_expansions` is used, each nonterminal is
+/// expanded by the alternative beginning its lowest derivation, to finish
+/// the derivation within the limit.
+///
+/// Builtins such as `DIGIT` are generated by sampling a char of their
+/// alphabet, which is the class the builtin matches unless set otherwise in
+/// `alphabets`. So `ANY` generates printable ASCII by default, as negated
+/// classes do. Regex terminals are generated by a random walk of their
+/// automaton, bounded by `max_regex_length`; see `Dfa::sample`.
+///
+/// Groups and repeats are derived in place, so their terms become children
+/// of the derivation they appear in. A group is expanded like a production,
+/// and a repeat a number of times chosen uniformly within its bounds, where
+/// `*`, `+` and `{m,}` repeat at most `max_repetitions` times more than
+/// their minimum. Once half of `max_expansions` is used, groups are
+/// expanded by the alternative beginning their lowest derivation and
+/// repeats their minimum number of times.
+///
+/// If the grammar has a `Layout`, its whitespace nonterminal is derived
+/// between terms like any other, so generated whitespace is random too.
+///
+/// Constructing a Generator analyses the grammar for its lowest derivations
+/// and compiles its regex terminals, which the `generate` methods of
+/// `Grammar` do on every call. To generate many sentences, as when fuzzing,
+/// construct one Generator and reuse it.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate bnf;
+/// use bnf::{Generator, Grammar};
+///
+/// fn main() {
+///     let grammar = Grammar::from_str(
+///         "<dna> ::= <base> | <base> <dna>
+///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+///     ).unwrap();
+///     let generator = Generator::new(&grammar);
+///     for seed in 0..100 {
+///         let sentence = generator.generate_from_seed(seed).unwrap();
+///         # assert!(sentence.chars().all(|c| "ACGT".contains(c)));
+///     }
+/// }
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct Generator<'gram> {
+    grammar: &'gram Grammar,
+    shortest: Shortest<'gram>,
+    /// Maximum number of nonterminals a single derivation may expand
+    pub max_expansions: usize,
+    /// Chars sampled for each builtin. Sentences only parse if these are
+    /// within the chars each builtin matches.
+    pub alphabets: HashMap<Builtin, CharClass>,
+    /// Chars a regex terminal generates before finishing its match by the
+    /// fewest chars it can
+    pub max_regex_length: usize,
+    /// Repetitions an unbounded repeat makes beyond its minimum, at most
+    pub max_repetitions: usize,
+}
+
+/// An expansion in progress: the alternative expanded and its terms not yet
+/// derived. Groups and repeats have frames of their own, which are not
+/// expansions of a production, and share the layout of the production.
+struct Frame<'gram> {
+    alternative: Alternative,
+    terms: Terms<'gram>,
+    expansion: bool,
+    layout: Option<&'gram Term>,
+}
+
+/// The terms of a frame not yet derived
+enum Terms<'gram> {
+    Listed(vec::IntoIter<&'gram Term>),
+    /// A repeated term, with the repetitions and whitespace between them
+    /// still to derive counted rather than listed
+    Repeated(&'gram Term, Option<&'gram Term>, usize),
+}
+
+impl<'gram> Iterator for Terms<'gram> {
+    type Item = &'gram Term;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match *self {
+            Terms::Listed(ref mut terms) => terms.next(),
+            Terms::Repeated(_, _, 0) => None,
+            Terms::Repeated(term, whitespace, ref mut left) => {
+                *left -= 1;
+                match whitespace {
+                    Some(whitespace) if *left % 2 == 1 => Some(whitespace),
+                    _ => Some(term),
+                }
+            }
+        }
+    }
+}
+
+/// Terms to derive in turn for `terms`, separated by `layout` if any
+fn laid_out<'gram>(terms: Vec<&'gram Term>, layout: Option<&'gram Term>) -> Terms<'gram> {
+    match layout {
+        Some(whitespace) if terms.len() > 1 => {
+            let mut spaced = Vec::with_capacity(2 * terms.len() - 1);
+            for (i, term) in terms.into_iter().enumerate() {
+                if i > 0 {
+                    spaced.push(whitespace);
+                }
+                spaced.push(term);
+            }
+            Terms::Listed(spaced.into_iter())
+        }
+        _ => Terms::Listed(terms.into_iter()),
+    }
+}
+
+impl<'gram> Generator<'gram> {
+    /// Construct a `Generator` for `grammar` with default limits
+    pub fn new(grammar: &'gram Grammar) -> Generator<'gram> {
+        Generator {
+            grammar,
+            shortest: Shortest::new(grammar),
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+            alphabets: Builtin::all()
+                .iter()
+                .map(|&builtin| (builtin, builtin.class()))
+                .collect(),
+            max_regex_length: DEFAULT_MAX_REGEX_LENGTH,
+            max_repetitions: DEFAULT_MAX_REPETITIONS,
+        }
+    }
+
+    /// Get the chars sampled for `builtin`
+    pub fn alphabet(&self, builtin: Builtin) -> CharClass {
+        self.alphabets
+            .get(&builtin)
+            .cloned()
+            .unwrap_or_else(|| builtin.class())
+    }
+
+    /// Get the `Grammar` generated from
+    pub fn grammar(&self) -> &'gram Grammar {
+        self.grammar
+    }
+
+    /// Derive the nonterminal `start`, letting `choose` pick which alternative
+    /// of each production to expand, and reporting the derivation to `derivation`
+    pub(crate) fn derive_with<R, F, D>(
+        &self,
+        rng: &mut R,
+        start: &Term,
+        choose: &mut F,
+        derivation: &mut D,
+    ) -> Result<(), Error>
+    where
+        R: Rng,
+        F: FnMut(&mut R, Option<Alternative>, usize, usize) -> usize,
+        D: Derivation<'gram>,
+    {
+        let indices = self.grammar.production_indices();
+        if let Some(layout) = self.grammar.layout() {
+            if !indices.contains_key(&layout.whitespace) {
+                return Err(Error::GenerateError(format!(
+                    "No production found for {} to lay out sentences with!",
+                    layout.whitespace
+                )));
+            }
+        }
+        let mut expansions = 0;
+        let mut stack: Vec<Frame<'gram>> = vec![];
+        let mut next: Option<(&Term, Option<Alternative>)> = Some((start, None));
+
+        loop {
+            if let Some((nonterm, parent)) = next.take() {
+                expansions += 1;
+                if expansions > self.max_expansions {
+                    return Err(Error::RecursionLimit(format!(
+                        "Limit of {} expansions reached processing {}!",
+                        self.max_expansions, nonterm
+                    )));
+                }
+
+                let index = *indices.get(nonterm).ok_or_else(|| {
+                    Error::GenerateError(format!("No production found for {}!", nonterm))
+                })?;
+                let production = self.grammar.production(index);
+                let count = production.rhs_iter().count();
+                if count == 0 {
+                    return Err(Error::GenerateError(String::from(
+                        "Couldn't select random Expression!",
+                    )));
+                }
+
+                let alternative = match self.shortest.lowest(index) {
+                    Some(lowest) if expansions > self.max_expansions / 2 => (index, lowest),
+                    _ => (index, choose(rng, parent, index, count)),
+                };
+                let expression = production
+                    .rhs_iter()
+                    .nth(alternative.1)
+                    .expect("chosen alternative should be in production");
+
+                derivation.expand(parent, alternative, &production.lhs, expression)?;
+                let layout = self.grammar.layout_between(index);
+                stack.push(Frame {
+                    alternative,
+                    terms: laid_out(expression.terms_iter().collect(), layout),
+                    expansion: true,
+                    layout,
+                });
+            }
+
+            let (alternative, layout, term) = match stack.last_mut() {
+                Some(frame) => (frame.alternative, frame.layout, frame.terms.next()),
+                None => return Ok(()),
+            };
+            let finishing = expansions > self.max_expansions / 2;
+
+            match term {
+                None => {
+                    if let Some(Frame { expansion: true, .. }) = stack.pop() {
+                        derivation.complete()?;
+                    }
+                }
+                Some(term) => match *term {
+                    Term::Nonterminal(_) => match indices.get(term) {
+                        Some(_) => next = Some((term, Some(alternative))),
+                        None => derivation.terminal(&term.to_string())?,
+                    },
+                    Term::Terminal(ref t) => derivation.terminal(t)?,
+                    Term::Class(ref class) => derivation.terminal(&sample(rng, term, class)?)?,
+                    Term::Builtin(builtin) => {
+                        derivation.terminal(&sample(rng, term, &self.alphabet(builtin))?)?
+                    }
+                    Term::Regex(ref pattern) => {
+                        let dfa = self.shortest.regexes().dfa(pattern)?;
+                        match dfa.sample(rng, self.max_regex_length) {
+                            Some(text) => derivation.terminal(&text)?,
+                            None => {
+                                return Err(Error::GenerateError(format!(
+                                    "{} matches no text to generate",
+                                    term
+                                )))
+                            }
+                        }
+                    }
+                    Term::Group(ref alternatives) => {
+                        let e = match self.shortest.lowest_alternative(alternatives, layout) {
+                            Some(lowest) if finishing => lowest,
+                            _ => {
+                                let productive =
+                                    self.shortest.productive_alternatives(alternatives, layout);
+                                match productive[..] {
+                                    [] => rng.gen_range(0, alternatives.len()),
+                                    _ => productive[rng.gen_range(0, productive.len())],
+                                }
+                            }
+                        };
+                        stack.push(Frame {
+                            alternative,
+                            terms: laid_out(alternatives[e].terms_iter().collect(), layout),
+                            expansion: false,
+                            layout,
+                        });
+                    }
+                    Term::Repeat(ref repeated, min, max) => {
+                        let max = max.unwrap_or_else(|| min.saturating_add(self.max_repetitions));
+                        let count = if finishing {
+                            min
+                        } else {
+                            rng.gen_range(min, max.saturating_add(1))
+                        };
+                        stack.push(Frame {
+                            alternative,
+                            terms: Terms::Repeated(repeated, layout, match layout {
+                                Some(_) => count.saturating_mul(2).saturating_sub(1),
+                                None => count,
+                            }),
+                            expansion: false,
+                            layout,
+                        });
+                    }
+                },
+            }
+        }
+    }
+
+    /// The analysis of the grammar alternatives are chosen by
+    pub(crate) fn shortest(&self) -> &Shortest<'gram> {
+        &self.shortest
+    }
+
+    /// Choose uniformly among the alternatives of production `index` able to
+    /// complete a derivation, or among all `count` if none can
+    fn choose_uniform<R: Rng>(&self, rng: &mut R, index: usize, count: usize) -> usize {
+        match self.shortest.productive(index) {
+            [] => rng.gen_range(0, count),
+            productive => productive[rng.gen_range(0, productive.len())],
+        }
+    }
+
+    /// Derive `start`, choosing alternatives uniformly at random
+    fn derive_from<R, D>(&self, rng: &mut R, start: &Term, derivation: &mut D) -> Result<(), Error>
+    where
+        R: Rng,
+        D: Derivation<'gram>,
+    {
+        self.derive_with(
+            rng,
+            start,
+            &mut |rng, _, index, count| self.choose_uniform(rng, index, count),
+            derivation,
+        )
+    }
+
+    /// Derive the start rule, choosing alternatives uniformly at random
+    fn derive<R: Rng, D: Derivation<'gram>>(&self, rng: &mut R, derivation: &mut D) -> Result<(), Error> {
+        let start_rule = Term::Nonterminal(self.grammar.start_rule()?);
+        self.derive_from(rng, &start_rule, derivation)
+    }
+
+    /// Generate a random derivation of `nonterm`, which must have a production
+    pub(crate) fn generate_tree_from<R: Rng>(
+        &self,
+        rng: &mut R,
+        nonterm: &Term,
+    ) -> Result<ParseTree<'gram>, Error> {
+        let mut builder = TreeBuilder::new();
+        self.derive_from(rng, nonterm, &mut builder)?;
+        Ok(builder
+            .into_tree()
+            .expect("successful derivation should complete tree"))
+    }
+
+    /// Generate a random sentence from seed for random.
+    /// See `Grammar::generate_seeded`.
+    pub fn generate_seeded<R: Rng>(&self, rng: &mut R) -> Result<String, Error> {
+        let mut sentence = String::new();
+        self.generate_fmt_seeded(rng, &mut sentence, "")?;
+        Ok(sentence)
+    }
+
+    /// Generate a random sentence from the rng `rng_from_seed` gives for `seed`.
+    /// See `Grammar::generate_from_seed`.
+    pub fn generate_from_seed(&self, seed: u64) -> Result<String, Error> {
+        self.generate_seeded(&mut rng_from_seed(seed))
+    }
+
+    /// Generate a random sentence, returning the seed used alongside it.
+    /// See `Grammar::generate_reproducible`.
+    pub fn generate_reproducible(&self) -> (u64, Result<String, Error>) {
+        let seed = random_seed();
+        (seed, self.generate_from_seed(seed))
+    }
+
+    /// Generate a random sentence.
+    /// See `Grammar::generate`.
+    pub fn generate(&self) -> Result<String, Error> {
+        self.generate_from_seed(random_seed())
+    }
+
+    /// Generate a random derivation from seed for random.
+    /// See `Grammar::generate_tree_seeded`.
+    pub fn generate_tree_seeded<R: Rng>(&self, rng: &mut R) -> Result<ParseTree<'gram>, Error> {
+        let start_rule = Term::Nonterminal(self.grammar.start_rule()?);
+        self.generate_tree_from(rng, &start_rule)
+    }
+
+    /// Generate a random derivation.
+    /// See `Grammar::generate_tree`.
+    pub fn generate_tree(&self) -> Result<ParseTree<'gram>, Error> {
+        self.generate_tree_seeded(&mut random_rng())
+    }
+
+    /// Generate a random sentence from seed for random, writing it to `out`.
+    /// See `Grammar::generate_fmt_seeded`.
+    pub fn generate_fmt_seeded<R: Rng, W: fmt::Write>(
+        &self,
+        rng: &mut R,
+        out: &mut W,
+        separator: &str,
+    ) -> Result<(), Error> {
+        self.derive(rng, &mut Writer::new(out, separator))
+    }
+
+    /// Generate a random sentence, writing it to `out`.
+    /// See `Grammar::generate_fmt`.
+    pub fn generate_fmt<W: fmt::Write>(&self, out: &mut W, separator: &str) -> Result<(), Error> {
+        self.generate_fmt_seeded(&mut random_rng(), out, separator)
+    }
+
+    /// Generate a random sentence from seed for random, writing it to `out`.
+    /// See `Grammar::generate_io_seeded`.
+    pub fn generate_io_seeded<R: Rng, W: io::Write>(
+        &self,
+        rng: &mut R,
+        out: &mut W,
+        separator: &str,
+    ) -> Result<(), Error> {
+        let mut adapter = IoAdapter::new(out);
+        let result = self.generate_fmt_seeded(rng, &mut adapter, separator);
+        match adapter.error {
+            Some(e) => Err(Error::GenerateError(format!(
+                "Failed writing generated sentence: {}",
+                e
+            ))),
+            None => result,
+        }
+    }
+
+    /// Generate a random sentence, writing it to `out`.
+    /// See `Grammar::generate_io`.
+    pub fn generate_io<W: io::Write>(&self, out: &mut W, separator: &str) -> Result<(), Error> {
+        self.generate_io_seeded(&mut random_rng(), out, separator)
+    }
+}
+
+/// Receives a derivation as generation walks it, depth first and left to right
+pub(crate) trait Derivation<'gram> {
+    /// Expansion of `lhs` by the alternative `rhs` begins
+    fn expand(
+        &mut self,
+        _parent: Option<Alternative>,
+        _alternative: Alternative,
+        _lhs: &'gram Term,
+        _rhs: &'gram Expression,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The most recently begun expansion is complete
+    fn complete(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// A terminal is appended to the sentence
+    fn terminal(&mut self, text: &str) -> Result<(), Error>;
+}
+
+/// Builds the `ParseTree` of a derivation
+struct TreeBuilder<'gram> {
+    stack: Vec<ParseTree<'gram>>,
+    tree: Option<ParseTree<'gram>>,
+}
+
+impl<'gram> TreeBuilder<'gram> {
+    pub fn new() -> TreeBuilder<'gram> {
+        TreeBuilder {
+            stack: vec![],
+            tree: None,
+        }
+    }
+
+    /// Take the completed tree, if the derivation is complete
+    pub fn into_tree(self) -> Option<ParseTree<'gram>> {
+        self.tree
+    }
+}
+
+impl<'gram> Derivation<'gram> for TreeBuilder<'gram> {
+    fn expand(
+        &mut self,
+        _: Option<Alternative>,
+        _: Alternative,
+        lhs: &'gram Term,
+        rhs: &'gram Expression,
+    ) -> Result<(), Error> {
+        self.stack.push(ParseTree::new(lhs, rhs, vec![]));
+        Ok(())
+    }
+
+    fn complete(&mut self) -> Result<(), Error> {
+        let tree = self.stack.pop().expect("completed expansion should have begun");
+        match self.stack.last_mut() {
+            Some(parent) => parent.push_child(ParseTreeNode::Nonterminal(tree)),
+            None => self.tree = Some(tree),
+        }
+        Ok(())
+    }
+
+    fn terminal(&mut self, text: &str) -> Result<(), Error> {
+        let parent = self.stack.last_mut().expect("terminal should be within an expansion");
+        parent.push_child(ParseTreeNode::Terminal(String::from(text)));
+        Ok(())
+    }
+}
+
+/// Writes the sentence of a derivation, separating terminals by `separator`
+struct Writer<'w, W: fmt::Write + 'w> {
+    out: &'w mut W,
+    separator: &'w str,
+    started: bool,
+}
+
+impl<'w, W: fmt::Write> Writer<'w, W> {
+    pub fn new(out: &'w mut W, separator: &'w str) -> Writer<'w, W> {
+        Writer {
+            out,
+            separator,
+            started: false,
+        }
+    }
+}
+
+impl<'gram, 'w, W: fmt::Write> Derivation<'gram> for Writer<'w, W> {
+    fn terminal(&mut self, text: &str) -> Result<(), Error> {
+        // empty terminals contribute nothing, not even a separator
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        if self.started {
+            self.out.write_str(self.separator)?;
+        }
+        self.started = true;
+        self.out.write_str(text)?;
+        Ok(())
+    }
+}
+
+/// Records the choices made by a derivation alongside its sentence
+pub(crate) struct Tracer {
+    pub sentence: String,
+    pub trace: Vec<Step>,
+}
+
+impl Tracer {
+    pub fn new() -> Tracer {
+        Tracer {
+            sentence: String::new(),
+            trace: vec![],
+        }
+    }
+}
+
+impl<'gram> Derivation<'gram> for Tracer {
+    fn expand(
+        &mut self,
+        parent: Option<Alternative>,
+        alternative: Alternative,
+        _: &'gram Term,
+        _: &'gram Expression,
+    ) -> Result<(), Error> {
+        self.trace.push((parent, alternative));
+        Ok(())
+    }
+
+    fn terminal(&mut self, text: &str) -> Result<(), Error> {
+        self.sentence.push_str(text);
+        Ok(())
+    }
+}
+
+/// Adapts an `io::Write` to `fmt::Write`, keeping the underlying io error
+struct IoAdapter<'w, W: io::Write + 'w> {
+    inner: &'w mut W,
+    pub error: Option<io::Error>,
+}
+
+impl<'w, W: io::Write> IoAdapter<'w, W> {
+    pub fn new(inner: &'w mut W) -> IoAdapter<'w, W> {
+        IoAdapter { inner, error: None }
+    }
+}
+
+impl<'w, W: io::Write> fmt::Write for IoAdapter<'w, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.inner.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use automaton::Dfa;
+
+    #[test]
+    fn deep_derivation() {
+        // far deeper than a recursive generator could manage on a test thread
+        let grammar = Grammar::from_str("<chain> ::= \"x\" <chain> | \"\"").unwrap();
+        let mut generator = Generator::new(&grammar);
+        generator.max_expansions = usize::MAX;
+
+        let depth = 500_000;
+        let mut expansions = 0;
+        let mut choose = |_: &mut Isaac64Rng, _, _, _| {
+            expansions += 1;
+            if expansions < depth {
+                0
+            } else {
+                1
+            }
+        };
+
+        let mut sentence = String::new();
+        generator
+            .derive_with(
+                &mut random_rng(),
+                &Term::Nonterminal(String::from("chain")),
+                &mut choose,
+                &mut Writer::new(&mut sentence, ""),
+            )
+            .unwrap();
+        assert_eq!(sentence.len(), depth - 1);
+    }
+
+    #[test]
+    fn reproducible_from_seed() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        let generator = Generator::new(&grammar);
+
+        for _ in 0..10 {
+            let (seed, sentence) = generator.generate_reproducible();
+            assert_eq!(generator.generate_from_seed(seed), sentence);
+        }
+    }
+
+    #[test]
+    fn any_rng() {
+        use rand::XorShiftRng;
+
+        let grammar = Grammar::from_str("<start> ::= \"a\" | \"b\"").unwrap();
+        let mut rng: XorShiftRng = SeedableRng::from_seed([1, 2, 3, 4]);
+        let sentence = Generator::new(&grammar).generate_seeded(&mut rng).unwrap();
+        assert!(sentence == "a" || sentence == "b");
+    }
+
+    #[test]
+    fn expansion_limit() {
+        let grammar = Grammar::from_str("<start> ::= \"a\" <b>\n<b> ::= \"b\"").unwrap();
+        let mut generator = Generator::new(&grammar);
+        assert_eq!(generator.generate(), Ok(String::from("ab")));
+
+        generator.max_expansions = 1;
+        match generator.generate() {
+            Err(Error::RecursionLimit(_)) => (),
+            r => panic!("should be Error::RecursionLimit: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn finishes_within_limit() {
+        // uniform choices would expand three <tree>s for every two finished
+        let grammar = Grammar::from_str("<tree> ::= <tree> <tree> <tree> | \"x\"").unwrap();
+        let mut generator = Generator::new(&grammar);
+        generator.max_expansions = 1_000;
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert_eq!(sentence.len() % 2, 1);
+        }
+    }
+
+    #[test]
+    fn avoids_unproductive_alternatives() {
+        let grammar = Grammar::from_str("<s> ::= <loop> | \"a\" | <s> <loop>\n<loop> ::= <loop>").unwrap();
+        let generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            assert_eq!(generator.generate_from_seed(seed), Ok(String::from("a")));
+        }
+    }
+
+    #[test]
+    fn generate_classes() {
+        let grammar = Grammar::from_str("<hex> ::= [0-9a-f] | [0-9a-f] <hex>").unwrap();
+        let generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(sentence.chars().all(|c| c.is_ascii_hexdigit() && !c.is_uppercase()));
+            assert!(grammar.recognizes(&sentence));
+        }
+        let empty = Grammar::from_str("<s> ::= \"a\" []").unwrap();
+        assert!(empty.generate().is_err());
+    }
+
+    #[test]
+    fn generate_builtins() {
+        let grammar = Grammar::from_str(
+            "<line> ::= <word> NEWLINE | <word> WHITESPACE <line>
+            <word> ::= ALPHA | DIGIT | ANY <word>",
+        ).unwrap();
+        let mut generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(sentence.ends_with('\n'));
+            assert!(grammar.recognizes(&sentence));
+        }
+
+        generator
+            .alphabets
+            .insert(Builtin::Whitespace, CharClass::new(vec![(' ', ' ')], false));
+        generator
+            .alphabets
+            .insert(Builtin::Any, CharClass::new(vec![('x', 'z')], false));
+        assert_eq!(generator.alphabet(Builtin::Digit), Builtin::Digit.class());
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(!sentence.contains(['\t', '\r']));
+            assert!(grammar.recognizes(&sentence));
+        }
+
+        generator.alphabets.insert(Builtin::Newline, CharClass::new(vec![], false));
+        assert!(generator.generate().is_err());
+    }
+
+    #[test]
+    fn generate_regexes() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <number> | <number> \",\" <list>
+            <number> ::= /-?[0-9]+(\\.[0-9]+)?/",
+        ).unwrap();
+        let number = Dfa::from_regex("-?[0-9]+(\\.[0-9]+)?").unwrap();
+        let mut generator = Generator::new(&grammar);
+        generator.max_regex_length = 4;
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(grammar.recognizes(&sentence));
+            for item in sentence.split(',') {
+                assert!(number.accepts(item));
+                assert!(item.len() <= 6, "{}", item);
+            }
+        }
+
+        let nothing = Grammar::from_str("<s> ::= \"a\" /[]/").unwrap();
+        assert!(nothing.generate().is_err());
+    }
+
+    #[test]
+    fn generate_groups_and_repeats() {
+        let grammar = Grammar::from_str(
+            "<s> ::= (\"a\" | \"b\" <s>)* \"c\"{2} \"d\"{1,3}",
+        ).unwrap();
+        let mut generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let tree = generator.generate_tree_seeded(&mut rng_from_seed(seed)).unwrap();
+            let sentence = tree.flatten();
+            assert!(grammar.recognizes(&sentence), "{}", sentence);
+        }
+
+        generator.max_repetitions = 0;
+        for seed in 0..5 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(sentence.starts_with("ccd") && sentence.len() <= 5, "{}", sentence);
+        }
+
+        // derivations finish by repeating as few times as allowed
+        generator.max_expansions = 1;
+        generator.max_repetitions = 100;
+        assert_eq!(generator.generate_from_seed(0), Ok(String::from("ccd")));
+    }
+
+    #[test]
+    fn writer_separates_nonempty_terminals() {
+        let mut out = String::new();
+        {
+            let mut writer = Writer::new(&mut out, " ");
+            for t in ["", "a", "", "b", "c", ""].iter() {
+                Derivation::terminal(&mut writer, t).unwrap();
+            }
+        }
+        assert_eq!(out, "a b c");
+    }
+
+    #[test]
+    fn io_adapter_keeps_error() {
+        use std::fmt::Write;
+
+        let mut buffer = [0u8; 2];
+        let mut cursor = io::Cursor::new(&mut buffer[..]);
+        let mut adapter = IoAdapter::new(&mut cursor);
+        assert!(adapter.write_str("ab").is_ok());
+        assert!(adapter.write_str("c").is_err());
+        assert!(adapter.error.is_some());
+    }
+}
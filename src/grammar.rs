@@ -1,33 +1,54 @@
 use std::fmt;
 use std::str;
 use std::slice;
+use std::io;
+use std::collections::HashMap;
 use nom::IResult;
-use expression::Expression;
 use production::Production;
+use layout::Layout;
 use term::Term;
+use parse_tree::ParseTree;
+use generate::Generator;
 use parsers;
 use error::Error;
-use rand::{Rng, SeedableRng, StdRng, thread_rng};
-use stacker;
+use rand::Rng;
+
+/// Position of an `Expression` alternative, as indices of its `Production`
+/// within the `Grammar` and of the `Expression` within the `Production`
+pub(crate) type Alternative = (usize, usize);
+
+/// A choice made during generation: the alternative expanded, and the
+/// alternative whose expansion required it
+pub(crate) type Step = (Option<Alternative>, Alternative);
 
 /// A Grammar is comprised of any number of Productions
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Grammar {
     productions: Vec<Production>,
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub(crate) layout: Option<Layout>,
 }
 
 impl Grammar {
     /// Construct a new `Grammar`
     pub fn new() -> Grammar {
-        Grammar { productions: vec![] }
+        Grammar {
+            productions: vec![],
+            layout: None,
+        }
     }
 
     /// Construct an `Grammar` from `Production`s
     pub fn from_parts(v: Vec<Production>) -> Grammar {
-        Grammar { productions: v }
+        Grammar {
+            productions: v,
+            layout: None,
+        }
     }
 
     // Get `Grammar` by parsing a string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match parsers::grammar_complete(s.as_bytes()) {
             IResult::Done(_, o) => Ok(o),
@@ -51,68 +72,62 @@ impl Grammar {
     }
 
     /// Get iterator of the `Grammar`'s `Production`s
-    pub fn productions_iter(&self) -> Iter {
+    pub fn productions_iter(&self) -> Iter<'_> {
         Iter { iterator: self.productions.iter() }
     }
 
     /// Get mutable iterator of the `Grammar`'s `Production`s
-    pub fn productions_iter_mut(&mut self) -> IterMut {
+    pub fn productions_iter_mut(&mut self) -> IterMut<'_> {
         IterMut { iterator: self.productions.iter_mut() }
     }
 
-    fn eval_terminal(&self, term: &Term, rng: &mut StdRng) -> Result<String, Error> {
-        match *term {
-            Term::Nonterminal(ref nt) => self.traverse(&nt, rng),
-            Term::Terminal(ref t) => Ok(t.clone()),
-        }
+    /// Find the index of the `Production` used to expand the given nonterminal
+    pub(crate) fn production_index(&self, nonterm: &Term) -> Option<usize> {
+        self.productions.iter().position(|p| p.lhs == *nonterm)
     }
 
-    fn traverse(&self, ident: &String, rng: &mut StdRng) -> Result<String, Error> {
-        let stack_red_zone: usize = 32 * 1024; // 32KB
-        // heavy recursion happening, we've hit out tolerable threshold
-        if stacker::remaining_stack() < stack_red_zone {
-            return Err(Error::RecursionLimit(format!(
-                "Limit for recursion reached processing <{}>!",
-                ident
-            )));
+    /// Map each nonterminal to the index of the `Production` used to expand it
+    pub(crate) fn production_indices(&self) -> HashMap<&Term, usize> {
+        let mut indices = HashMap::new();
+        for (i, production) in self.productions.iter().enumerate() {
+            indices.entry(&production.lhs).or_insert(i);
         }
+        indices
+    }
 
-        let nonterm = Term::Nonterminal(ident.clone());
-        let production;
-        let find_lhs = self.productions_iter().find(|&x| x.lhs == nonterm);
-
-        match find_lhs {
-            Some(p) => production = p,
-            None => return Ok(nonterm.to_string()),
-        }
-
-        let expression;
-        let expressions = production.rhs_iter().collect::<Vec<&Expression>>();
-
-        match rng.choose(&expressions) {
-            Some(e) => expression = e.clone(),
-            None => {
-                return Err(Error::GenerateError(
-                    String::from("Couldn't select random Expression!"),
-                ));
-            }
-        }
+    /// Get the `Production` at `index`, as found by `production_index`
+    pub(crate) fn production(&self, index: usize) -> &Production {
+        &self.productions[index]
+    }
 
-        let mut result = String::new();
-        for term in expression.terms_iter() {
-            match self.eval_terminal(&term, rng) {
-                Ok(s) => result = result + &s,
-                Err(e) => return Err(e),
-            }
+    /// Get the nonterminal generation begins from, the lhs of first production
+    pub(crate) fn start_rule(&self) -> Result<String, Error> {
+        match self.productions.first() {
+            Some(prod) => match prod.lhs {
+                Term::Nonterminal(ref nt) => Ok(nt.clone()),
+                _ => Err(Error::GenerateError(format!(
+                    "Termainal type cannot define a production in '{}'!",
+                    prod
+                ))),
+            },
+            None => Err(Error::GenerateError(String::from(
+                "Failed to get first production!",
+            ))),
         }
-
-        return Ok(result);
     }
 
     /// Generate a random sentence from self and seed for random.
     /// Use if interested in reproducing the output generated.
     /// Begins from lhs of first production.
     ///
+    /// Past half of `DEFAULT_MAX_EXPANSIONS` expanded nonterminals, the derivation
+    /// is finished by the lowest derivations of `minimal_derivations`. Fails with
+    /// `Error::RecursionLimit` if it still expands more, as when no sentence can
+    /// be derived at all; use a `Generator` to change the limit.
+    ///
+    /// Each call analyses the grammar anew; see `Generator` on generating many
+    /// sentences.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -138,33 +153,14 @@ impl Grammar {
     ///     # assert!(sentence_clone.is_ok());
     /// }
     /// ```
-    pub fn generate_seeded(&self, rng: &mut StdRng) -> Result<String, Error> {
-        let start_rule: String;
-        let first_production = self.productions_iter().nth(0);
-
-        match first_production {
-            Some(term) => {
-                match term.lhs {
-                    Term::Nonterminal(ref nt) => start_rule = nt.clone(),
-                    Term::Terminal(_) => {
-                        return Err(Error::GenerateError(format!(
-                            "Termainal type cannot define a production in '{}'!",
-                            term
-                        )));
-                    }
-                }
-            }
-            None => {
-                return Err(Error::GenerateError(
-                    String::from("Failed to get first production!"),
-                ));
-            }
-        }
-        self.traverse(&start_rule, rng)
+    pub fn generate_seeded<R: Rng>(&self, rng: &mut R) -> Result<String, Error> {
+        Generator::new(self).generate_seeded(rng)
     }
 
     /// Generate a random sentence from self.
     /// Begins from lhs of first production.
+    /// Use `generate_reproducible` to also learn the seed of the sentence.
+    /// See `generate_seeded` for details.
     ///
     /// # Example
     ///
@@ -188,21 +184,189 @@ impl Grammar {
     /// }
     /// ```
     pub fn generate(&self) -> Result<String, Error> {
-        // let seed: &[_] = &[1, 2, 3, 4];
-        let seed: Vec<usize> = thread_rng()
-            .gen_iter::<usize>()
-            .take(1000)
-            .collect::<Vec<usize>>();
-        let mut rng: StdRng = SeedableRng::from_seed(&seed[..]);
-        self.generate_seeded(&mut rng)
+        Generator::new(self).generate()
+    }
+
+    /// Generate a random sentence from self, using the rng `rng_from_seed`
+    /// constructs from `seed`. The same seed always generates the same sentence.
+    /// Begins from lhs of first production.
+    pub fn generate_from_seed(&self, seed: u64) -> Result<String, Error> {
+        Generator::new(self).generate_from_seed(seed)
+    }
+
+    /// Generate a random sentence from self, returning the seed used so the
+    /// sentence can be reproduced with `generate_from_seed`.
+    /// Begins from lhs of first production.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let (seed, sentence) = grammar.generate_reproducible();
+    ///     match sentence {
+    ///         Ok(ref s) => println!("random sentence from seed {}: {}", seed, s),
+    ///         Err(ref e) => println!("something went wrong with seed {}: {}!", seed, e)
+    ///     }
+    ///
+    ///     # assert_eq!(grammar.generate_from_seed(seed), sentence);
+    /// }
+    /// ```
+    pub fn generate_reproducible(&self) -> (u64, Result<String, Error>) {
+        Generator::new(self).generate_reproducible()
+    }
+
+    /// Generate a random derivation from self and seed for random.
+    /// The sentence derived is that of `generate_seeded` for the same seed,
+    /// and can be had with `ParseTree::flatten`.
+    /// Begins from lhs of first production.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// extern crate rand;
+    /// use rand::{SeedableRng, StdRng};
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let seed: &[_] = &[1,2,3,4];
+    ///     let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     let tree = grammar.generate_tree_seeded(&mut rng);
+    ///     match tree {
+    ///         Ok(t) => println!("random sentence {} derived by:\n{}", t.flatten(), t),
+    ///         Err(e) => println!("something went wrong: {}!", e)
+    ///     }
+    ///
+    ///     # let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     # let tree = grammar.generate_tree_seeded(&mut rng).unwrap();
+    ///     # let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     # assert_eq!(tree.flatten(), grammar.generate_seeded(&mut rng).unwrap());
+    /// }
+    /// ```
+    pub fn generate_tree_seeded<R: Rng>(&self, rng: &mut R) -> Result<ParseTree<'_>, Error> {
+        Generator::new(self).generate_tree_seeded(rng)
+    }
+
+    /// Generate a random derivation from self.
+    /// Begins from lhs of first production.
+    pub fn generate_tree(&self) -> Result<ParseTree<'_>, Error> {
+        Generator::new(self).generate_tree()
+    }
+
+    /// Generate a random sentence from self and seed for random, writing it
+    /// to `out` as it is generated rather than building it in memory.
+    /// Consecutive non-empty terminals are separated by `separator`, pass
+    /// `""` to concatenate them as `generate_seeded` does.
+    /// Begins from lhs of first production.
+    ///
+    /// If generation fails, `out` is left holding the part of the sentence
+    /// generated before the failure.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// extern crate rand;
+    /// use rand::{SeedableRng, StdRng};
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let seed: &[_] = &[1,2,3,4];
+    ///     let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     let mut tokens = String::new();
+    ///     let result = grammar.generate_fmt_seeded(&mut rng, &mut tokens, " ");
+    ///     match result {
+    ///         Ok(()) => println!("random bases: {}", tokens),
+    ///         Err(e) => println!("something went wrong: {}!", e)
+    ///     }
+    ///
+    ///     # assert!(tokens.split(' ').all(|base| base.len() == 1));
+    /// }
+    /// ```
+    pub fn generate_fmt_seeded<R: Rng, W: fmt::Write>(
+        &self,
+        rng: &mut R,
+        out: &mut W,
+        separator: &str,
+    ) -> Result<(), Error> {
+        Generator::new(self).generate_fmt_seeded(rng, out, separator)
+    }
+
+    /// Generate a random sentence from self, writing it to `out`.
+    /// See `generate_fmt_seeded` for details.
+    pub fn generate_fmt<W: fmt::Write>(&self, out: &mut W, separator: &str) -> Result<(), Error> {
+        Generator::new(self).generate_fmt(out, separator)
+    }
+
+    /// Generate a random sentence from self and seed for random, writing it
+    /// to `out` as it is generated. Consecutive non-empty terminals are
+    /// separated by `separator`, pass `""` to concatenate them.
+    /// Begins from lhs of first production.
+    ///
+    /// Terminals are written individually, so wrap unbuffered writers such as
+    /// files in a `std::io::BufWriter`. If generation fails, `out` is left
+    /// holding the part of the sentence generated before the failure.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// extern crate rand;
+    /// use std::io::Write;
+    /// use rand::{SeedableRng, StdRng};
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let seed: &[_] = &[1,2,3,4];
+    ///     let mut rng: StdRng = SeedableRng::from_seed(seed);
+    ///     let stdout = std::io::stdout();
+    ///     let mut out = stdout.lock();
+    ///     let result = grammar.generate_io_seeded(&mut rng, &mut out, "");
+    ///     writeln!(out).unwrap();
+    ///
+    ///     # assert!(result.is_ok());
+    /// }
+    /// ```
+    pub fn generate_io_seeded<R: Rng, W: io::Write>(
+        &self,
+        rng: &mut R,
+        out: &mut W,
+        separator: &str,
+    ) -> Result<(), Error> {
+        Generator::new(self).generate_io_seeded(rng, out, separator)
+    }
+
+    /// Generate a random sentence from self, writing it to `out`.
+    /// See `generate_io_seeded` for details.
+    pub fn generate_io<W: io::Write>(&self, out: &mut W, separator: &str) -> Result<(), Error> {
+        Generator::new(self).generate_io(out, separator)
     }
 }
 
 impl fmt::Display for Grammar {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
+        writeln!(
             f,
-            "{}\n",
+            "{}",
             self.productions
                 .iter()
                 .map(|s| s.to_string())
@@ -253,16 +417,21 @@ mod tests {
     use term::Term;
     use expression::Expression;
     use production::Production;
+    use parse_tree::ParseTreeNode;
     use self::quickcheck::{Arbitrary, Gen, QuickCheck, StdGen, TestResult};
+    use rand::{SeedableRng, StdRng};
 
     impl Arbitrary for Grammar {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let mut productions = Vec::<Production>::arbitrary(g);
             // grammar must always have atleast one production
-            if productions.len() < 1 {
+            if productions.is_empty() {
                 productions.push(Production::arbitrary(g));
             }
-            Grammar { productions: productions }
+            Grammar {
+                productions,
+                layout: None,
+            }
         }
     }
 
@@ -283,6 +452,24 @@ mod tests {
             .quickcheck(prop_to_string_and_back as fn(Grammar) -> TestResult)
     }
 
+    #[cfg(feature = "serde")]
+    fn prop_serde_round_trip(gram: Grammar) -> TestResult {
+        let json = ::serde_json::to_string(&gram).expect("gram should serialize");
+        match ::serde_json::from_str::<Grammar>(&json) {
+            Ok(from_json) => TestResult::from_bool(from_json == gram),
+            Err(e) => TestResult::error(format!("{} to JSON and back should be safe: {}", json, e)),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        QuickCheck::new()
+            .tests(1000)
+            .gen(StdGen::new(rand::thread_rng(), 12usize))
+            .quickcheck(prop_serde_round_trip as fn(Grammar) -> TestResult)
+    }
+
     #[test]
     fn new_grammars() {
         let lhs1: Term = Term::Nonterminal(String::from("STRING A"));
@@ -441,6 +628,84 @@ mod tests {
         assert_eq!(sentence.unwrap(), String::from("<not-used>"));
     }
 
+    #[test]
+    fn tree_matches_sentence() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap();
+        let seed: &[_] = &[4, 3, 2, 1];
+
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let tree = grammar.generate_tree_seeded(&mut rng).unwrap();
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let sentence = grammar.generate_seeded(&mut rng).unwrap();
+        assert_eq!(tree.flatten(), sentence);
+
+        // every node is expanded by an alternative of its own production
+        fn check(grammar: &Grammar, tree: &ParseTree) {
+            let production = grammar
+                .productions_iter()
+                .find(|p| p.lhs == *tree.lhs)
+                .unwrap();
+            assert!(production.rhs_iter().any(|e| e == tree.rhs));
+            assert_eq!(tree.children_iter().count(), tree.rhs.terms_iter().count());
+            for child in tree.children_iter() {
+                if let ParseTreeNode::Nonterminal(ref child) = *child {
+                    check(grammar, child);
+                }
+            }
+        }
+        check(&grammar, &tree);
+    }
+
+    #[test]
+    fn streamed_sentences_match() {
+        let grammar = Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\" | \"\"",
+        ).unwrap();
+        let seed: &[_] = &[5, 6, 7, 8];
+
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let sentence = grammar.generate_seeded(&mut rng).unwrap();
+
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut bytes: Vec<u8> = vec![];
+        grammar.generate_io_seeded(&mut rng, &mut bytes, "").unwrap();
+        assert_eq!(String::from_utf8(bytes).unwrap(), sentence);
+
+        // separators go between non-empty terminals only
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut separated = String::new();
+        grammar.generate_fmt_seeded(&mut rng, &mut separated, " ").unwrap();
+        assert_eq!(separated.replace(' ', ""), sentence);
+        assert!(!separated.starts_with(' ') && !separated.ends_with(' '));
+        assert!(!separated.contains("  "), "{:?}", separated);
+    }
+
+    #[test]
+    fn streamed_write_error() {
+        let grammar = Grammar::from_str("<start> ::= \"too long\"").unwrap();
+        let mut buffer = [0u8; 3];
+        let mut out = io::Cursor::new(&mut buffer[..]);
+        let result = grammar.generate_io(&mut out, "");
+        match result {
+            Err(Error::GenerateError(_)) => (),
+            r => panic!("should be Error::GenerateError: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn tree_lhs_not_found() {
+        let grammar = Grammar::from_str("<start> ::= <not-used>").unwrap();
+        let tree = grammar.generate_tree().unwrap();
+        assert_eq!(
+            tree.children_iter().collect::<Vec<_>>(),
+            vec![&ParseTreeNode::Terminal(String::from("<not-used>"))]
+        );
+    }
+
     #[test]
     fn lhs_is_terminal_parse() {
         let grammar = Grammar::from_str("\"wrong place\" ::= <not-used>");
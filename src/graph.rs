@@ -0,0 +1,236 @@
+use grammar::Grammar;
+use term::Term;
+
+/// A DependencyGraph is the graph of which nonterminals of a `Grammar` use
+/// which: a vertex for each `Production`, by its index within the grammar,
+/// with an edge to each production expanding a nonterminal of its
+/// alternatives, groups and repeats included
+#[derive(PartialEq, Debug, Clone)]
+pub struct DependencyGraph<'gram> {
+    nonterminals: Vec<&'gram Term>,
+    adjacency: Vec<Vec<usize>>,
+    components: Vec<Vec<usize>>,
+    component: Vec<usize>,
+}
+
+impl<'gram> DependencyGraph<'gram> {
+    /// Construct the `DependencyGraph` of `grammar`
+    pub fn new(grammar: &'gram Grammar) -> DependencyGraph<'gram> {
+        let indices = grammar.production_indices();
+        let nonterminals = grammar
+            .productions_iter()
+            .map(|production| &production.lhs)
+            .collect::<Vec<_>>();
+        let adjacency = grammar
+            .productions_iter()
+            .map(|production| {
+                let mut successors = production
+                    .rhs_iter()
+                    .flat_map(|rhs| rhs.terms_iter())
+                    .flat_map(|term| term.leaves())
+                    .filter_map(|term| indices.get(term).cloned())
+                    .collect::<Vec<_>>();
+                successors.sort();
+                successors.dedup();
+                successors
+            })
+            .collect::<Vec<_>>();
+        let components = strongly_connected(&adjacency);
+        let mut component = vec![0; adjacency.len()];
+        for (c, members) in components.iter().enumerate() {
+            for &member in members {
+                component[member] = c;
+            }
+        }
+        DependencyGraph {
+            nonterminals,
+            adjacency,
+            components,
+            component,
+        }
+    }
+
+    /// Get the nonterminal of each vertex, the lhs of its production
+    pub fn nonterminals(&self) -> &[&'gram Term] {
+        &self.nonterminals
+    }
+
+    /// Get the vertices each vertex has an edge to, in ascending order
+    pub fn adjacency(&self) -> &[Vec<usize>] {
+        &self.adjacency
+    }
+
+    /// Get the strongly connected components of the graph in reverse
+    /// topological order, each vertex of a component reaching only
+    /// vertices of itself and of earlier components
+    pub fn components(&self) -> &[Vec<usize>] {
+        &self.components
+    }
+
+    /// Get the index within `components` of the component of `vertex`
+    pub fn component(&self, vertex: usize) -> usize {
+        self.component[vertex]
+    }
+
+    /// Whether the nonterminal of `vertex` may derive itself, so lies on a
+    /// cycle of the graph
+    pub fn is_recursive(&self, vertex: usize) -> bool {
+        self.components[self.component[vertex]].len() > 1
+            || self.adjacency[vertex].contains(&vertex)
+    }
+
+    /// Whether each vertex is reachable from the start rule, the first
+    /// vertex. Nonterminals which are not are never expanded.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.adjacency.len()];
+        let mut pending = if reachable.is_empty() { vec![] } else { vec![0] };
+        while let Some(vertex) = pending.pop() {
+            if reachable[vertex] {
+                continue;
+            }
+            reachable[vertex] = true;
+            pending.extend(self.adjacency[vertex].iter().filter(|&&v| !reachable[v]));
+        }
+        reachable
+    }
+}
+
+impl Grammar {
+    /// Get the `DependencyGraph` of the grammar's nonterminals
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "<expr> ::= <term> | <term> \"+\" <expr>
+    ///         <term> ::= DIGIT+ | \"(\" <expr> \")\"
+    ///         <unused> ::= <term>",
+    ///     ).unwrap();
+    ///     let graph = grammar.dependency_graph();
+    ///
+    ///     assert_eq!(graph.adjacency(), &[vec![0, 1], vec![0], vec![1]]);
+    ///     assert!(graph.is_recursive(0) && graph.is_recursive(1));
+    ///     assert!(!graph.is_recursive(2));
+    ///     assert_eq!(graph.reachable(), vec![true, true, false]);
+    /// }
+    /// ```
+    pub fn dependency_graph(&self) -> DependencyGraph<'_> {
+        DependencyGraph::new(self)
+    }
+}
+
+/// Find the strongly connected components of the graph with the given
+/// adjacency lists, by Tarjan's algorithm. Components are returned in
+/// reverse topological order, each vertex of a component reaching only
+/// vertices of itself and of earlier components.
+pub(crate) fn strongly_connected(adjacency: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let count = adjacency.len();
+    let mut index = vec![None; count];
+    let mut low = vec![0; count];
+    let mut on_stack = vec![false; count];
+    let mut stack = vec![];
+    let mut components = vec![];
+    let mut next = 0;
+
+    for root in 0..count {
+        if index[root].is_some() {
+            continue;
+        }
+        // vertices being visited, with how many of their edges are explored
+        let mut visiting = vec![(root, 0)];
+        index[root] = Some(next);
+        low[root] = next;
+        next += 1;
+        stack.push(root);
+        on_stack[root] = true;
+
+        while let Some(&mut (vertex, ref mut edge)) = visiting.last_mut() {
+            if let Some(&successor) = adjacency[vertex].get(*edge) {
+                *edge += 1;
+                match index[successor] {
+                    None => {
+                        index[successor] = Some(next);
+                        low[successor] = next;
+                        next += 1;
+                        stack.push(successor);
+                        on_stack[successor] = true;
+                        visiting.push((successor, 0));
+                    }
+                    Some(i) if on_stack[successor] => low[vertex] = low[vertex].min(i),
+                    Some(_) => (),
+                }
+                continue;
+            }
+
+            visiting.pop();
+            if let Some(&(parent, _)) = visiting.last() {
+                low[parent] = low[parent].min(low[vertex]);
+            }
+            if Some(low[vertex]) == index[vertex] {
+                let mut component = vec![];
+                loop {
+                    let member = stack.pop().expect("component should be on stack");
+                    on_stack[member] = false;
+                    component.push(member);
+                    if member == vertex {
+                        break;
+                    }
+                }
+                components.push(component);
+            }
+        }
+    }
+
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components() {
+        let adjacency = vec![vec![1], vec![2, 3], vec![0], vec![4], vec![], vec![5]];
+        let mut components = strongly_connected(&adjacency);
+        for component in &mut components {
+            component.sort();
+        }
+        assert_eq!(components, vec![vec![4], vec![3], vec![0, 1, 2], vec![5]]);
+    }
+
+    #[test]
+    fn deep_chain() {
+        let count = 100_000;
+        let adjacency = (0..count)
+            .map(|v| if v + 1 < count { vec![v + 1] } else { vec![0] })
+            .collect::<Vec<_>>();
+        assert_eq!(strongly_connected(&adjacency).len(), 1);
+    }
+
+    #[test]
+    fn dependency_graph() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <a> (<b> | \"x\")* <undefined>
+            <a> ::= <a> \"a\" | \"\"
+            <b> ::= <c>
+            <c> ::= <b> <a>
+            <d> ::= <s>",
+        ).unwrap();
+        let graph = grammar.dependency_graph();
+        let names = graph.nonterminals().iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(names, ["<s>", "<a>", "<b>", "<c>", "<d>"]);
+        assert_eq!(graph.adjacency(), &[vec![1, 2], vec![1], vec![3], vec![1, 2], vec![0]]);
+
+        let recursive = (0..5).map(|v| graph.is_recursive(v)).collect::<Vec<_>>();
+        assert_eq!(recursive, [false, true, true, true, false]);
+        assert_eq!(graph.component(2), graph.component(3));
+        assert!(graph.component(1) < graph.component(2));
+        assert!(graph.component(2) < graph.component(0));
+        assert_eq!(graph.reachable(), [true, true, true, true, false]);
+        assert!(Grammar::new().dependency_graph().reachable().is_empty());
+    }
+}
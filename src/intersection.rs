@@ -0,0 +1,456 @@
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use automaton::{Dfa, Nfa};
+use class::{intersect, CharClass, CharRange};
+use error::Error;
+use expression::Expression;
+use grammar::Grammar;
+use production::Production;
+use regex::Regexes;
+use term::Term;
+
+/// A nonterminal of the intersection: a production, deriving sentences
+/// which take the automaton from one state to another
+type Triple = (usize, usize, usize);
+
+/// The Bar-Hillel construction of a `Grammar` intersected with a `Dfa`
+struct Intersection<'gram, 'dfa> {
+    grammar: &'gram Grammar,
+    dfa: &'dfa Dfa,
+    indices: HashMap<&'gram Term, usize>,
+    /// Per production and state, the states a sentence of the production
+    /// reaches from it
+    reach: Vec<Vec<BTreeSet<usize>>>,
+    names: HashMap<Triple, Term>,
+    /// Names of nonterminals, to name those of the intersection apart
+    taken: HashSet<String>,
+    regexes: Regexes,
+}
+
+impl<'gram, 'dfa> Intersection<'gram, 'dfa> {
+    fn new(grammar: &'gram Grammar, dfa: &'dfa Dfa) -> Intersection<'gram, 'dfa> {
+        let taken = grammar
+            .productions_iter()
+            .flat_map(|production| {
+                Some(&production.lhs)
+                    .into_iter()
+                    .chain(production.rhs_iter().flat_map(|rhs| rhs.terms_iter()))
+            })
+            .filter_map(|term| match *term {
+                Term::Nonterminal(ref name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+        let mut intersection = Intersection {
+            grammar,
+            dfa,
+            indices: grammar.production_indices(),
+            reach: vec![
+                vec![BTreeSet::new(); dfa.state_count()];
+                grammar.productions_iter().count()
+            ],
+            names: HashMap::new(),
+            taken,
+            regexes: Regexes::new(grammar),
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, production) in grammar.productions_iter().enumerate() {
+                if intersection.indices[&production.lhs] != index {
+                    continue;
+                }
+                for from in 0..dfa.state_count() {
+                    let mut reached = BTreeSet::new();
+                    for rhs in production.rhs_iter() {
+                        let mut states = vec![from];
+                        for term in rhs.terms_iter() {
+                            states = states
+                                .into_iter()
+                                .flat_map(|state| intersection.ends(term, state))
+                                .collect::<BTreeSet<_>>()
+                                .into_iter()
+                                .collect();
+                        }
+                        reached.extend(states);
+                    }
+                    if !reached.is_subset(&intersection.reach[index][from]) {
+                        intersection.reach[index][from].extend(reached);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        intersection
+    }
+
+    /// The states a sentence of `term` reaches from `state`
+    fn ends(&self, term: &Term, state: usize) -> Vec<usize> {
+        if let Some(&n) = self.indices.get(term) {
+            return self.reach[n][state].iter().cloned().collect();
+        }
+        if let Term::Regex(ref pattern) = *term {
+            let regex = match self.regexes.dfa(pattern) {
+                Ok(regex) => regex,
+                Err(_) => return vec![],
+            };
+            let (_, pairs) = self.product(regex, state);
+            return pairs
+                .into_iter()
+                .filter(|&(r, _)| regex.is_accepting(r))
+                .map(|(_, to)| to)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect();
+        }
+        if let Some(class) = term.class() {
+            return self.dfa
+                .transitions(state)
+                .iter()
+                .map(|&(_, _, to)| to)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .filter(|&to| !self.between(&class, state, to).is_empty())
+                .collect();
+        }
+        let text = match *term {
+            Term::Terminal(ref t) => t.clone(),
+            _ => term.to_string(),
+        };
+        text.chars()
+            .try_fold(state, |state, c| self.dfa.next(state, c))
+            .into_iter()
+            .collect()
+    }
+
+    /// The chars of `class` taking the automaton from `from` to `to`
+    fn between(&self, class: &CharClass, from: usize, to: usize) -> Vec<CharRange> {
+        let ranges = self.dfa
+            .transitions(from)
+            .iter()
+            .filter(|&&(_, _, state)| state == to)
+            .map(|&(lo, hi, _)| (lo, hi))
+            .collect::<Vec<_>>();
+        intersect(&class.matched(), &ranges)
+    }
+
+    /// The automaton reading texts of `regex` alongside the automaton from
+    /// `from`, along with the pair of states each of its states stands for
+    fn product(&self, regex: &Dfa, from: usize) -> (Nfa, Vec<(usize, usize)>) {
+        let mut nfa = Nfa::new();
+        let mut pairs = vec![(0, from)];
+        let mut states = HashMap::new();
+        states.insert((0, from), 0);
+        let mut next = 0;
+        while next < pairs.len() {
+            let (r, d) = pairs[next];
+            for &(lo, hi, r_to) in regex.transitions(r) {
+                for &(l, h, d_to) in self.dfa.transitions(d) {
+                    if lo.max(l) > hi.min(h) {
+                        continue;
+                    }
+                    let to = match states.get(&(r_to, d_to)) {
+                        Some(&to) => to,
+                        None => {
+                            let to = nfa.add_state();
+                            states.insert((r_to, d_to), to);
+                            pairs.push((r_to, d_to));
+                            to
+                        }
+                    };
+                    nfa.add_transition(next, Some((lo.max(l), hi.min(h))), to);
+                }
+            }
+            next += 1;
+        }
+        (nfa, pairs)
+    }
+
+    /// A regex matching the texts of `regex` taking the automaton from
+    /// `from` to `to`
+    fn narrow(&self, regex: &Dfa, from: usize, to: usize) -> String {
+        let (mut nfa, pairs) = self.product(regex, from);
+        for (state, &(r, d)) in pairs.iter().enumerate() {
+            if regex.is_accepting(r) && d == to {
+                nfa.set_accepting(state);
+            }
+        }
+        nfa.to_regex()
+    }
+
+    /// The nonterminal of the intersection for `triple`
+    fn name(&mut self, triple: Triple) -> Term {
+        if let Some(name) = self.names.get(&triple) {
+            return name.clone();
+        }
+        let (index, from, to) = triple;
+        let mut name = match self.grammar.production(index).lhs {
+            Term::Nonterminal(ref lhs) | Term::Terminal(ref lhs) => {
+                format!("{}@{}-{}", lhs, from, to)
+            }
+            ref lhs => format!("{}@{}-{}", lhs, from, to),
+        };
+        while self.taken.contains(&name) {
+            name.push('\'');
+        }
+        self.taken.insert(name.clone());
+        let name = Term::Nonterminal(name);
+        self.names.insert(triple, name.clone());
+        name
+    }
+
+    /// Every alternative of the intersection deriving sentences of `terms`
+    /// which take the automaton from `from` to `to`, along with the
+    /// nonterminals of the intersection they use. Classes and regexes are
+    /// narrowed to the texts taking the automaton where the alternative needs.
+    fn alternatives(
+        &self,
+        terms: &[&'gram Term],
+        from: usize,
+        to: usize,
+    ) -> Vec<Vec<(Term, Option<Triple>)>> {
+        // per position, the states from which the rest of `terms` reach `to`
+        let mut finishing = vec![BTreeSet::new(); terms.len() + 1];
+        finishing[terms.len()].insert(to);
+        for (position, term) in terms.iter().enumerate().rev() {
+            finishing[position] = (0..self.dfa.state_count())
+                .filter(|&state| {
+                    self.ends(term, state)
+                        .iter()
+                        .any(|end| finishing[position + 1].contains(end))
+                })
+                .collect();
+        }
+
+        let mut alternatives = vec![];
+        let mut pending = vec![(0, from, vec![])];
+        while let Some((position, state, alternative)) = pending.pop() {
+            if position == terms.len() {
+                alternatives.push(alternative);
+                continue;
+            }
+            let term = terms[position];
+            for end in self.ends(term, state) {
+                if !finishing[position + 1].contains(&end) {
+                    continue;
+                }
+                let mut alternative = alternative.clone();
+                let triple = self.indices.get(term).map(|&n| (n, state, end));
+                let term = match *term {
+                    Term::Regex(ref pattern) => {
+                        let regex = self.regexes
+                            .dfa(pattern)
+                            .expect("regex with ends should compile");
+                        Term::Regex(self.narrow(regex, state, end))
+                    }
+                    _ => match term.class() {
+                        Some(class) => {
+                            Term::Class(CharClass::new(self.between(&class, state, end), false))
+                        }
+                        None => term.clone(),
+                    },
+                };
+                alternative.push((term, triple));
+                pending.push((position + 1, end, alternative));
+            }
+        }
+        alternatives.reverse();
+        alternatives
+    }
+
+    fn build(mut self) -> Grammar {
+        let grammar = self.grammar;
+        let start = match grammar.productions_iter().next() {
+            Some(production) => &production.lhs,
+            None => return Grammar::new(),
+        };
+        let index = self.indices[start];
+        let accepted = self.reach[index][0]
+            .iter()
+            .cloned()
+            .filter(|&state| self.dfa.is_accepting(state))
+            .collect::<Vec<_>>();
+
+        let mut pending = accepted
+            .into_iter()
+            .map(|state| (index, 0, state))
+            .collect::<VecDeque<_>>();
+        let start_rhs = pending
+            .iter()
+            .map(|&triple| Expression::from_parts(vec![self.name(triple)]))
+            .collect();
+        let mut productions = vec![Production::from_parts(start.clone(), start_rhs)];
+        let mut built = pending.iter().cloned().collect::<HashSet<_>>();
+
+        while let Some(triple) = pending.pop_front() {
+            let (index, from, to) = triple;
+            let mut rhs = vec![];
+            for expression in grammar.production(index).rhs_iter() {
+                let terms = expression.terms_iter().collect::<Vec<_>>();
+                for alternative in self.alternatives(&terms, from, to) {
+                    let terms = alternative
+                        .into_iter()
+                        .map(|(term, triple)| match triple {
+                            Some(triple) => {
+                                if built.insert(triple) {
+                                    pending.push_back(triple);
+                                }
+                                self.name(triple)
+                            }
+                            None => term,
+                        })
+                        .collect();
+                    rhs.push(Expression::from_parts(terms));
+                }
+            }
+            productions.push(Production::from_parts(self.name(triple), rhs));
+        }
+        Grammar::from_parts(productions)
+    }
+}
+
+impl Grammar {
+    /// Construct a `Grammar` deriving the sentences of self which `dfa`
+    /// accepts, by the Bar-Hillel construction. Its nonterminals are those
+    /// of self along with the states the automaton is in before and after
+    /// their sentences, such as `<expr@0-2>`, and only those able to take
+    /// part in deriving a sentence are kept. The start rule keeps its name.
+    /// If no sentence is accepted, only the start rule is kept, with no
+    /// alternatives. Groups and repeats are desugared first; see `desugar`.
+    /// Any layout is written out, as the whitespace nonterminal between the
+    /// terms it goes between, so the grammar constructed has none.
+    pub fn intersect(&self, dfa: &Dfa) -> Grammar {
+        let grammar = self.desugar().explicit_layout();
+        Intersection::new(&grammar, dfa).build()
+    }
+
+    /// Construct a `Grammar` deriving the sentences of self which `pattern`
+    /// matches as a whole. See `Nfa::from_regex` for its syntax and
+    /// `intersect` for the construction.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let gattaca = grammar.intersect_regex("GA.{0,8}").unwrap();
+    ///     let sentence = gattaca.generate().unwrap();
+    ///     println!("{}", sentence);
+    ///
+    ///     # assert!(sentence.starts_with("GA") && sentence.len() <= 10);
+    ///     # assert!(gattaca.is_finite_language());
+    /// }
+    /// ```
+    pub fn intersect_regex(&self, pattern: &str) -> Result<Grammar, Error> {
+        Ok(self.intersect(&Dfa::from_regex(pattern)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::Generator;
+    use language::Bound;
+
+    fn dna() -> Grammar {
+        Grammar::from_str(
+            "<dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap()
+    }
+
+    #[test]
+    fn intersect_prefix() {
+        let grammar = dna().intersect_regex("GA.*").unwrap();
+        let sentences = grammar.sentences_bounded(Bound::Length(3)).collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["GA", "GAA", "GAC", "GAG", "GAT"]);
+        assert!(!grammar.is_finite_language());
+        assert_eq!(grammar.productions_iter().next().unwrap().lhs.to_string(), "<dna>");
+        let generator = Generator::new(&grammar);
+        for seed in 0..10 {
+            assert!(generator.generate_from_seed(seed).unwrap().starts_with("GA"));
+        }
+        // no production is useless
+        let minimal = grammar.minimal_derivations();
+        assert_eq!(minimal.len(), grammar.productions_iter().count());
+        assert!(minimal.iter().all(|m| m.is_productive()));
+    }
+
+    #[test]
+    fn intersect_context_free() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"(\" <s> \")\" <s> | <empty>
+            <empty> ::= \"\"",
+        ).unwrap();
+        let short = grammar.intersect_regex(".{0,4}").unwrap();
+        let sentences = short.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["", "(())", "()", "()()"]);
+
+        let nested = grammar.intersect_regex("\\(*\\)*").unwrap();
+        assert!(nested.recognizes("((()))"));
+        assert!(!nested.recognizes("()()"));
+        assert!(!nested.recognizes("(()"));
+    }
+
+    #[test]
+    fn intersect_empty() {
+        let grammar = dna().intersect_regex("GA[^ACGT]").unwrap();
+        assert!(grammar.is_empty_language());
+        assert_eq!(grammar.productions_iter().count(), 1);
+        assert!(Grammar::new().intersect_regex("a").unwrap().is_empty_language());
+        assert!(dna().intersect_regex("(").is_err());
+    }
+
+    #[test]
+    fn intersect_classes() {
+        let grammar = Grammar::from_str("<id> ::= [a-z] | [a-z] <id>").unwrap();
+        let intersection = grammar.intersect_regex("[^k-z]x.").unwrap();
+        assert!(intersection.recognizes("axe"));
+        assert!(!intersection.recognizes("kxe"));
+        assert!(!intersection.recognizes("ax"));
+        let sentences = intersection.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences.len(), 10 * 26);
+        assert!(intersection.generate_from_seed(5).unwrap().starts_with(|c| c < 'k'));
+    }
+
+    #[test]
+    fn intersect_regexes() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <number> | <number> \",\" <list>
+            <number> ::= /[0-9]+/",
+        ).unwrap();
+        let intersection = grammar.intersect_regex("1[0-9]*(,2[0-9]*)*").unwrap();
+        assert!(intersection.recognizes("1,2,20"));
+        assert!(intersection.recognizes("123"));
+        assert!(!intersection.recognizes("1,3"));
+        assert!(!intersection.recognizes("21"));
+        for seed in 0..10 {
+            let sentence = intersection.generate_from_seed(seed).unwrap();
+            assert!(sentence.starts_with('1'), "{}", sentence);
+            assert!(grammar.recognizes(&sentence));
+        }
+        let short = grammar.intersect_regex("[0-9]{2}").unwrap();
+        assert_eq!(short.sentences_iter().unwrap().count(), 100);
+    }
+
+    #[test]
+    fn intersect_names_apart() {
+        // <s@0-1> has no production, but the name is already taken
+        let grammar = Grammar::from_str("<s> ::= \"x\" | \"\" | <s@0-1>").unwrap();
+        let intersection = grammar.intersect_regex("x?").unwrap();
+        assert!(intersection.recognizes("x"));
+        assert!(intersection.recognizes(""));
+        assert!(!intersection.recognizes("<s@0-1>"));
+        let names = intersection
+            .productions_iter()
+            .map(|production| production.lhs.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["<s>", "<s@0-0>", "<s@0-1'>"]);
+    }
+}
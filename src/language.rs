@@ -0,0 +1,588 @@
+use std::collections::{btree_set, BTreeSet, HashMap};
+use automaton::Dfa;
+use class::ENUMERATED_CHARS;
+use graph::strongly_connected;
+use grammar::Grammar;
+use regex::Regexes;
+use shortest::Shortest;
+use term::Term;
+
+/// Iterator over the sentences of a finite language, in lexicographic order
+#[derive(Debug)]
+pub struct Sentences {
+    iterator: btree_set::IntoIter<String>,
+}
+
+impl Iterator for Sentences {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iterator.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Sentences {}
+
+/// A bound on the sentences enumerated from a `Grammar`
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Bound {
+    /// Sentences of at most this many chars
+    Length(usize),
+    /// Sentences with a derivation at most this high, counted in levels of
+    /// nonterminals as `MinimalDerivation::height` is. Regex terminals
+    /// contribute those of their texts at most this many chars long, and
+    /// groups and repeats count the levels of their desugared productions;
+    /// see `Grammar::desugar`. The whitespace of a layout counts as the
+    /// nonterminal it is.
+    Depth(usize),
+}
+
+/// The texts a term without a production stands for: its own text, any
+/// one char of a class, or the texts of a regex up to `length` chars long,
+/// or all of them if `None` and they are finitely many. Classes and regex
+/// ranges of many chars only stand for some of them; see `is_enumerable`.
+fn texts(regexes: &Regexes, term: &Term, length: Option<usize>) -> BTreeSet<String> {
+    match *term {
+        Term::Terminal(ref t) => Some(t.clone()).into_iter().collect(),
+        Term::Nonterminal(_) => Some(term.to_string()).into_iter().collect(),
+        Term::Class(_) | Term::Builtin(_) => term.class()
+            .into_iter()
+            .flat_map(|class| class.enumerated())
+            .map(String::from)
+            .collect(),
+        Term::Regex(ref pattern) => match regexes.dfa(pattern) {
+            Ok(dfa) => dfa.inputs_within(length.unwrap_or_else(|| dfa.state_count())),
+            Err(_) => BTreeSet::new(),
+        },
+        Term::Group(_) | Term::Repeat(..) => unreachable!("grammar should be desugared"),
+    }
+}
+
+/// Whether `texts` gives every text `term` stands for, rather than some of
+/// the chars of a class or regex range of more than 256
+fn is_enumerable(regexes: &Regexes, term: &Term) -> bool {
+    match *term {
+        Term::Class(_) | Term::Builtin(_) => term.class()
+            .into_iter()
+            .all(|class| class.len() <= ENUMERATED_CHARS),
+        Term::Regex(ref pattern) => regexes.dfa(pattern).map_or(true, |dfa| dfa.is_enumerable()),
+        _ => true,
+    }
+}
+
+/// The sentences each production derives within `bound`. Only the first
+/// production of each nonterminal is expanded, as in generation.
+fn bounded_sentences(grammar: &Grammar, bound: Bound) -> Vec<BTreeSet<String>> {
+    let indices = grammar.production_indices();
+    let mut sentences = vec![BTreeSet::new(); grammar.productions_iter().count()];
+    let within = |sentence: &String| match bound {
+        Bound::Length(length) => sentence.chars().count() <= length,
+        Bound::Depth(_) => true,
+    };
+    let regexes = Regexes::new(grammar);
+    let length = match bound {
+        Bound::Length(length) | Bound::Depth(length) => length,
+    };
+
+    // each round derives sentences one level higher than the last
+    let mut height = 0;
+    loop {
+        if bound == Bound::Depth(height) {
+            return sentences;
+        }
+        height += 1;
+
+        let mut derived_sentences = sentences.clone();
+        for (index, production) in grammar.productions_iter().enumerate() {
+            if indices[&production.lhs] != index {
+                continue;
+            }
+            for rhs in production.rhs_iter() {
+                let mut derived = BTreeSet::new();
+                derived.insert(String::new());
+                for term in rhs.terms_iter() {
+                    let literal;
+                    let texts = match indices.get(term) {
+                        Some(&n) => &sentences[n],
+                        None => {
+                            literal = texts(&regexes, term, Some(length));
+                            &literal
+                        }
+                    };
+                    derived = derived
+                        .iter()
+                        .flat_map(|prefix| texts.iter().map(move |text| prefix.clone() + text))
+                        .filter(&within)
+                        .collect();
+                }
+                derived_sentences[index].extend(derived);
+            }
+        }
+
+        if derived_sentences == sentences {
+            return sentences;
+        }
+        sentences = derived_sentences;
+    }
+}
+
+/// Count the inputs a minimal `dfa` accepting finitely many inputs accepts,
+/// as its paths from the start to acceptance, each read by a single input
+fn count_accepted(dfa: &Dfa) -> usize {
+    let mut counts: Vec<Option<usize>> = vec![None; dfa.state_count()];
+    let mut pending = vec![0];
+    while let Some(&state) = pending.last() {
+        if counts[state].is_some() {
+            pending.pop();
+            continue;
+        }
+        // a finite language's minimal automaton has no cycles, so states
+        // are counted after every state they lead to
+        let uncounted = dfa.transitions(state)
+            .iter()
+            .filter(|&&(_, _, to)| counts[to].is_none())
+            .map(|&(_, _, to)| to)
+            .collect::<Vec<_>>();
+        if !uncounted.is_empty() {
+            pending.extend(uncounted);
+            continue;
+        }
+        pending.pop();
+        let accepted = if dfa.is_accepting(state) { 1 } else { 0 };
+        let count = dfa.transitions(state)
+            .iter()
+            .fold(accepted, |count: usize, &(lo, hi, to)| {
+                let chars = hi as usize - lo as usize + 1;
+                let paths = counts[to].expect("later state should be counted");
+                count.saturating_add(chars.saturating_mul(paths))
+            });
+        counts[state] = Some(count);
+    }
+    counts[0].unwrap_or(0)
+}
+
+/// The productions able to take part in deriving a sentence: those reachable
+/// from the start rule through alternatives able to complete a derivation
+struct Useful<'gram> {
+    indices: HashMap<&'gram Term, usize>,
+    shortest: Shortest<'gram>,
+    grammar: &'gram Grammar,
+    reachable: Vec<bool>,
+}
+
+impl<'gram> Useful<'gram> {
+    /// Find the useful productions, or `None` if self derives no sentence
+    fn new(grammar: &'gram Grammar) -> Option<Useful<'gram>> {
+        let start = Term::Nonterminal(grammar.start_rule().ok()?);
+        let indices = grammar.production_indices();
+        let shortest = Shortest::new(grammar);
+        let start = indices[&start];
+        shortest.height(start)?;
+
+        let mut useful = Useful {
+            reachable: vec![false; grammar.productions_iter().count()],
+            indices,
+            shortest,
+            grammar,
+        };
+        useful.reachable[start] = true;
+        let mut pending = vec![start];
+        while let Some(index) = pending.pop() {
+            for n in useful.successors(index) {
+                if !useful.reachable[n] {
+                    useful.reachable[n] = true;
+                    pending.push(n);
+                }
+            }
+        }
+        Some(useful)
+    }
+
+    /// The terms of each alternative of production `index` able to complete a derivation
+    fn alternatives(&self, index: usize) -> Vec<Vec<&'gram Term>> {
+        let expressions = self.grammar.production(index).rhs_iter().collect::<Vec<_>>();
+        self.shortest
+            .productive(index)
+            .iter()
+            .map(|&e| expressions[e].terms_iter().collect())
+            .collect()
+    }
+
+    /// Productions the alternatives of production `index` expand
+    fn successors(&self, index: usize) -> Vec<usize> {
+        self.alternatives(index)
+            .into_iter()
+            .flat_map(|terms| terms.into_iter())
+            .filter_map(|term| self.indices.get(term).cloned())
+            .collect()
+    }
+
+    /// Whether some sentence derived from each useful production is not empty
+    fn growing(&self) -> Vec<bool> {
+        let mut growing = vec![false; self.reachable.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in 0..growing.len() {
+                if !self.reachable[index] || growing[index] {
+                    continue;
+                }
+                let grows = self.alternatives(index)
+                    .iter()
+                    .any(|terms| terms.iter().any(|term| self.grows(term, &growing)));
+                if grows {
+                    growing[index] = true;
+                    changed = true;
+                }
+            }
+        }
+        growing
+    }
+
+    fn grows(&self, term: &Term, growing: &[bool]) -> bool {
+        match self.indices.get(term) {
+            Some(&n) => growing[n],
+            None => match *term {
+                // a regex matching more than the empty text can leave its start
+                Term::Regex(ref pattern) => match self.shortest.regexes().dfa(pattern) {
+                    Ok(dfa) => !dfa.transitions(0).is_empty(),
+                    Err(_) => false,
+                },
+                _ => match term.class() {
+                    Some(class) => !class.is_empty(),
+                    None => *term != Term::Terminal(String::new()),
+                },
+            },
+        }
+    }
+
+    /// Whether infinitely many sentences are derived. This holds when some
+    /// production derives itself alongside terms which are not always empty,
+    /// that is when a cycle among the useful productions has such an edge,
+    /// or when a useful alternative has a regex matching infinitely many texts.
+    fn is_infinite(&self) -> bool {
+        let regexes = self.shortest.regexes();
+        let infinite_regex = (0..self.reachable.len())
+            .filter(|&index| self.reachable[index])
+            .flat_map(|index| self.alternatives(index))
+            .flat_map(|terms| terms.into_iter())
+            .any(|term| match *term {
+                Term::Regex(ref pattern) => regexes.dfa(pattern).map(|dfa| !dfa.is_finite()) == Ok(true),
+                _ => false,
+            });
+        if infinite_regex {
+            return true;
+        }
+
+        let adjacency = (0..self.reachable.len())
+            .map(|index| {
+                if self.reachable[index] {
+                    self.successors(index)
+                } else {
+                    vec![]
+                }
+            })
+            .collect::<Vec<_>>();
+        let mut component = vec![0; adjacency.len()];
+        for (c, members) in strongly_connected(&adjacency).into_iter().enumerate() {
+            for member in members {
+                component[member] = c;
+            }
+        }
+
+        let growing = self.growing();
+        (0..self.reachable.len())
+            .filter(|&index| self.reachable[index])
+            .any(|index| {
+                self.alternatives(index).iter().any(|terms| {
+                    terms.iter().enumerate().any(|(i, term)| {
+                        let cyclic = match self.indices.get(term) {
+                            Some(&n) => component[n] == component[index],
+                            None => false,
+                        };
+                        cyclic
+                            && terms
+                                .iter()
+                                .enumerate()
+                                .any(|(j, other)| i != j && self.grows(other, &growing))
+                    })
+                })
+            })
+    }
+
+    /// Whether every text of the terms of useful alternatives is enumerated
+    fn is_enumerable(&self) -> bool {
+        (0..self.reachable.len())
+            .filter(|&index| self.reachable[index])
+            .flat_map(|index| self.alternatives(index))
+            .flat_map(|terms| terms.into_iter())
+            .all(|term| is_enumerable(self.shortest.regexes(), term))
+    }
+
+    /// The sentences derived from each useful production, if finitely many
+    fn sentences(&self) -> Vec<BTreeSet<String>> {
+        let mut sentences = vec![BTreeSet::new(); self.reachable.len()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for index in (0..sentences.len()).filter(|&i| self.reachable[i]) {
+                for terms in self.alternatives(index) {
+                    let mut derived = BTreeSet::new();
+                    derived.insert(String::new());
+                    for term in terms {
+                        let texts = match self.indices.get(term) {
+                            Some(&n) => sentences[n].clone(),
+                            None => texts(self.shortest.regexes(), term, None),
+                        };
+                        derived = derived
+                            .iter()
+                            .flat_map(|prefix| texts.iter().map(move |text| prefix.clone() + text))
+                            .collect();
+                    }
+                    if !derived.is_subset(&sentences[index]) {
+                        sentences[index].extend(derived);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        sentences
+    }
+}
+
+impl Grammar {
+    /// Whether self derives no sentence at all from lhs of first production,
+    /// as when every derivation of it recurses forever
+    pub fn is_empty_language(&self) -> bool {
+        Useful::new(&self.desugar().explicit_layout()).is_none()
+    }
+
+    /// Whether self derives finitely many sentences from lhs of first production
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let dna = Grammar::from_str(
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+    ///     ).unwrap();
+    ///     let codon = Grammar::from_str(
+    ///         "<codon> ::= <base> <base> <base>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+    ///     ).unwrap();
+    ///
+    ///     assert!(!dna.is_finite_language());
+    ///     assert!(codon.is_finite_language());
+    ///     assert_eq!(codon.count_sentences(), Some(64));
+    /// }
+    /// ```
+    pub fn is_finite_language(&self) -> bool {
+        match Useful::new(&self.desugar().explicit_layout()) {
+            Some(useful) => !useful.is_infinite(),
+            None => true,
+        }
+    }
+
+    /// Count the distinct sentences self derives from lhs of first production,
+    /// or `None` if there are infinitely many. Gives `None` as
+    /// `sentences_iter` does for a class of too many chars.
+    ///
+    /// # Cost
+    ///
+    /// When the start rule is regular by `is_regular`, as finite languages
+    /// mostly are, the sentences are counted as the paths through the
+    /// minimal `Dfa` of the language, without deriving any of them. Otherwise
+    /// every sentence is derived, as for `sentences_iter`, which is only
+    /// practical for modest languages. Counts beyond `usize::MAX` saturate.
+    pub fn count_sentences(&self) -> Option<usize> {
+        match Useful::new(&self.desugar().explicit_layout()) {
+            Some(ref useful) if useful.is_infinite() || !useful.is_enumerable() => None,
+            Some(_) => {
+                let start = &self.productions_iter().next()?.lhs;
+                match self.to_nfa(start) {
+                    Some(nfa) => Some(count_accepted(&nfa.to_dfa())),
+                    None => self.sentences_iter().map(|sentences| sentences.len()),
+                }
+            }
+            None => Some(0),
+        }
+    }
+
+    /// Iterate over every distinct sentence self derives from lhs of first
+    /// production, or `None` if there are infinitely many. Also `None` if a
+    /// class or regex range the sentences use has more than 256 chars, such
+    /// as `ANY` or `[^a]`, rather than enumerating each of them.
+    pub fn sentences_iter(&self) -> Option<Sentences> {
+        let grammar = self.desugar().explicit_layout();
+        let sentences = match Useful::new(&grammar) {
+            Some(ref useful) if useful.is_infinite() || !useful.is_enumerable() => return None,
+            Some(useful) => {
+                let start = useful.indices[&self.productions_iter().next()?.lhs];
+                useful.sentences().swap_remove(start)
+            }
+            None => BTreeSet::new(),
+        };
+        Some(Sentences {
+            iterator: sentences.into_iter(),
+        })
+    }
+
+    /// Iterate over every distinct sentence self derives from lhs of first
+    /// production within `bound`, even if the language is infinite. A class
+    /// or regex range of more than 256 chars, such as `ANY`, stands for up to
+    /// 256 of them, from printable ASCII where it has some, as in generation.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Bound, Grammar};
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///
+    ///     assert_eq!(grammar.sentences_bounded(Bound::Length(2)).count(), 4 + 16);
+    ///     assert_eq!(grammar.sentences_bounded(Bound::Depth(3)).count(), 4 + 16);
+    /// }
+    /// ```
+    pub fn sentences_bounded(&self, bound: Bound) -> Sentences {
+        let sentences = match self.productions_iter().next() {
+            Some(start) => {
+                let index = self.production_indices()[&start.lhs];
+                bounded_sentences(&self.desugar().explicit_layout(), bound).swap_remove(index)
+            }
+            None => BTreeSet::new(),
+        };
+        Sentences {
+            iterator: sentences.into_iter(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_language() {
+        assert!(Grammar::new().is_empty_language());
+        let grammar = Grammar::from_str(
+            "<s> ::= <s> \"a\" | <t>
+            <t> ::= <s>
+            <u> ::= \"u\"",
+        ).unwrap();
+        assert!(grammar.is_empty_language());
+        assert!(grammar.is_finite_language());
+        assert_eq!(grammar.count_sentences(), Some(0));
+
+        let grammar = Grammar::from_str("<s> ::= <s> \"a\" | \"\"").unwrap();
+        assert!(!grammar.is_empty_language());
+    }
+
+    #[test]
+    fn infinite_language() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <e> <s> <e> | \"(\" <s> \")\" | \"\"
+            <e> ::= \"\"",
+        ).unwrap();
+        assert!(!grammar.is_finite_language());
+        assert!(grammar.count_sentences().is_none());
+        assert!(grammar.sentences_iter().is_none());
+    }
+
+    #[test]
+    fn finite_despite_cycles() {
+        // cycles which only ever add empty strings do not add sentences,
+        // and neither do cycles no sentence can be derived through
+        let grammar = Grammar::from_str(
+            "<s> ::= <s> | <e> <s> <e> | <a> <a> | <never>
+            <a> ::= \"x\" | \"y\" | <e>
+            <e> ::= \"\" | <e> <e>
+            <never> ::= \"!\" <never>",
+        ).unwrap();
+        assert!(grammar.is_finite_language());
+        let sentences = grammar.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["", "x", "xx", "xy", "y", "yx", "yy"]);
+        assert_eq!(grammar.count_sentences(), Some(7));
+    }
+
+    #[test]
+    fn unreachable_infinite_production() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"a\" <undefined> | \"b\"
+            <loop> ::= \"c\" <loop> | \"c\"",
+        ).unwrap();
+        assert!(grammar.is_finite_language());
+        let sentences = grammar.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["a<undefined>", "b"]);
+    }
+
+    #[test]
+    fn bounded_sentences() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"(\" <s> \")\" | <s> <s> | \"\"",
+        ).unwrap();
+        let sentences = grammar.sentences_bounded(Bound::Length(4)).collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["", "(())", "()", "()()"]);
+        let sentences = grammar.sentences_bounded(Bound::Depth(2)).collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["", "()"]);
+        assert_eq!(grammar.sentences_bounded(Bound::Depth(0)).count(), 0);
+
+        let grammar = Grammar::from_str("<s> ::= <s> \"a\"").unwrap();
+        assert_eq!(grammar.sentences_bounded(Bound::Length(10)).count(), 0);
+        assert_eq!(Grammar::new().sentences_bounded(Bound::Length(10)).count(), 0);
+    }
+
+    #[test]
+    fn count_without_deriving() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <d> <d> <d> <d> <d> <d> <d> <d> | <d> <d> | \"00\"
+            <d> ::= DIGIT",
+        ).unwrap();
+        assert_eq!(grammar.count_sentences(), Some(100_000_000 + 100));
+    }
+
+    #[test]
+    fn large_classes() {
+        let negated = Grammar::from_str("<s> ::= [^a] | \"a\" DIGIT").unwrap();
+        assert!(negated.is_finite_language());
+        assert_eq!(negated.count_sentences(), None);
+        assert!(negated.sentences_iter().is_none());
+        let sentences = negated.sentences_bounded(Bound::Length(2)).collect::<Vec<_>>();
+        assert_eq!(sentences.len(), 94 + 10);
+        assert!(sentences.iter().all(|sentence| negated.recognizes(sentence)));
+
+        let any = Grammar::from_str("<s> ::= ANY ANY").unwrap();
+        assert_eq!(any.count_sentences(), None);
+        assert_eq!(any.sentences_bounded(Bound::Length(2)).count(), 95 * 95);
+        let dot = Grammar::from_str("<s> ::= /./").unwrap();
+        assert_eq!(dot.count_sentences(), None);
+        assert_eq!(dot.sentences_bounded(Bound::Length(1)).count(), 95);
+
+        let small = Grammar::from_str("<s> ::= [^\u{0}-\u{10FF00}] | /[a-c]/").unwrap();
+        assert_eq!(small.count_sentences(), Some(255 + 3));
+    }
+
+    #[test]
+    fn groups_and_repeats() {
+        let grammar = Grammar::from_str("<s> ::= (\"a\" | \"b\"){1,2} \"c\"?").unwrap();
+        assert!(grammar.is_finite_language());
+        assert_eq!(grammar.count_sentences(), Some(12));
+
+        let grammar = Grammar::from_str("<s> ::= \"a\"+").unwrap();
+        assert!(!grammar.is_finite_language());
+        let sentences = grammar.sentences_bounded(Bound::Length(2)).collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["a", "aa"]);
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::HashSet;
+use expression::Expression;
+use grammar::Grammar;
+use term::Term;
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+/// A Layout is how the sentences of a `Grammar` are laid out: what text
+/// may come between the terms of its productions. Between each two terms of
+/// an alternative, and each two repetitions of a repeat, a derivation of the
+/// `whitespace` nonterminal is implied, except within lexical productions,
+/// whose terms are adjacent in the text.
+///
+/// The production of `whitespace` is lexical, as are the productions of the
+/// nonterminals in `lexical`. Nonterminals used by a lexical production
+/// keep their own layout, so the parts of a token should be lexical too.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Layout {
+    /// Nonterminal deriving the text allowed between terms
+    pub whitespace: Term,
+    /// Nonterminals whose productions are lexical
+    #[cfg_attr(feature = "serde", serde(serialize_with = "sorted"))]
+    pub lexical: HashSet<Term>,
+}
+
+/// Serialize `lexical` in the order of the nonterminals' names, so that
+/// equal layouts serialize equally
+#[cfg(feature = "serde")]
+fn sorted<S: Serializer>(lexical: &HashSet<Term>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut nonterms = lexical.iter().collect::<Vec<_>>();
+    nonterms.sort_by_key(|nonterm| nonterm.to_string());
+    nonterms.serialize(serializer)
+}
+
+impl Layout {
+    /// Construct a `Layout` separating terms by `whitespace`, without
+    /// lexical productions besides its own
+    pub fn new(whitespace: Term) -> Layout {
+        Layout {
+            whitespace,
+            lexical: HashSet::new(),
+        }
+    }
+
+    /// Make the production of `nonterm` lexical
+    pub fn add_lexical(&mut self, nonterm: Term) {
+        self.lexical.insert(nonterm);
+    }
+
+    /// Whether the production of `nonterm` is lexical
+    pub fn is_lexical(&self, nonterm: &Term) -> bool {
+        *nonterm == self.whitespace || self.lexical.contains(nonterm)
+    }
+}
+
+impl Grammar {
+    /// Get the layout of sentences, if any. Without one, the terms of
+    /// productions are adjacent in the text.
+    pub fn layout(&self) -> Option<&Layout> {
+        self.layout.as_ref()
+    }
+
+    /// Set the layout of sentences. Generation derives the whitespace
+    /// nonterminal between terms, so its choices are random too, and
+    /// parsing reads it there, so the whitespace becomes part of the parse
+    /// tree. Parsing tokens ignores the layout, as the `Lexer` skips it.
+    /// Analyses of the sentences, such as `sentences_iter`,
+    /// `minimal_derivations` and `intersect`, read whitespace where parsing
+    /// does.
+    ///
+    /// The whitespace nonterminal must have a production.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Grammar, Layout, Term};
+    ///
+    /// fn main() {
+    ///     let mut grammar = Grammar::from_str(
+    ///         "<sum> ::= <number> | <number> \"+\" <sum>
+    ///         <number> ::= DIGIT+
+    ///         <ws> ::= \" \"*",
+    ///     ).unwrap();
+    ///     let mut layout = Layout::new(Term::Nonterminal(String::from("ws")));
+    ///     layout.add_lexical(Term::Nonterminal(String::from("number")));
+    ///     grammar.set_layout(Some(layout));
+    ///
+    ///     assert!(grammar.recognizes("12 +3+  45"));
+    ///     assert!(!grammar.recognizes("1 2"));
+    ///     let sentence = grammar.generate().unwrap();
+    ///     assert!(grammar.recognizes(&sentence));
+    /// }
+    /// ```
+    pub fn set_layout(&mut self, layout: Option<Layout>) {
+        self.layout = layout;
+    }
+
+    /// The grammar deriving the same sentences without a layout: the
+    /// whitespace nonterminal is written between the terms of each
+    /// production which is not lexical, for analyses reading productions
+    /// alone. Groups and repeats must be desugared first, as whitespace goes
+    /// between repetitions rather than around each.
+    pub(crate) fn explicit_layout(&self) -> Grammar {
+        let mut grammar = self.clone();
+        grammar.layout = None;
+        for (index, production) in grammar.productions_iter_mut().enumerate() {
+            let whitespace = match self.layout_between(index) {
+                Some(whitespace) => whitespace,
+                None => continue,
+            };
+            for expression in production.rhs_iter_mut() {
+                let mut terms = vec![];
+                for (i, term) in expression.terms_iter().enumerate() {
+                    if i > 0 {
+                        terms.push(whitespace.clone());
+                    }
+                    terms.push(term.clone());
+                }
+                *expression = Expression::from_parts(terms);
+            }
+        }
+        grammar
+    }
+
+    /// The whitespace nonterminal to derive between the terms of production
+    /// `index`, unless it is lexical
+    pub(crate) fn layout_between(&self, index: usize) -> Option<&Term> {
+        let layout = self.layout.as_ref()?;
+        if layout.is_lexical(&self.production(index).lhs) {
+            None
+        } else {
+            Some(&layout.whitespace)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::Generator;
+    use language::Bound;
+    use parse_tree::ParseTreeNode;
+
+    fn nonterminal(name: &str) -> Term {
+        Term::Nonterminal(String::from(name))
+    }
+
+    fn statements() -> Grammar {
+        let mut grammar = Grammar::from_str(
+            "<block> ::= <statement>*
+            <statement> ::= \"let\" <ident> \"=\" <ident> \";\"
+            <ident> ::= ALPHA+
+            <ws> ::= [ \\n]+",
+        ).unwrap();
+        let mut layout = Layout::new(nonterminal("ws"));
+        layout.add_lexical(nonterminal("ident"));
+        grammar.set_layout(Some(layout));
+        grammar
+    }
+
+    #[test]
+    fn parse_with_layout() {
+        let grammar = statements();
+        assert!(grammar.recognizes("let x = y ;"));
+        assert!(grammar.recognizes("let x = y ;\nlet  abc = x ;"));
+        assert!(grammar.recognizes(""));
+        // whitespace goes between terms, and not within lexical productions
+        assert!(!grammar.recognizes("letx=y;"));
+        assert!(!grammar.recognizes("let a b = y ;"));
+        assert!(!grammar.recognizes(" let x = y ;"));
+
+        let tree = grammar.parse_input("let x = y ; let z = x ;").unwrap();
+        assert_eq!(tree.flatten(), "let x = y ; let z = x ;");
+        let children = tree.children_iter().collect::<Vec<_>>();
+        match children[..] {
+            [&ParseTreeNode::Nonterminal(_), ParseTreeNode::Nonterminal(ws), _] => {
+                assert_eq!(*ws.lhs, nonterminal("ws"));
+            }
+            _ => panic!("expected whitespace between statements, got {:?}", children),
+        }
+
+        let mut plain = grammar.clone();
+        plain.set_layout(None);
+        assert!(plain.recognizes("letx=y;"));
+        assert!(!plain.recognizes("let x = y ;"));
+
+        let mut pairs = Grammar::from_str("<s> ::= \"x\"{2,} | \"y\"+ \"z\"\n<ws> ::= \" \"").unwrap();
+        pairs.set_layout(grammar.layout().cloned());
+        assert!(pairs.recognizes("x x x") && pairs.recognizes("y y z"));
+        assert!(!pairs.recognizes("x") && !pairs.recognizes("x x ") && !pairs.recognizes("yy z"));
+    }
+
+    #[test]
+    fn repeats_with_layout() {
+        let mut grammar = Grammar::from_str(
+            "<s> ::= \"x\"{1,3} \"y\" \"z\"{2,4}
+            <ws> ::= \" \"",
+        ).unwrap();
+        grammar.set_layout(Some(Layout::new(nonterminal("ws"))));
+        assert!(grammar.recognizes("x y z z"));
+        assert!(grammar.recognizes("x x x y z z z z"));
+        assert!(!grammar.recognizes("y z z"));
+        assert!(!grammar.recognizes("x x x x y z z"));
+        assert!(!grammar.recognizes("x y z z "));
+        assert!(!grammar.recognizes("xx y z z"));
+
+        let desugared = grammar.desugar();
+        let generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(grammar.recognizes(&sentence), "{}", sentence);
+            assert!(desugared.recognizes(&sentence), "{}", sentence);
+        }
+    }
+
+    #[test]
+    fn analyses_with_layout() {
+        let mut grammar = Grammar::from_str(
+            "<s> ::= \"let\" <id> \";\"
+            <id> ::= \"x\" | \"yy\"
+            <ws> ::= \" \"",
+        ).unwrap();
+        let mut layout = Layout::new(nonterminal("ws"));
+        layout.add_lexical(nonterminal("id"));
+        grammar.set_layout(Some(layout));
+
+        let sentences = grammar.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["let x ;", "let yy ;"]);
+        let bounded = grammar.sentences_bounded(Bound::Length(8)).collect::<Vec<_>>();
+        assert_eq!(bounded, sentences);
+        assert!(sentences.iter().all(|sentence| grammar.recognizes(sentence)));
+        assert!(grammar.language_diff(&grammar, Bound::Length(8)).is_empty());
+
+        let minimal = grammar.minimal_derivations();
+        assert_eq!(minimal[0].sentence(), Some(String::from("let x ;")));
+        let reduced = grammar.reduce("let yy ;", |s| s.starts_with("let"));
+        assert_eq!(reduced, Ok(String::from("let x ;")));
+
+        let intersection = grammar.intersect_regex("let y.*").unwrap();
+        let sentences = intersection.sentences_iter().unwrap().collect::<Vec<_>>();
+        assert_eq!(sentences, vec!["let yy ;"]);
+        let nfa = grammar.to_nfa(&nonterminal("s")).unwrap();
+        assert!(nfa.accepts("let x ;") && !nfa.accepts("letx;"));
+
+        // whitespace goes between repetitions, and around the repeat as a whole
+        let mut repeats = Grammar::from_str(
+            "<s> ::= \"a\"* \"b\" \"c\"{2,}
+            <ws> ::= \" \"",
+        ).unwrap();
+        repeats.set_layout(grammar.layout().cloned());
+        let sentences = repeats.sentences_bounded(Bound::Length(9)).collect::<Vec<_>>();
+        assert_eq!(sentences, vec![" b c c", " b c c c", "a a b c c", "a b c c", "a b c c c"]);
+        assert!(sentences.iter().all(|sentence| repeats.recognizes(sentence)));
+        assert_eq!(repeats.minimal_derivations()[0].sentence(), Some(String::from(" b c c")));
+    }
+
+    #[test]
+    fn generate_with_layout() {
+        let grammar = statements();
+        let generator = Generator::new(&grammar);
+        let mut spaced = false;
+        for seed in 0..20 {
+            let tree = generator.generate_tree_seeded(&mut ::rng_from_seed(seed)).unwrap();
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert_eq!(tree.flatten(), sentence);
+            assert!(grammar.recognizes(&sentence), "{}", sentence);
+            spaced |= sentence.contains("let ");
+            assert!(!sentence.contains("let="), "{}", sentence);
+        }
+        assert!(spaced);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_layout() {
+        let mut grammar = statements();
+        if let Some(ref mut layout) = grammar.layout {
+            layout.add_lexical(nonterminal("a"));
+        }
+        let json = ::serde_json::to_value(&grammar).unwrap();
+        assert_eq!(
+            json["layout"],
+            json!({
+                "whitespace": {"type": "Nonterminal", "value": "ws"},
+                "lexical": [
+                    {"type": "Nonterminal", "value": "a"},
+                    {"type": "Nonterminal", "value": "ident"},
+                ],
+            })
+        );
+        assert_eq!(::serde_json::from_value::<Grammar>(json).unwrap(), grammar);
+
+        grammar.set_layout(None);
+        let json = ::serde_json::to_value(&grammar).unwrap();
+        assert!(json.get("layout").is_none());
+        assert_eq!(::serde_json::from_value::<Grammar>(json).unwrap(), grammar);
+    }
+
+    #[test]
+    fn whitespace_must_be_defined() {
+        let mut grammar = Grammar::from_str("<s> ::= \"a\" \"b\"").unwrap();
+        grammar.set_layout(Some(Layout::new(nonterminal("ws"))));
+        assert!(grammar.generate().is_err());
+        assert!(grammar.parse_input("ab").is_err());
+        assert!(!grammar.recognizes("ab"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::HashSet;
+use automaton::{Dfa, Nfa};
+use grammar::Grammar;
+use production::Production;
+use term::Term;
+use error::Error;
+
+/// A Token is a piece of text a `Lexer` matched, along with the kind of
+/// token matched: a `Term::Terminal` standing for its own text, or a
+/// `Term::Nonterminal` standing for the texts it derives
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub struct Token {
+    pub kind: Term,
+    pub text: String,
+    /// Offset in bytes of the text within the input tokenized
+    pub start: usize,
+}
+
+impl Token {
+    /// Construct a `Token` of `kind` with `text` at byte offset `start`
+    pub fn new(kind: Term, text: &str, start: usize) -> Token {
+        Token {
+            kind,
+            text: String::from(text),
+            start,
+        }
+    }
+}
+
+/// Length in bytes of the longest prefix of `text` which `dfa` accepts
+fn longest(dfa: &Dfa, text: &str) -> Option<usize> {
+    let mut state = 0;
+    let mut length = if dfa.is_accepting(state) { Some(0) } else { None };
+    for (offset, c) in text.char_indices() {
+        state = match dfa.next(state, c) {
+            Some(next) => next,
+            None => break,
+        };
+        if dfa.is_accepting(state) {
+            length = Some(offset + c.len_utf8());
+        }
+    }
+    length
+}
+
+/// A Lexer splits text into `Token`s by longest match. At each position
+/// the kind matching the most text is chosen, or the kind added first if
+/// several match as much. Text matched by `skip` between tokens is dropped.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Lexer {
+    kinds: Vec<(Term, Dfa)>,
+    /// Text to skip before each token, whitespace by default. Nothing is
+    /// skipped if `None`.
+    pub skip: Option<Dfa>,
+}
+
+impl Lexer {
+    /// Construct a `Lexer` without token kinds, skipping whitespace
+    pub fn new() -> Lexer {
+        Lexer {
+            kinds: vec![],
+            skip: Some(Dfa::from_regex("[\\t-\\r ]*").expect("whitespace should be a regex")),
+        }
+    }
+
+    /// Add a kind of token, matching the texts `dfa` accepts. Kinds added
+    /// earlier take precedence over those matching as much text.
+    pub fn add_kind(&mut self, kind: Term, dfa: Dfa) {
+        self.kinds.push((kind, dfa))
+    }
+
+    /// Get iterator of the kinds of tokens, in order of precedence
+    pub fn kinds_iter(&self) -> impl Iterator<Item = &Term> {
+        self.kinds.iter().map(|(kind, _)| kind)
+    }
+
+    /// Split `input` into tokens, skipping text `skip` matches between them.
+    ///
+    /// Returns `Error::ParseError` if no kind of token matches some text.
+    pub fn tokenize(&self, input: &str) -> Result<Vec<Token>, Error> {
+        let mut tokens = vec![];
+        let mut position = 0;
+        loop {
+            if let Some(ref skip) = self.skip {
+                position += longest(skip, &input[position..]).unwrap_or(0);
+            }
+            if position == input.len() {
+                return Ok(tokens);
+            }
+
+            let mut matched: Option<(&Term, usize)> = None;
+            for (kind, dfa) in &self.kinds {
+                match (longest(dfa, &input[position..]), matched) {
+                    (Some(length), Some((_, l))) if length <= l => (),
+                    (Some(0), None) | (None, _) => (),
+                    (Some(length), _) => matched = Some((kind, length)),
+                }
+            }
+            match matched {
+                Some((kind, length)) => {
+                    let end = position + length;
+                    tokens.push(Token::new(kind.clone(), &input[position..end], position));
+                    position = end;
+                }
+                None => {
+                    return Err(Error::ParseError(format!(
+                        "Lexing error: no token matches at byte {}",
+                        position
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl Default for Lexer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Grammar {
+    /// Split self into a `Lexer` and the grammar of the remaining
+    /// productions, which derives sentences of tokens. Each of `tokens`
+    /// becomes a kind of token, matching the texts it derives, so must be
+    /// regular by `is_regular`. Each terminal of the remaining productions
+    /// becomes a kind of token too, matching its own text, with precedence
+    /// over `tokens` so that keywords are not taken for identifiers.
+    ///
+    /// Returns `Error::ParseError` if one of `tokens` is not regular.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Grammar, Term};
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "<sum> ::= <number> | <number> \"+\" <sum>
+    ///         <number> ::= DIGIT+",
+    ///     ).unwrap();
+    ///     let number = Term::Nonterminal(String::from("number"));
+    ///
+    ///     let (lexer, sum) = grammar.split_tokens(&[number]).unwrap();
+    ///     let tokens = lexer.tokenize("12 + 3+45").unwrap();
+    ///     assert_eq!(tokens.len(), 5);
+    ///     assert!(sum.recognizes_tokens(&tokens));
+    /// }
+    /// ```
+    pub fn split_tokens(&self, tokens: &[Term]) -> Result<(Lexer, Grammar), Error> {
+        let mut lexer = Lexer::new();
+        let is_token = self.productions_iter()
+            .map(|production| tokens.contains(&production.lhs))
+            .collect::<Vec<_>>();
+        let starts = if is_token.first() == Some(&false) { vec![0] } else { vec![] };
+        let sentential = self.reachable_avoiding(&starts, &is_token);
+        let token_starts = (0..is_token.len()).filter(|&i| is_token[i]).collect::<Vec<_>>();
+        let lexical = self.reachable_avoiding(&token_starts, &vec![false; is_token.len()]);
+
+        // productions only tokens use belong to the lexer alone
+        let remaining = self.productions_iter()
+            .enumerate()
+            .filter(|&(i, _)| !is_token[i] && (sentential[i] || !lexical[i]))
+            .map(|(_, production)| production.clone())
+            .collect::<Vec<Production>>();
+
+        let mut literals = HashSet::new();
+        let used = self.productions_iter()
+            .enumerate()
+            .filter(|&(i, _)| sentential[i])
+            .map(|(_, production)| production);
+        for production in used {
+            let terms = production
+                .rhs_iter()
+                .flat_map(|rhs| rhs.terms_iter())
+                .flat_map(|term| term.leaves());
+            for term in terms {
+                match *term {
+                    Term::Terminal(ref t) if !t.is_empty() && literals.insert(t) => {
+                        let mut nfa = Nfa::new();
+                        let end = nfa.add_text(0, t);
+                        nfa.set_accepting(end);
+                        lexer.add_kind(term.clone(), nfa.to_dfa());
+                    }
+                    _ => (),
+                }
+            }
+        }
+        for token in tokens {
+            match self.to_nfa(token) {
+                Some(nfa) => lexer.add_kind(token.clone(), nfa.to_dfa()),
+                None => {
+                    return Err(Error::ParseError(format!(
+                        "{} is not a regular nonterminal to match tokens by",
+                        token
+                    )))
+                }
+            }
+        }
+
+        Ok((lexer, Grammar::from_parts(remaining)))
+    }
+
+    /// Which productions are reachable from those at `starts`, without
+    /// passing through the productions `blocked` marks
+    fn reachable_avoiding(&self, starts: &[usize], blocked: &[bool]) -> Vec<bool> {
+        let mut reached = vec![false; blocked.len()];
+        let mut stack = starts.to_vec();
+        for &start in starts {
+            reached[start] = true;
+        }
+        while let Some(index) = stack.pop() {
+            let terms = self.production(index)
+                .rhs_iter()
+                .flat_map(|rhs| rhs.terms_iter())
+                .flat_map(|term| term.leaves());
+            for term in terms {
+                match self.production_index(term) {
+                    Some(child) if !blocked[child] && !reached[child] => {
+                        reached[child] = true;
+                        stack.push(child);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        reached
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonterminal(name: &str) -> Term {
+        Term::Nonterminal(String::from(name))
+    }
+
+    fn statements() -> Grammar {
+        Grammar::from_str(
+            "<block> ::= <statement>*
+            <statement> ::= \"let\" <ident> \"=\" <expr> \";\"
+            <expr> ::= <ident> | <number> | <expr> \"+\" <expr>
+            <ident> ::= ALPHA (ALPHA | DIGIT)*
+            <number> ::= DIGIT+",
+        ).unwrap()
+    }
+
+    #[test]
+    fn longest_match() {
+        let grammar = statements();
+        let (lexer, _) = grammar
+            .split_tokens(&[nonterminal("ident"), nonterminal("number")])
+            .unwrap();
+        let kinds = lexer.kinds_iter().map(|kind| kind.to_string()).collect::<Vec<_>>();
+        assert_eq!(kinds, ["\"let\"", "\"=\"", "\";\"", "\"+\"", "<ident>", "<number>"]);
+
+        let tokens = lexer.tokenize("let letter = let2+ 12;\n").unwrap();
+        let expected = vec![
+            Token::new(Term::Terminal(String::from("let")), "let", 0),
+            Token::new(nonterminal("ident"), "letter", 4),
+            Token::new(Term::Terminal(String::from("=")), "=", 11),
+            Token::new(nonterminal("ident"), "let2", 13),
+            Token::new(Term::Terminal(String::from("+")), "+", 17),
+            Token::new(nonterminal("number"), "12", 19),
+            Token::new(Term::Terminal(String::from(";")), ";", 21),
+        ];
+        assert_eq!(tokens, expected);
+        assert!(lexer.tokenize("let x = 1 $").is_err());
+        assert!(grammar.split_tokens(&[nonterminal("expr")]).is_err());
+    }
+
+    #[test]
+    fn skip_configurable() {
+        let grammar = statements();
+        let (mut lexer, _) = grammar.split_tokens(&[nonterminal("ident")]).unwrap();
+        assert_eq!(lexer.tokenize(" let ").unwrap().len(), 1);
+        lexer.skip = None;
+        assert!(lexer.tokenize(" let").is_err());
+        lexer.skip = Some(Dfa::from_regex("(#[^\\n]*\\n| )*").unwrap());
+        let tokens = lexer.tokenize("# comment\nlet x #\n").unwrap();
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[1].start, 14);
+    }
+
+    #[test]
+    fn token_parts_stay_lexical() {
+        let grammar = Grammar::from_str(
+            "<sum> ::= <number> | <number> \"+\" <sum>
+            <number> ::= <digit>+
+            <digit> ::= \"0\" | \"1\" | \"2\" | \"3\" | \"4\" | \"5\" | \"6\" | \"7\" | \"8\" | \"9\"",
+        ).unwrap();
+        let (lexer, sum) = grammar.split_tokens(&[nonterminal("number")]).unwrap();
+        let kinds = lexer.kinds_iter().map(|kind| kind.to_string()).collect::<Vec<_>>();
+        assert_eq!(kinds, ["\"+\"", "<number>"]);
+        assert_eq!(sum.productions_iter().count(), 1);
+
+        let tokens = lexer.tokenize("1 + 23").unwrap();
+        assert_eq!(tokens[0].kind, nonterminal("number"));
+        assert_eq!(tokens[2], Token::new(nonterminal("number"), "23", 4));
+        assert!(sum.recognizes_tokens(&tokens));
+    }
+
+    #[test]
+    fn parse_tokens() {
+        let grammar = statements();
+        let (lexer, statements) = grammar
+            .split_tokens(&[nonterminal("ident"), nonterminal("number")])
+            .unwrap();
+        let tokens = lexer.tokenize("let x = 1; let y = x + 22 + x;").unwrap();
+        let tree = statements.parse_tokens(&tokens).unwrap();
+        assert_eq!(tree.flatten(), "letx=1;lety=x+22+x;");
+        assert!(statements.recognizes_tokens(&[]));
+
+        let missing = lexer.tokenize("let x = ;").unwrap();
+        assert!(statements.parse_tokens(&missing).is_err());
+        // the text of a token does not matter, only its kind
+        let renamed = vec![
+            Token::new(Term::Terminal(String::from("let")), "let", 0),
+            Token::new(nonterminal("ident"), "1", 0),
+            Token::new(Term::Terminal(String::from("=")), "=", 0),
+            Token::new(nonterminal("number"), "x", 0),
+            Token::new(Term::Terminal(String::from(";")), ";", 0),
+        ];
+        assert!(statements.recognizes_tokens(&renamed));
+    }
+}
@@ -28,6 +28,19 @@
 //!     <opt-apt-num> ::= <apt-num> | ""
 //! ```
 //!
+//! A line starting with `#` is a comment. The comment lines right before a
+//! production are its documentation, kept as `Production::comment`. A comment on
+//! the line a production ends on remarks on that production, so is appended to
+//! its comment. Comments after the last production are dropped. Comments take
+//! no part in equality, so productions and grammars differing only in comments
+//! are equal.
+//!
+//! ```text
+//! # A sum of terms
+//! <expr> ::= <term> | <term> "+" <expr>   # also on <expr>, not documenting <term>
+//! <term> ::= DIGIT+
+//! ```
+//!
 //! ## Output
 //! Take the following grammar for DNA sequences to be input to this library's
 //! `parse` function.
@@ -106,6 +119,8 @@
 //! Once the `Grammar` object is populated you can generate a random sentence
 //! from it by calling the object's generate function. `grammar.generate()`.
 //! For the above grammar you could expect something like "T" "TGGC" or "AG".
+//! Each call analyses the grammar anew, so to generate many sentences construct
+//! a `Generator` from the grammar once and call its generate functions instead.
 //!
 //! If the generate function can't find a production for a nonterminal it tries
 //! to evaluate it will produce the identifer as is, i.e. `<identifier>`.
@@ -113,6 +128,29 @@
 //! The generate function will return an error if it detects an infinite loop
 //! caused by a production such as `<PATTERN> ::= <PATTERN>`.
 //!
+//! ## Serde
+//!
+//! With the `serde` feature, `Grammar`, `Production`, `Expression` and `Term`
+//! implement `Serialize` and `Deserialize`. In JSON, a grammar is an object of
+//! its `productions`, each of its `lhs` term and `rhs` expressions, each of
+//! its `terms`. A term is an object of its `type`, the name of its variant,
+//! and its `value`:
+//!
+//! ```json
+//! {"type": "Terminal", "value": "A"}
+//! {"type": "Nonterminal", "value": "dna"}
+//! {"type": "Class", "value": {"ranges": [["a", "z"], ["_", "_"]], "negated": false}}
+//! {"type": "Builtin", "value": "DIGIT"}
+//! {"type": "Regex", "value": "[0-9]+"}
+//! {"type": "Group", "value": [{"terms": [...]}, {"terms": [...]}]}
+//! {"type": "Repeat", "value": [{"type": "Builtin", "value": "DIGIT"}, 1, null]}
+//! ```
+//!
+//! A repeat's value is the term repeated, its minimum and its maximum, or
+//! `null` if unbounded. A production with a comment has its `comment` string,
+//! and a grammar with a `Layout` has a `layout` object too, of its
+//! `whitespace` term and `lexical` terms.
+//!
 //! ## Parse Example
 //!
 //! ```rust
@@ -166,15 +204,62 @@
 #[macro_use]
 extern crate nom;
 extern crate rand;
-extern crate stacker;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_json;
 mod parsers;
 mod error;
 mod term;
 mod expression;
 mod production;
 mod grammar;
+mod parse_tree;
+mod generate;
+mod coverage;
+mod earley;
+mod mutator;
+mod negative;
+mod shortest;
+mod reduce;
+mod graph;
+mod dot;
+mod railroad;
+mod docs;
+mod language;
+mod ambiguity;
+mod equivalence;
+mod diff;
+mod desugar;
+mod class;
+mod automaton;
+mod regex;
+mod regular;
+mod lexer;
+mod layout;
+mod intersection;
 pub use term::Term;
 pub use expression::Expression;
 pub use production::Production;
 pub use grammar::Grammar;
+pub use parse_tree::{ParseTree, ParseTreeNode};
+pub use generate::{
+    rng_from_seed, Generator, DEFAULT_MAX_EXPANSIONS, DEFAULT_MAX_REGEX_LENGTH,
+    DEFAULT_MAX_REPETITIONS,
+};
+pub use coverage::{CoverageGoal, CoverageReport, CoveredAlternative, CoveredPair};
+pub use mutator::Mutator;
+pub use negative::{Corruption, NearMiss};
+pub use shortest::MinimalDerivation;
+pub use language::{Bound, Sentences};
+pub use ambiguity::Ambiguity;
+pub use equivalence::LanguageDiff;
+pub use diff::{GrammarDiff, ProductionDiff};
+pub use class::{Builtin, CharClass, CharRange};
+pub use automaton::{Dfa, Nfa};
+pub use lexer::{Lexer, Token};
+pub use layout::Layout;
+pub use graph::DependencyGraph;
 pub use error::Error;
@@ -0,0 +1,264 @@
+use rand::Rng;
+use generate::{random_rng, rng_from_seed, Generator};
+use grammar::Grammar;
+use parse_tree::ParseTree;
+use error::Error;
+
+/// Number of subtrees a mutation tries replacing before giving up, when
+/// fresh derivations keep running out of expansions
+const MUTATE_ATTEMPTS: usize = 8;
+
+/// A Mutator derives new inputs from existing ones, for fuzzing.
+///
+/// Inputs are parsed against the `Grammar`, so every mutation is again a
+/// sentence of it. A mutation replaces a random subtree of the input with a
+/// fresh derivation of the same nonterminal; a splice replaces it with a
+/// subtree of the same nonterminal taken from another input.
+///
+/// Like generation, each operation has a `_seeded` form taking any rng and
+/// a `_from_seed` form taking a `u64`, so it fits custom mutator hooks such
+/// as libFuzzer's `LLVMFuzzerCustomMutator`.
+///
+/// # Example
+///
+/// ```rust
+/// extern crate bnf;
+/// use bnf::{Grammar, Mutator};
+///
+/// fn main() {
+///     let input =
+///         "<dna> ::= <base> | <base> <dna>
+///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+///     let grammar = Grammar::from_str(input).unwrap();
+///     let mutator = Mutator::new(&grammar);
+///
+///     let mutant = mutator.mutate_from_seed("GATTACA", 42).unwrap();
+///     let spliced = mutator.splice_from_seed("GATTACA", "CCCC", 42).unwrap();
+///     println!("{} {}", mutant, spliced);
+///
+///     # assert!(grammar.parse_input(&mutant).is_ok());
+///     # assert!(grammar.parse_input(&spliced).is_ok());
+/// }
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct Mutator<'gram> {
+    generator: Generator<'gram>,
+}
+
+impl<'gram> Mutator<'gram> {
+    /// Construct a `Mutator` for `grammar` with default limits
+    pub fn new(grammar: &'gram Grammar) -> Mutator<'gram> {
+        Mutator::from_generator(Generator::new(grammar))
+    }
+
+    /// Construct a `Mutator` deriving fresh subtrees with `generator`
+    pub fn from_generator(generator: Generator<'gram>) -> Mutator<'gram> {
+        Mutator { generator }
+    }
+
+    /// Get the `Generator` fresh subtrees are derived with
+    pub fn generator(&self) -> &Generator<'gram> {
+        &self.generator
+    }
+
+    fn parse(&self, input: &str) -> Result<ParseTree<'gram>, Error> {
+        self.generator.grammar().parse_input(input)
+    }
+
+    /// Replace a random subtree of `tree` with a fresh derivation of the same
+    /// nonterminal, using seed for random
+    pub fn mutate_tree_seeded<R: Rng>(
+        &self,
+        rng: &mut R,
+        tree: &ParseTree<'gram>,
+    ) -> Result<ParseTree<'gram>, Error> {
+        let nonterminals = tree.nonterminals()
+            .iter()
+            .map(|t| t.lhs)
+            .collect::<Vec<_>>();
+
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let n = rng.gen_range(0, nonterminals.len());
+            match self.generator.generate_tree_from(rng, nonterminals[n]) {
+                Ok(fresh) => {
+                    let mut mutant = tree.clone();
+                    *mutant
+                        .nonterminal_mut(n)
+                        .expect("nonterminal should be in tree") = fresh;
+                    return Ok(mutant);
+                }
+                Err(Error::RecursionLimit(_)) if attempt < MUTATE_ATTEMPTS => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Parse `input`, then replace a random subtree with a fresh derivation of
+    /// the same nonterminal, using seed for random
+    pub fn mutate_seeded<R: Rng>(&self, rng: &mut R, input: &str) -> Result<String, Error> {
+        let tree = self.parse(input)?;
+        Ok(self.mutate_tree_seeded(rng, &tree)?.flatten())
+    }
+
+    /// Mutate `input` with the rng `rng_from_seed` gives for `seed`
+    pub fn mutate_from_seed(&self, input: &str, seed: u64) -> Result<String, Error> {
+        self.mutate_seeded(&mut rng_from_seed(seed), input)
+    }
+
+    /// Mutate `input` randomly
+    pub fn mutate(&self, input: &str) -> Result<String, Error> {
+        self.mutate_seeded(&mut random_rng(), input)
+    }
+
+    /// Replace a random subtree of `tree` with a subtree of `donor` deriving
+    /// the same nonterminal, using seed for random
+    pub fn splice_tree_seeded<R: Rng>(
+        &self,
+        rng: &mut R,
+        tree: &ParseTree<'gram>,
+        donor: &ParseTree<'gram>,
+    ) -> Result<ParseTree<'gram>, Error> {
+        let donations = donor.nonterminals();
+        let candidates = tree.nonterminals()
+            .iter()
+            .enumerate()
+            .filter(|&(_, t)| donations.iter().any(|d| d.lhs == t.lhs))
+            .map(|(n, t)| (n, t.lhs))
+            .collect::<Vec<_>>();
+
+        let (n, lhs) = match rng.choose(&candidates) {
+            Some(&candidate) => candidate,
+            None => {
+                return Err(Error::GenerateError(String::from(
+                    "No nonterminal in common to splice!",
+                )))
+            }
+        };
+        let matching = donations
+            .iter()
+            .filter(|d| d.lhs == lhs)
+            .collect::<Vec<_>>();
+        let donation = rng.choose(&matching)
+            .expect("candidate should have matching donation");
+
+        let mut spliced = tree.clone();
+        *spliced
+            .nonterminal_mut(n)
+            .expect("nonterminal should be in tree") = (**donation).clone();
+        Ok(spliced)
+    }
+
+    /// Parse `input` and `donor`, then replace a random subtree of `input`
+    /// with a subtree of `donor` deriving the same nonterminal, using seed for random
+    pub fn splice_seeded<R: Rng>(
+        &self,
+        rng: &mut R,
+        input: &str,
+        donor: &str,
+    ) -> Result<String, Error> {
+        let tree = self.parse(input)?;
+        let donor = self.parse(donor)?;
+        Ok(self.splice_tree_seeded(rng, &tree, &donor)?.flatten())
+    }
+
+    /// Splice `donor` into `input` with the rng `rng_from_seed` gives for `seed`
+    pub fn splice_from_seed(&self, input: &str, donor: &str, seed: u64) -> Result<String, Error> {
+        self.splice_seeded(&mut rng_from_seed(seed), input, donor)
+    }
+
+    /// Splice `donor` into `input` randomly
+    pub fn splice(&self, input: &str, donor: &str) -> Result<String, Error> {
+        self.splice_seeded(&mut random_rng(), input, donor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn arithmetic() -> Grammar {
+        Grammar::from_str(
+            "<expr> ::= <term> | <expr> \"+\" <term>
+            <term> ::= <factor> | <term> \"*\" <factor>
+            <factor> ::= \"(\" <expr> \")\" | <digit>
+            <digit> ::= \"0\" | \"1\" | \"2\" | \"3\"",
+        ).unwrap()
+    }
+
+    fn mutator(grammar: &Grammar) -> Mutator<'_> {
+        let mut generator = Generator::new(grammar);
+        generator.max_expansions = 1_000;
+        Mutator::from_generator(generator)
+    }
+
+    #[test]
+    fn mutants_are_sentences() {
+        let grammar = arithmetic();
+        let mutator = mutator(&grammar);
+        let mut mutants = HashSet::new();
+        for seed in 0..100 {
+            match mutator.mutate_from_seed("(1+2)*3", seed) {
+                Ok(mutant) => {
+                    assert!(grammar.parse_input(&mutant).is_ok(), "{}", mutant);
+                    mutants.insert(mutant);
+                }
+                Err(Error::RecursionLimit(_)) => (),
+                e => panic!("should be Ok or Error::RecursionLimit: {:?}", e),
+            }
+        }
+        assert!(mutants.len() > 10, "{:?}", mutants);
+    }
+
+    #[test]
+    fn mutate_reproducible_from_seed() {
+        let grammar = arithmetic();
+        let mutator = mutator(&grammar);
+        assert_eq!(
+            mutator.mutate_from_seed("1*2+3", 7),
+            mutator.mutate_from_seed("1*2+3", 7)
+        );
+    }
+
+    #[test]
+    fn splices_from_donor() {
+        let grammar = arithmetic();
+        let mutator = Mutator::new(&grammar);
+        let mut spliced = HashSet::new();
+        for seed in 0..100 {
+            let s = mutator.splice_from_seed("1+1", "(2*3)", seed).unwrap();
+            assert!(grammar.parse_input(&s).is_ok(), "{}", s);
+            assert!(s.chars().all(|c| "1+(2*3)".contains(c)), "{}", s);
+            spliced.insert(s);
+        }
+        assert!(spliced.contains("(2*3)+1"), "{:?}", spliced);
+        assert!(spliced.contains("1+2"), "{:?}", spliced);
+    }
+
+    #[test]
+    fn mutate_unparseable() {
+        let grammar = arithmetic();
+        let mutator = Mutator::new(&grammar);
+        match mutator.mutate("1+") {
+            Err(Error::ParseError(_)) => (),
+            e => panic!("should be Error::ParseError: {:?}", e),
+        }
+        assert!(mutator.splice("1", "+").is_err());
+    }
+
+    #[test]
+    fn mutate_recursion_limit() {
+        let grammar = Grammar::from_str("<start> ::= \"x\" | <start> <start> | <loop>\n<loop> ::= <loop>").unwrap();
+        let mut generator = Generator::new(&grammar);
+        generator.max_expansions = 100;
+        let mutator = Mutator::from_generator(generator);
+        for seed in 0..20 {
+            match mutator.mutate_from_seed("xx", seed) {
+                Ok(_) | Err(Error::RecursionLimit(_)) => (),
+                e => panic!("should be Ok or Error::RecursionLimit: {:?}", e),
+            }
+        }
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use rand::Rng;
+use generate::{random_rng, rng_from_seed, Generator};
+use grammar::Grammar;
+use parse_tree::{ParseTree, ParseTreeNode};
+use regex::Regexes;
+use term::Term;
+use error::Error;
+
+/// Number of sentences corrupted before giving up on finding a near miss
+const NEAR_MISS_ATTEMPTS: usize = 64;
+
+/// How a valid sentence was corrupted into a near miss
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub enum Corruption {
+    /// A non-empty terminal was removed
+    DropTerminal,
+    /// A non-empty terminal was repeated
+    DuplicateTerminal,
+    /// A terminal was replaced with one from another alternative of the
+    /// same production
+    SwapTerminal,
+    /// A terminal which may follow some nonterminal was inserted after a
+    /// nonterminal it may not follow
+    InsertFollow,
+}
+
+const CORRUPTIONS: [Corruption; 4] = [
+    Corruption::DropTerminal,
+    Corruption::DuplicateTerminal,
+    Corruption::SwapTerminal,
+    Corruption::InsertFollow,
+];
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Corruption::DropTerminal => "drop terminal",
+            Corruption::DuplicateTerminal => "duplicate terminal",
+            Corruption::SwapTerminal => "swap terminal",
+            Corruption::InsertFollow => "insert follow",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A NearMiss is a sentence just outside the language of a `Grammar`:
+/// a valid sentence with a single `Corruption` applied, which the grammar
+/// has been confirmed to reject
+#[derive(PartialEq, Debug, Clone)]
+pub struct NearMiss {
+    /// The rejected sentence
+    pub sentence: String,
+    /// The valid sentence it was corrupted from
+    pub original: String,
+    /// The corruption applied
+    pub corruption: Corruption,
+}
+
+/// The terminals a derivation's sentence is made of, and for each derived
+/// nonterminal, how many of those terminals precede its end
+struct Leaves<'a, 'gram: 'a> {
+    texts: Vec<&'a str>,
+    parents: Vec<&'a ParseTree<'gram>>,
+    ends: Vec<(&'gram Term, usize)>,
+}
+
+impl<'a, 'gram> Leaves<'a, 'gram> {
+    fn new(tree: &'a ParseTree<'gram>) -> Leaves<'a, 'gram> {
+        let mut leaves = Leaves {
+            texts: vec![],
+            parents: vec![],
+            ends: vec![],
+        };
+        let mut stack = vec![(tree, tree.children_iter())];
+        loop {
+            let (node, child) = match stack.last_mut() {
+                Some(&mut (node, ref mut children)) => (node, children.next()),
+                None => return leaves,
+            };
+            match child {
+                None => {
+                    stack.pop();
+                    leaves.ends.push((node.lhs, leaves.texts.len()));
+                }
+                Some(child) => match *child {
+                    ParseTreeNode::Terminal(ref t) => {
+                        leaves.texts.push(t);
+                        leaves.parents.push(node);
+                    }
+                    ParseTreeNode::Nonterminal(ref child) => {
+                        stack.push((child, child.children_iter()));
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Non-empty terminals which may begin, and which may follow, the
+/// derivation of each nonterminal
+struct Follow<'gram> {
+    first: HashMap<&'gram Term, BTreeSet<String>>,
+    follow: HashMap<&'gram Term, BTreeSet<String>>,
+}
+
+impl<'gram> Follow<'gram> {
+    fn new(grammar: &'gram Grammar, regexes: &Regexes) -> Follow<'gram> {
+        let indices = grammar.production_indices();
+        let productions = indices
+            .values()
+            .map(|&index| grammar.production(index))
+            .collect::<Vec<_>>();
+
+        let mut nullable: HashMap<&Term, bool> = HashMap::new();
+        let mut sets = Follow {
+            first: HashMap::new(),
+            follow: HashMap::new(),
+        };
+        for production in &productions {
+            nullable.insert(&production.lhs, false);
+            sets.first.insert(&production.lhs, BTreeSet::new());
+            sets.follow.insert(&production.lhs, BTreeSet::new());
+        }
+
+        // first terminals of a sequence of terms, and whether it may derive ""
+        let first_of = |terms: &[Term],
+                        nullable: &HashMap<&Term, bool>,
+                        first: &HashMap<&Term, BTreeSet<String>>| {
+            let mut set = BTreeSet::new();
+            for term in terms {
+                if let Some(&is_nullable) = nullable.get(term) {
+                    set.extend(first[term].iter().cloned());
+                    if !is_nullable {
+                        return (set, false);
+                    }
+                } else if !matches_empty(regexes, term) {
+                    let text = literal(regexes, term);
+                    if !text.is_empty() {
+                        set.insert(text);
+                    }
+                    return (set, false);
+                }
+            }
+            (set, true)
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for production in &productions {
+                for expression in production.rhs_iter() {
+                    let terms = expression.terms_iter().cloned().collect::<Vec<_>>();
+                    let (first, is_nullable) = first_of(&terms, &nullable, &sets.first);
+                    let lhs = &production.lhs;
+                    if is_nullable && !nullable[lhs] {
+                        nullable.insert(lhs, true);
+                        changed = true;
+                    }
+                    if !first.is_subset(&sets.first[lhs]) {
+                        sets.first.get_mut(lhs).unwrap().extend(first);
+                        changed = true;
+                    }
+
+                    for (i, term) in terms.iter().enumerate() {
+                        if !sets.follow.contains_key(term) {
+                            continue;
+                        }
+                        let (mut follow, rest_nullable) =
+                            first_of(&terms[i + 1..], &nullable, &sets.first);
+                        if rest_nullable {
+                            follow.extend(sets.follow[lhs].iter().cloned());
+                        }
+                        if !follow.is_subset(&sets.follow[term]) {
+                            sets.follow.get_mut(term).unwrap().extend(follow);
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        sets
+    }
+}
+
+/// Whether a term which is not expanded may contribute "" to sentences
+fn matches_empty(regexes: &Regexes, term: &Term) -> bool {
+    match *term {
+        Term::Terminal(ref t) => t.is_empty(),
+        Term::Regex(ref pattern) => regexes.shortest(pattern) == Some(""),
+        _ => false,
+    }
+}
+
+/// Text a term contributes to sentences when it is not expanded
+fn literal(regexes: &Regexes, term: &Term) -> String {
+    match *term {
+        Term::Terminal(ref t) => t.clone(),
+        Term::Nonterminal(_) => term.to_string(),
+        Term::Regex(ref pattern) => regexes
+            .shortest(pattern)
+            .map(String::from)
+            .unwrap_or_default(),
+        Term::Class(_) | Term::Builtin(_) => term.class()
+            .and_then(|class| class.representative())
+            .map(String::from)
+            .unwrap_or_default(),
+        Term::Group(_) | Term::Repeat(..) => unreachable!("grammar should be desugared"),
+    }
+}
+
+/// Apply `corruption` to the sentence `tree` derives, if it is applicable
+fn corrupt<R: Rng>(
+    rng: &mut R,
+    grammar: &Grammar,
+    follow: &Follow,
+    tree: &ParseTree,
+    corruption: Corruption,
+) -> Option<String> {
+    let leaves = Leaves::new(tree);
+    let mut texts = leaves
+        .texts
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>();
+    let nonempty = (0..texts.len())
+        .filter(|&i| !texts[i].is_empty())
+        .collect::<Vec<_>>();
+
+    match corruption {
+        Corruption::DropTerminal => {
+            let i = *rng.choose(&nonempty)?;
+            texts.remove(i);
+        }
+        Corruption::DuplicateTerminal => {
+            let i = *rng.choose(&nonempty)?;
+            let text = texts[i].clone();
+            texts.insert(i, text);
+        }
+        Corruption::SwapTerminal => {
+            let swaps = (0..texts.len())
+                .flat_map(|i| {
+                    let parent = leaves.parents[i];
+                    let index = grammar
+                        .production_index(parent.lhs)
+                        .expect("derived nonterminal should have production");
+                    grammar
+                        .production(index)
+                        .rhs_iter()
+                        .filter(move |expression| *expression != parent.rhs)
+                        .flat_map(|expression| expression.terms_iter())
+                        .filter_map(move |term| match *term {
+                            Term::Terminal(ref t) if !t.is_empty() => Some((i, t.clone())),
+                            _ => None,
+                        })
+                })
+                .filter(|&(i, ref t)| *t != texts[i])
+                .collect::<Vec<_>>();
+            let (i, ref text) = *rng.choose(&swaps)?;
+            texts[i] = text.clone();
+        }
+        Corruption::InsertFollow => {
+            let insertions = leaves
+                .ends
+                .iter()
+                .flat_map(|&(lhs, end)| {
+                    let allowed = &follow.follow[lhs];
+                    follow
+                        .follow
+                        .values()
+                        .flat_map(|set| set.iter())
+                        .filter(move |t| !allowed.contains(*t))
+                        .map(move |t| (end, t))
+                })
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let (end, text) = *rng.choose(&insertions)?;
+            texts.insert(end, text.clone());
+        }
+    }
+
+    Some(texts.concat())
+}
+
+impl Grammar {
+    /// Generate a near miss from seed for random: a sentence derived from
+    /// self and then corrupted, such that self rejects it.
+    ///
+    /// Returns `Error::GenerateError` if no corruption of several sentences
+    /// falls outside the language.
+    pub fn generate_near_miss_seeded<R: Rng>(&self, rng: &mut R) -> Result<NearMiss, Error> {
+        // corruptions are found among plain productions
+        let grammar = self.desugar();
+        let generator = Generator::new(&grammar);
+        let follow = Follow::new(&grammar, generator.shortest().regexes());
+
+        for _ in 0..NEAR_MISS_ATTEMPTS {
+            let tree = match generator.generate_tree_seeded(rng) {
+                Ok(tree) => tree,
+                Err(Error::RecursionLimit(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            let corruption = *rng.choose(&CORRUPTIONS).expect("corruptions are not empty");
+            if let Some(sentence) = corrupt(rng, &grammar, &follow, &tree, corruption) {
+                if !grammar.recognizes(&sentence) {
+                    return Ok(NearMiss {
+                        sentence,
+                        original: tree.flatten(),
+                        corruption,
+                    });
+                }
+            }
+        }
+
+        Err(Error::GenerateError(format!(
+            "No near miss found in {} attempts!",
+            NEAR_MISS_ATTEMPTS
+        )))
+    }
+
+    /// Generate a near miss from the rng `rng_from_seed` gives for `seed`.
+    /// See `generate_near_miss_seeded`.
+    pub fn generate_near_miss_from_seed(&self, seed: u64) -> Result<NearMiss, Error> {
+        self.generate_near_miss_seeded(&mut rng_from_seed(seed))
+    }
+
+    /// Generate a near miss: a sentence derived from self and then
+    /// corrupted, such that self rejects it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<pair> ::= \"(\" <dna> \",\" <dna> \")\"
+    ///         <dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     match grammar.generate_near_miss() {
+    ///         Ok(miss) => println!("{} ({} of {})", miss.sentence, miss.corruption, miss.original),
+    ///         Err(e) => println!("something went wrong: {}!", e),
+    ///     }
+    ///
+    ///     # let miss = grammar.generate_near_miss().unwrap();
+    ///     # assert!(!grammar.recognizes(&miss.sentence));
+    /// }
+    /// ```
+    pub fn generate_near_miss(&self) -> Result<NearMiss, Error> {
+        self.generate_near_miss_seeded(&mut random_rng())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn pair() -> Grammar {
+        Grammar::from_str(
+            "<pair> ::= \"(\" <dna> \",\" <dna> \")\" | \"[\" <dna> \"]\"
+            <dna> ::= <base> | <base> <dna>
+            <base> ::= \"A\" | \"C\" | \"G\" | \"T\"",
+        ).unwrap()
+    }
+
+    #[test]
+    fn near_misses_are_rejected() {
+        let grammar = pair();
+        let mut corruptions = HashSet::new();
+        for seed in 0..100 {
+            let miss = grammar.generate_near_miss_from_seed(seed).unwrap();
+            assert!(!grammar.recognizes(&miss.sentence), "{:?}", miss);
+            assert!(grammar.recognizes(&miss.original), "{:?}", miss);
+            corruptions.insert(miss.corruption);
+        }
+        assert_eq!(corruptions.len(), CORRUPTIONS.len());
+    }
+
+    #[test]
+    fn near_miss_reproducible_from_seed() {
+        let grammar = pair();
+        assert_eq!(
+            grammar.generate_near_miss_from_seed(3),
+            grammar.generate_near_miss_from_seed(3)
+        );
+    }
+
+    #[test]
+    fn follow_sets() {
+        let grammar = pair();
+        let follow = Follow::new(&grammar, &Regexes::new(&grammar));
+        let dna = Term::Nonterminal(String::from("dna"));
+        let base = Term::Nonterminal(String::from("base"));
+        let set = |s: &[&str]| s.iter().map(|t| t.to_string()).collect::<BTreeSet<_>>();
+
+        assert_eq!(follow.first[&dna], set(&["A", "C", "G", "T"]));
+        assert_eq!(follow.follow[&dna], set(&[")", ",", "]"]));
+        assert_eq!(follow.follow[&base], set(&[")", ",", "A", "C", "G", "T", "]"]));
+    }
+
+    #[test]
+    fn follow_past_empty_regexes() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <a> /x?/ \"b\" | <a> /y*/
+            <a> ::= /z*/ \"a\"",
+        ).unwrap();
+        let follow = Follow::new(&grammar, &Regexes::new(&grammar));
+        let s = Term::Nonterminal(String::from("s"));
+        let a = Term::Nonterminal(String::from("a"));
+        let set = |s: &[&str]| s.iter().map(|t| t.to_string()).collect::<BTreeSet<_>>();
+
+        assert_eq!(follow.first[&s], set(&["a"]));
+        assert_eq!(follow.first[&a], set(&["a"]));
+        assert_eq!(follow.follow[&a], set(&["b"]));
+    }
+
+    #[test]
+    fn no_near_miss() {
+        let grammar = Grammar::from_str("<as> ::= \"\" | \"a\" <as>").unwrap();
+        match grammar.generate_near_miss_from_seed(0) {
+            Err(Error::GenerateError(_)) => (),
+            e => panic!("should be Error::GenerateError: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,429 @@
+use std::fmt;
+use std::mem;
+use std::slice;
+use expression::Expression;
+use term::Term;
+
+/// A ParseTree is the derivation of a sentence from a `Grammar`.
+/// Each node records the nonterminal expanded and the `Expression`
+/// alternative chosen to expand it.
+///
+/// Trees are as deep as the sentences they derive by right recursion are
+/// long, so they are compared, cloned, displayed and dropped a subtree at a
+/// time rather than within one another, whatever their depth.
+pub struct ParseTree<'gram> {
+    pub lhs: &'gram Term,
+    pub rhs: &'gram Expression,
+    children: Vec<ParseTreeNode<'gram>>,
+}
+
+/// A ParseTreeNode is either a terminal leaf, holding the text it
+/// contributes to the sentence, or the derivation of a nonterminal
+#[derive(PartialEq, Debug, Clone)]
+pub enum ParseTreeNode<'gram> {
+    Terminal(String),
+    Nonterminal(ParseTree<'gram>),
+}
+
+impl<'gram> ParseTree<'gram> {
+    /// Construct a `ParseTree` from the expansion of `lhs` by `rhs`, with
+    /// one child for each `Term` of `rhs`. Groups and repeats have none of
+    /// their own; the children of their terms take their place. Under a
+    /// `Layout`, derivations of its whitespace come between them.
+    pub fn new(
+        lhs: &'gram Term,
+        rhs: &'gram Expression,
+        children: Vec<ParseTreeNode<'gram>>,
+    ) -> ParseTree<'gram> {
+        ParseTree { lhs, rhs, children }
+    }
+
+    pub(crate) fn push_child(&mut self, child: ParseTreeNode<'gram>) {
+        self.children.push(child)
+    }
+
+    /// Get iterator of the `ParseTree`'s children
+    pub fn children_iter(&self) -> slice::Iter<'_, ParseTreeNode<'gram>> {
+        self.children.iter()
+    }
+
+    /// Get mutable iterator of the `ParseTree`'s children
+    pub fn children_iter_mut(&mut self) -> slice::IterMut<'_, ParseTreeNode<'gram>> {
+        self.children.iter_mut()
+    }
+
+    /// Concatenate the terminal leaves of the tree into the sentence it derives
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let tree = grammar.generate_tree().unwrap();
+    ///     println!("{} derives {}", tree, tree.flatten());
+    ///
+    ///     # assert!(!tree.flatten().is_empty());
+    /// }
+    /// ```
+    pub fn flatten(&self) -> String {
+        let mut sentence = String::new();
+        let mut stack = vec![self.children.iter()];
+        while let Some(children) = stack.last_mut().map(|children| children.next()) {
+            match children {
+                Some(ParseTreeNode::Terminal(t)) => sentence.push_str(t),
+                Some(ParseTreeNode::Nonterminal(tree)) => stack.push(tree.children.iter()),
+                None => {
+                    stack.pop();
+                }
+            }
+        }
+        sentence
+    }
+
+    /// Every derivation of a nonterminal within the tree, self included, in
+    /// depth first order
+    pub(crate) fn nonterminals(&self) -> Vec<&ParseTree<'gram>> {
+        let mut nonterminals = vec![];
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            nonterminals.push(tree);
+            for child in tree.children.iter().rev() {
+                if let ParseTreeNode::Nonterminal(ref t) = *child {
+                    stack.push(t);
+                }
+            }
+        }
+        nonterminals
+    }
+
+    /// The `n`th derivation `nonterminals` would give, to be modified
+    pub(crate) fn nonterminal_mut(&mut self, mut n: usize) -> Option<&mut ParseTree<'gram>> {
+        let mut stack = vec![self];
+        while let Some(tree) = stack.pop() {
+            if n == 0 {
+                return Some(tree);
+            }
+            n -= 1;
+            for child in tree.children.iter_mut().rev() {
+                if let ParseTreeNode::Nonterminal(ref mut t) = *child {
+                    stack.push(t);
+                }
+            }
+        }
+        None
+    }
+
+    /// The derivation reached by following `path`, a child index per level
+    pub(crate) fn subtree(&self, path: &[usize]) -> Option<&ParseTree<'gram>> {
+        let mut tree = self;
+        for &child in path {
+            tree = match tree.children.get(child) {
+                Some(ParseTreeNode::Nonterminal(t)) => t,
+                _ => return None,
+            };
+        }
+        Some(tree)
+    }
+
+    /// The derivation reached by following `path`, to be modified
+    pub(crate) fn subtree_mut(&mut self, path: &[usize]) -> Option<&mut ParseTree<'gram>> {
+        let mut tree = self;
+        for &child in path {
+            tree = match tree.children.get_mut(child) {
+                Some(&mut ParseTreeNode::Nonterminal(ref mut t)) => t,
+                _ => return None,
+            };
+        }
+        Some(tree)
+    }
+}
+
+impl<'gram> PartialEq for ParseTree<'gram> {
+    fn eq(&self, other: &ParseTree<'gram>) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some((a, b)) = stack.pop() {
+            if a.lhs != b.lhs || a.rhs != b.rhs || a.children.len() != b.children.len() {
+                return false;
+            }
+            for pair in a.children.iter().zip(b.children.iter()) {
+                match pair {
+                    (ParseTreeNode::Terminal(x), ParseTreeNode::Terminal(y)) if x == y => (),
+                    (ParseTreeNode::Nonterminal(x), ParseTreeNode::Nonterminal(y)) => {
+                        stack.push((x, y))
+                    }
+                    _ => return false,
+                }
+            }
+        }
+        true
+    }
+}
+
+impl<'gram> Clone for ParseTree<'gram> {
+    fn clone(&self) -> ParseTree<'gram> {
+        // each tree being cloned: its clone so far, and its children left
+        let mut stack = vec![(ParseTree::new(self.lhs, self.rhs, vec![]), self.children.iter())];
+        loop {
+            let child = stack.last_mut().expect("stack should not be empty").1.next();
+            match child {
+                Some(ParseTreeNode::Terminal(t)) => {
+                    let clone = &mut stack.last_mut().expect("stack should not be empty").0;
+                    clone.children.push(ParseTreeNode::Terminal(t.clone()));
+                }
+                Some(ParseTreeNode::Nonterminal(tree)) => {
+                    stack.push((ParseTree::new(tree.lhs, tree.rhs, vec![]), tree.children.iter()));
+                }
+                None => {
+                    let (clone, _) = stack.pop().expect("stack should not be empty");
+                    match stack.last_mut() {
+                        Some(parent) => parent.0.children.push(ParseTreeNode::Nonterminal(clone)),
+                        None => return clone,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Debug output of `value` nested `level` values deep, as `{:#?}` writes it
+fn indented<T: fmt::Debug>(value: &T, level: usize) -> String {
+    format!("{:#?}", value).replace('\n', &format!("\n{}", "    ".repeat(level)))
+}
+
+impl<'gram> fmt::Debug for ParseTree<'gram> {
+    /// Written as it would be derived
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pretty = f.alternate();
+        // each tree being written, and its children left
+        let mut stack = vec![];
+        let mut next = Some(self);
+        loop {
+            if let Some(tree) = next.take() {
+                if pretty {
+                    let level = 3 * stack.len() + 1;
+                    let field = "    ".repeat(level);
+                    write!(f, "ParseTree {{\n{}lhs: {},\n", field, indented(&tree.lhs, level))?;
+                    write!(f, "{}rhs: {},\n{}children: [", field, indented(&tree.rhs, level), field)?;
+                } else {
+                    write!(f, "ParseTree {{ lhs: {:?}, rhs: {:?}, children: [", tree.lhs, tree.rhs)?;
+                }
+                stack.push((tree, tree.children.iter().enumerate()));
+            }
+
+            // each tree nests three values deep: its fields, its children,
+            // and the derivation within a nonterminal child
+            let level = 3 * (stack.len() - 1);
+            let child = stack.last_mut().expect("stack should not be empty").1.next();
+            match child {
+                Some((i, child)) => {
+                    if pretty {
+                        write!(f, "\n{}", "    ".repeat(level + 2))?;
+                    } else if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match (child, pretty) {
+                        (ParseTreeNode::Terminal(_), true) => {
+                            write!(f, "{},", indented(child, level + 2))?
+                        }
+                        (ParseTreeNode::Terminal(_), false) => write!(f, "{:?}", child)?,
+                        (ParseTreeNode::Nonterminal(tree), true) => {
+                            write!(f, "Nonterminal(\n{}", "    ".repeat(level + 3))?;
+                            next = Some(tree);
+                        }
+                        (ParseTreeNode::Nonterminal(tree), false) => {
+                            f.write_str("Nonterminal(")?;
+                            next = Some(tree);
+                        }
+                    }
+                }
+                None => {
+                    let (tree, _) = stack.pop().expect("stack should not be empty");
+                    if pretty {
+                        if !tree.children.is_empty() {
+                            write!(f, "\n{}", "    ".repeat(level + 1))?;
+                        }
+                        write!(f, "],\n{}}}", "    ".repeat(level))?;
+                    } else {
+                        f.write_str("] }")?;
+                    }
+                    match (stack.is_empty(), pretty) {
+                        (true, _) => return Ok(()),
+                        (false, true) => write!(f, ",\n{}),", "    ".repeat(level - 1))?,
+                        (false, false) => f.write_str(")")?,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'gram> Drop for ParseTree<'gram> {
+    fn drop(&mut self) {
+        let mut children = mem::take(&mut self.children);
+        while let Some(child) = children.pop() {
+            if let ParseTreeNode::Nonterminal(mut tree) = child {
+                children.append(&mut tree.children);
+            }
+        }
+    }
+}
+
+impl<'gram> fmt::Display for ParseTree<'gram> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} ::= {}", self.lhs, self.rhs)?;
+        // the children left of each tree being displayed, and the length of
+        // the prefix of their lines, which is kept as one string
+        let mut stack = vec![(self.children.iter().peekable(), 0)];
+        let mut prefix = String::new();
+        while let Some(&mut (ref mut children, length)) = stack.last_mut() {
+            prefix.truncate(length);
+            let child = match children.next() {
+                Some(child) => child,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+            let (branch, indent) = match children.peek() {
+                None => ("└── ", "    "),
+                Some(_) => ("├── ", "│   "),
+            };
+            write!(f, "{}{}", prefix, branch)?;
+            match *child {
+                ParseTreeNode::Terminal(ref t) => writeln!(f, "{}", Term::Terminal(t.clone()))?,
+                ParseTreeNode::Nonterminal(ref tree) => {
+                    writeln!(f, "{} ::= {}", tree.lhs, tree.rhs)?;
+                    prefix.push_str(indent);
+                    stack.push((tree.children.iter().peekable(), prefix.len()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Write;
+
+    #[test]
+    fn flatten_and_display() {
+        let dna = Term::Nonterminal(String::from("dna"));
+        let base = Term::Nonterminal(String::from("base"));
+        let dna_rhs = Expression::from_parts(vec![base.clone(), dna.clone()]);
+        let dna_last = Expression::from_parts(vec![base.clone()]);
+        let a = Expression::from_parts(vec![Term::Terminal(String::from("A"))]);
+        let g = Expression::from_parts(vec![Term::Terminal(String::from("G"))]);
+
+        let leaf = |rhs, text: &str| {
+            ParseTreeNode::Nonterminal(ParseTree::new(
+                &base,
+                rhs,
+                vec![ParseTreeNode::Terminal(String::from(text))],
+            ))
+        };
+        let tree = ParseTree::new(
+            &dna,
+            &dna_rhs,
+            vec![
+                leaf(&a, "A"),
+                ParseTreeNode::Nonterminal(ParseTree::new(&dna, &dna_last, vec![leaf(&g, "G")])),
+            ],
+        );
+
+        assert_eq!(tree.flatten(), "AG");
+        assert_eq!(tree.children_iter().count(), 2);
+        let display = [
+            "<dna> ::= <base> <dna>",
+            "├── <base> ::= \"A\"",
+            "│   └── \"A\"",
+            "└── <dna> ::= <base>",
+            "    └── <base> ::= \"G\"",
+            "        └── \"G\"",
+        ];
+        assert_eq!(tree.to_string(), display.join("\n") + "\n");
+
+        let lhs = tree.nonterminals()
+            .iter()
+            .map(|t| t.lhs.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(lhs, vec!["<dna>", "<base>", "<dna>", "<base>"]);
+
+        let mut tree = tree.clone();
+        *tree.nonterminal_mut(3).unwrap() =
+            ParseTree::new(&base, &a, vec![ParseTreeNode::Terminal(String::from("A"))]);
+        assert_eq!(tree.flatten(), "AA");
+        assert!(tree.nonterminal_mut(4).is_none());
+
+        assert_eq!(tree.subtree(&[1]).unwrap().lhs, &dna);
+        assert_eq!(tree.subtree_mut(&[1, 0]).unwrap().flatten(), "A");
+        assert_eq!(tree.subtree_mut(&[]).unwrap().flatten(), "AA");
+        assert!(tree.subtree_mut(&[0, 0]).is_none());
+    }
+
+    /// Counts what is written, as displaying a deep tree writes more than
+    /// can be kept
+    struct Length(usize);
+
+    impl fmt::Write for Length {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn deep_trees() {
+        let s = Term::Nonterminal(String::from("s"));
+        let recurse = Expression::from_parts(vec![s.clone()]);
+        let end = Expression::from_parts(vec![Term::Terminal(String::from("a"))]);
+        let chain = |depth: usize| {
+            let mut tree = ParseTree::new(&s, &end, vec![ParseTreeNode::Terminal(String::from("a"))]);
+            for _ in 1..depth {
+                tree = ParseTree::new(&s, &recurse, vec![ParseTreeNode::Nonterminal(tree)]);
+            }
+            tree
+        };
+        // each line past the first is indented by four bytes a level,
+        // with a three character branch of ten bytes
+        let length = |depth: usize| {
+            let lines = (1..depth).map(|level| 4 * (level - 1) + 10 + 12).sum::<usize>();
+            12 + lines + 4 * (depth - 1) + 10 + 4
+        };
+
+        let shallow = chain(3);
+        let display = [
+            "<s> ::= <s>",
+            "└── <s> ::= <s>",
+            "    └── <s> ::= \"a\"",
+            "        └── \"a\"",
+        ];
+        assert_eq!(shallow.to_string(), display.join("\n") + "\n");
+        assert_eq!(shallow.to_string().len(), length(3));
+        assert_eq!(
+            format!("{:?}", shallow.subtree(&[0, 0]).unwrap()),
+            "ParseTree { lhs: Nonterminal(\"s\"), rhs: Expression { terms: [Terminal(\"a\")] }, \
+             children: [Terminal(\"a\")] }"
+        );
+
+        let depth = 100_000;
+        let tree = chain(depth);
+        let copy = tree.clone();
+        assert_eq!(copy, tree);
+        assert_ne!(copy, chain(depth - 1));
+
+        let mut written = Length(0);
+        write!(written, "{}", copy).unwrap();
+        assert_eq!(written.0, length(depth));
+        let mut written = Length(0);
+        write!(written, "{:?}", copy).unwrap();
+        assert!(written.0 > depth);
+    }
+}
@@ -1,3 +1,7 @@
+use nom::{ErrorKind, IResult, Needed};
+use automaton::Nfa;
+use class::{Builtin, CharClass};
+use error::Error;
 use term::Term;
 use expression::Expression;
 use production::Production;
@@ -29,7 +33,136 @@ named!(pub nonterminal< &[u8], Term >,
     )
 );
 
-named!(pub term< &[u8], Term >, alt!(terminal | nonterminal));
+/// The body of a class or regex, up to its first unescaped `close`
+fn body(input: &[u8], close: u8) -> IResult<&[u8], &[u8]> {
+    let mut escaped = false;
+    for (i, &byte) in input.iter().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' => escaped = true,
+            _ if byte == close => return IResult::Done(&input[i..], &input[..i]),
+            _ => (),
+        }
+    }
+    IResult::Incomplete(Needed::Unknown)
+}
+
+fn char_class(body: &[u8]) -> Result<CharClass, String> {
+    let mut chars = String::from_utf8_lossy(body).chars().collect::<Vec<_>>();
+    chars.push(']');
+    CharClass::parse(&chars).map(|(class, _)| class)
+}
+
+named!(pub class< &[u8], Term >,
+    do_parse!(
+        body: delimited!(char!('['), call!(body, b']'), ws!(char!(']'))) >>
+        class: expr_res!(char_class(body)) >>
+        (Term::Class(class))
+    )
+);
+
+fn regex_pattern(pattern: &[u8]) -> Result<String, Error> {
+    let pattern = String::from_utf8_lossy(pattern).into_owned();
+    Nfa::from_regex(&pattern)?;
+    Ok(pattern)
+}
+
+named!(pub regex< &[u8], Term >,
+    do_parse!(
+        pattern: delimited!(char!('/'), call!(body, b'/'), ws!(char!('/'))) >>
+        pattern: expr_res!(regex_pattern(pattern)) >>
+        (Term::Regex(pattern))
+    )
+);
+
+/// The name of a builtin as a whole word, so that `DIGITS` or `DIGITALPHA`
+/// are not read as a builtin followed by more
+fn builtin_name(input: &[u8]) -> IResult<&[u8], Builtin> {
+    let length = input
+        .iter()
+        .take_while(|&&byte| byte.is_ascii_alphanumeric() || byte == b'_')
+        .count();
+    match Builtin::from_name(&String::from_utf8_lossy(&input[..length])) {
+        Some(builtin) => IResult::Done(&input[length..], builtin),
+        None => IResult::Error(error_position!(ErrorKind::Custom(0), input)),
+    }
+}
+
+named!(pub builtin< &[u8], Term >,
+    do_parse!(
+        builtin: ws!(call!(builtin_name)) >>
+        (Term::Builtin(builtin))
+    )
+);
+
+named!(pub group< &[u8], Term >,
+    do_parse!(
+        ws!(char!('(')) >>
+        alternatives: separated_nonempty_list!(ws!(char!('|')), group_alternative) >>
+        ws!(char!(')')) >>
+        (Term::Group(alternatives))
+    )
+);
+
+named!(group_alternative< &[u8], Expression >,
+    do_parse!(
+        terms: many1!(term) >>
+        (Expression::from_parts(terms))
+    )
+);
+
+/// A count of repetitions in braces, after the opening brace
+fn count(input: &[u8]) -> Option<(usize, &[u8])> {
+    let digits = input.iter().take_while(|byte| byte.is_ascii_digit()).count();
+    let count = String::from_utf8_lossy(&input[..digits]).parse().ok()?;
+    Some((count, &input[digits..]))
+}
+
+/// The minimum and maximum number of repetitions a quantifier allows,
+/// followed by any whitespace
+fn quantifier(input: &[u8]) -> IResult<&[u8], (usize, Option<usize>)> {
+    let error = IResult::Error(error_position!(ErrorKind::Custom(0), input));
+    let (repetitions, rest) = match input.first() {
+        Some(&b'?') => ((0, Some(1)), &input[1..]),
+        Some(&b'*') => ((0, None), &input[1..]),
+        Some(&b'+') => ((1, None), &input[1..]),
+        Some(&b'{') => {
+            let (min, rest) = match count(&input[1..]) {
+                Some(parsed) => parsed,
+                None => return error,
+            };
+            let (max, rest) = match rest.first() {
+                Some(&b'}') => (Some(min), rest),
+                Some(&b',') if rest.get(1) == Some(&b'}') => (None, &rest[1..]),
+                Some(&b',') => match count(&rest[1..]) {
+                    Some((max, rest)) if max >= min => (Some(max), rest),
+                    _ => return error,
+                },
+                _ => return error,
+            };
+            if rest.first() != Some(&b'}') {
+                return error;
+            }
+            ((min, max), &rest[1..])
+        }
+        _ => return error,
+    };
+    let spaces = rest.iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+    IResult::Done(&rest[spaces..], repetitions)
+}
+
+named!(pub atom< &[u8], Term >, alt!(terminal | nonterminal | class | regex | builtin | group));
+
+named!(pub term< &[u8], Term >,
+    do_parse!(
+        atom: atom >>
+        quantifier: opt!(quantifier) >>
+        (match quantifier {
+            Some((min, max)) => Term::Repeat(Box::new(atom), min, max),
+            None => atom,
+        })
+    )
+);
 
 named!(pub term_complete< &[u8], Term >,
     do_parse!(
@@ -39,6 +172,31 @@ named!(pub term_complete< &[u8], Term >,
     )
 );
 
+/// A comment, from `#` to the end of its line, preceded by any whitespace.
+/// Returns its text without the `#` and a space following it.
+fn comment(input: &[u8]) -> IResult<&[u8], String> {
+    let spaces = input.iter().take_while(|byte| byte.is_ascii_whitespace()).count();
+    let line = &input[spaces..];
+    if line.first() != Some(&b'#') {
+        return IResult::Error(error_position!(ErrorKind::Custom(0), input));
+    }
+    let end = line.iter().position(|&byte| byte == b'\n').unwrap_or(line.len());
+    let mut text = &line[1..end];
+    if text.first() == Some(&b' ') {
+        text = &text[1..];
+    }
+    if text.last() == Some(&b'\r') {
+        text = &text[..text.len() - 1];
+    }
+    let rest = line.get(end + 1..).unwrap_or(&[]);
+    IResult::Done(rest, String::from_utf8_lossy(text).into_owned())
+}
+
+// The lines of comments before a production
+named!(documentation< &[u8], Vec<String> >,
+    terminated!(many1!(comment), peek!(ws!(prod_lhs)))
+);
+
 named!(pub expression_next,
     do_parse!(
         ws!(char!('|')) >>
@@ -55,7 +213,8 @@ named!(pub expression< &[u8], Expression >,
                 recognize!(peek!(complete!(eof!()))) |
                 recognize!(peek!(complete!(char!(';')))) |
                 expression_next |
-                recognize!(peek!(complete!(prod_lhs)))
+                recognize!(peek!(complete!(prod_lhs))) |
+                recognize!(peek!(complete!(comment)))
             )
         ) >>
         (Expression::from_parts(terms))
@@ -70,21 +229,56 @@ named!(pub expression_complete< &[u8], Expression >,
     )
 );
 
-named!(pub production< &[u8], Production >,
+named!(production_body< &[u8], Production >,
     do_parse!(
+        comments: opt!(complete!(documentation)) >>
         lhs: ws!(prod_lhs) >>
         rhs: many1!(expression) >>
         ws!(
             alt!(
                 recognize!(peek!(complete!(eof!()))) |
                 tag!(";") |
-                recognize!(peek!(complete!(prod_lhs)))
+                recognize!(peek!(complete!(prod_lhs))) |
+                recognize!(peek!(complete!(comment)))
             )
         ) >>
-        (Production::from_parts(lhs, rhs))
+        ({
+            let mut production = Production::from_parts(lhs, rhs);
+            production.comment = comments.map(|lines| lines.join("\n"));
+            production
+        })
     )
 );
 
+/// A production, and the comment on the line it ends on if any, which
+/// remarks on the production rather than documenting the next, so is
+/// appended to its comment
+pub fn production(input: &[u8]) -> IResult<&[u8], Production> {
+    match production_body(input) {
+        IResult::Done(rest, production) => {
+            let consumed = &input[..input.len() - rest.len()];
+            let same_line = !consumed
+                .iter()
+                .rev()
+                .take_while(|c| c.is_ascii_whitespace())
+                .any(|&c| c == b'\n');
+            match comment(rest) {
+                IResult::Done(after, remark) if same_line && rest.first() == Some(&b'#') => {
+                    let mut production = production;
+                    production.comment = Some(match production.comment {
+                        Some(comment) => comment + "\n" + &remark,
+                        None => remark,
+                    });
+                    IResult::Done(after, production)
+                }
+                _ => IResult::Done(rest, production),
+            }
+        }
+        IResult::Error(e) => IResult::Error(e),
+        IResult::Incomplete(needed) => IResult::Incomplete(needed),
+    }
+}
+
 named!(pub production_complete< &[u8], Production >,
     do_parse!(
         p: production >>
@@ -104,7 +298,8 @@ named!(pub grammar< &[u8], Grammar >,
 named!(pub grammar_complete< &[u8], Grammar >,
     do_parse!(
         g: grammar >>
-        eof!() >>
+        many0!(comment) >>
+        ws!(eof!()) >>
         (g)
     )
 );
@@ -147,6 +342,84 @@ mod tests {
         );
     }
 
+    #[test]
+    fn class_match() {
+        let parsed = class(b"[^\\]a-c] <rest>").unwrap();
+        assert_eq!(parsed.0, &b"<rest>"[..]);
+        assert_eq!(
+            parsed.1,
+            Term::Class(CharClass::new(vec![(']', ']'), ('a', 'c')], true))
+        );
+    }
+
+    #[test]
+    fn builtin_match() {
+        let parsed = builtin(b"ALPHA  DIGIT").unwrap();
+        assert_eq!(parsed.0, &b"DIGIT"[..]);
+        assert_eq!(parsed.1, Term::Builtin(Builtin::Alpha));
+        assert!(expression(b"ALPHA DIGIT NEWLINE").is_done());
+        assert_eq!(builtin(b"DIGIT").unwrap().1, Term::Builtin(Builtin::Digit));
+        assert!(builtin(b"DIGIT+").is_done());
+        assert!(!builtin(b"DIGITALPHA").is_done());
+        assert!(!builtin(b"ANYTHING").is_done());
+        assert!(!builtin(b"ALPHA_1").is_done());
+        assert!(!production_complete(b"<a> ::= DIGITALPHA").is_done());
+        assert!(!production_complete(b"<a> ::= ANYTHING").is_done());
+    }
+
+    #[test]
+    fn group_match() {
+        let parsed = production(b"<s> ::= (\"a\" | <s>)* \"b\"+ | \"c\"").unwrap().1;
+        let a = Term::Terminal(String::from("a"));
+        let s = Term::Nonterminal(String::from("s"));
+        let group = Term::Group(vec![
+            Expression::from_parts(vec![a]),
+            Expression::from_parts(vec![s.clone()]),
+        ]);
+        let b = Term::Terminal(String::from("b"));
+        let expected = Production::from_parts(
+            s,
+            vec![
+                Expression::from_parts(vec![
+                    Term::Repeat(Box::new(group), 0, None),
+                    Term::Repeat(Box::new(b), 1, None),
+                ]),
+                Expression::from_parts(vec![Term::Terminal(String::from("c"))]),
+            ],
+        );
+        assert_eq!(parsed, expected);
+    }
+
+    #[test]
+    fn comments_match() {
+        let input = "# A list of items,
+            #
+            #separated by commas
+            <list> ::= <item> | <item> \",\" <list>
+            # An item\r
+            <item> ::= \"x\" # comments on the line of a production remark on it
+            # rather than documenting the next
+            <unused> ::= \"y\" ; # nor do these
+            <other> ::= \"z\"
+            # nor do comments at the end\n\n";
+        let parsed = grammar_complete(input.as_bytes()).unwrap().1;
+        let comments = parsed
+            .productions_iter()
+            .map(|production| production.comment.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            vec![
+                Some(String::from("A list of items,\n\nseparated by commas")),
+                Some(String::from(
+                    "An item\ncomments on the line of a production remark on it",
+                )),
+                Some(String::from("rather than documenting the next\nnor do these")),
+                None,
+            ]
+        );
+    }
+
     fn construct_expression_tuple() -> (Expression, String) {
         let nonterminal_tuple = construct_nonterminal_tuple();
         let terminal_tuple = construct_terminal_tuple();
@@ -197,7 +470,7 @@ mod tests {
             construct_production_tuple().0,
         ]);
 
-        (grammar_object, String::from(grammar_pattern))
+        (grammar_object, grammar_pattern)
     }
 
     #[test]
@@ -8,8 +8,13 @@ use parsers;
 use error::Error;
 
 /// A Production is comprised of any number of Expressions
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Production {
+    /// Documentation of the production, written on `#` lines before it.
+    /// Productions differing only in their comments are equal
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub comment: Option<String>,
     pub lhs: Term,
     rhs: Vec<Expression>,
 }
@@ -18,6 +23,7 @@ impl Production {
     /// Construct a new `Production`
     pub fn new() -> Production {
         Production {
+            comment: None,
             lhs: Term::Nonterminal(String::new()),
             rhs: vec![],
         }
@@ -25,10 +31,15 @@ impl Production {
 
     /// Construct an `Production` from `Expression`s
     pub fn from_parts(t: Term, e: Vec<Expression>) -> Production {
-        Production { lhs: t, rhs: e }
+        Production {
+            comment: None,
+            lhs: t,
+            rhs: e,
+        }
     }
 
     // Get `Production` by parsing a string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match parsers::production_complete(s.as_bytes()) {
             IResult::Done(_, o) => Ok(o),
@@ -54,22 +65,39 @@ impl Production {
     }
 
     /// Get iterator of the `Production`'s right hand side `Expression`s
-    pub fn rhs_iter(&self) -> Iter {
+    pub fn rhs_iter(&self) -> Iter<'_> {
         Iter { iterator: self.rhs.iter() }
     }
 
     /// Get mutable iterator of the `Production`'s right hand side `Expression`s
-    pub fn rhs_iter_mut(&mut self) -> IterMut {
+    pub fn rhs_iter_mut(&mut self) -> IterMut<'_> {
         IterMut { iterator: self.rhs.iter_mut() }
     }
 }
 
+impl PartialEq for Production {
+    fn eq(&self, other: &Production) -> bool {
+        self.lhs == other.lhs && self.rhs == other.rhs
+    }
+}
+
+impl Default for Production {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl fmt::Display for Production {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref comment) = self.comment {
+            for line in comment.split('\n') {
+                writeln!(f, "# {}", line)?;
+            }
+        }
         write!(
             f,
             "{} ::= {}",
-            self.lhs.to_string(),
+            self.lhs,
             self.rhs
                 .iter()
                 .map(|s| s.to_string())
@@ -123,16 +151,22 @@ mod tests {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
             let lhs_str = String::arbitrary(g)
                 .chars()
-                .filter(|&c| (c != '>'))
+                .filter(|&c| c != '>')
                 .collect();
 
             let lhs = Term::Nonterminal(lhs_str);
 
             let mut rhs = Vec::<Expression>::arbitrary(g);
-            if rhs.len() < 1 {
+            if rhs.is_empty() {
                 rhs.push(Expression::arbitrary(g));
             }
-            Production { lhs: lhs, rhs: rhs }
+            // comments are lines of their own, which may end with a carriage return
+            let comment = if g.gen_weighted_bool(4) {
+                Some(String::arbitrary(g).chars().filter(|&c| c != '\r').collect())
+            } else {
+                None
+            };
+            Production { comment, lhs, rhs }
         }
     }
 
@@ -140,7 +174,9 @@ mod tests {
         let to_string = prop.to_string();
         let from_str = Production::from_str(&to_string);
         match from_str {
-            Ok(from_prod) => TestResult::from_bool(from_prod == prop),
+            Ok(from_prod) => {
+                TestResult::from_bool(from_prod == prop && from_prod.comment == prop.comment)
+            }
             _ => TestResult::error(format!("{} to string and back should be safe", prop)),
         }
     }
@@ -153,6 +189,26 @@ mod tests {
             .quickcheck(prop_to_string_and_back as fn(Production) -> TestResult)
     }
 
+    #[cfg(feature = "serde")]
+    fn prop_serde_round_trip(prod: Production) -> TestResult {
+        let json = ::serde_json::to_string(&prod).expect("prod should serialize");
+        match ::serde_json::from_str::<Production>(&json) {
+            Ok(from_json) => {
+                TestResult::from_bool(from_json == prod && from_json.comment == prod.comment)
+            }
+            Err(e) => TestResult::error(format!("{} to JSON and back should be safe: {}", json, e)),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        QuickCheck::new()
+            .tests(1000)
+            .gen(StdGen::new(rand::thread_rng(), 25usize))
+            .quickcheck(prop_serde_round_trip as fn(Production) -> TestResult)
+    }
+
     #[test]
     fn new_productions() {
         let lhs1: Term = Term::Nonterminal(String::from("STRING A"));
@@ -174,6 +230,14 @@ mod tests {
         assert_eq!(p1, p2);
     }
 
+    #[test]
+    fn comments_ignored_by_equality() {
+        let documented = Production::from_str("# A base\n<base> ::= \"A\" | \"C\"").unwrap();
+        let plain = Production::from_str("<base> ::= \"A\" | \"C\"").unwrap();
+        assert_eq!(documented.comment, Some(String::from("A base")));
+        assert_eq!(documented, plain);
+    }
+
     #[test]
     fn remove_from_rhs() {
         let lhs = Term::Nonterminal(String::from("dna"));
@@ -0,0 +1,495 @@
+use std::collections::HashMap;
+use expression::Expression;
+use grammar::Grammar;
+use production::Production;
+use term::{quantifier, Term};
+
+/// Width of a char of text, in pixels
+const CHAR_WIDTH: usize = 8;
+/// Height of the box of a term, which is centred on its line
+const BOX_HEIGHT: usize = 22;
+/// Space between terms in sequence, and between alternatives
+const GAP: usize = 10;
+/// Radius of the arcs lines turn by
+const ARC: usize = 10;
+/// Space around a diagram
+const PADDING: usize = 20;
+
+/// Styles of the elements of a diagram
+const STYLE: &str = "svg.railroad path { fill: none; stroke: #333; stroke-width: 2; }
+svg.railroad rect { stroke: #333; stroke-width: 2; }
+svg.railroad rect.terminal { fill: #fed; }
+svg.railroad rect.nonterminal { fill: #def; }
+svg.railroad text { font: 14px monospace; text-anchor: middle; }
+svg.railroad text.label { font-size: 12px; }
+svg.railroad a text { fill: #03c; text-decoration: underline; }";
+
+/// Escape `text` for XML
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The id of the section of production `index` within an HTML page
+pub(crate) fn anchor(index: usize) -> String {
+    format!("production-{}", index)
+}
+
+/// A railroad diagram: the paths a sentence may take through a production,
+/// left to right along a line
+#[derive(PartialEq, Debug, Clone)]
+enum Diagram {
+    /// Nothing to read
+    Skip,
+    /// Text read as is, or text a class or regex matches, in a rounded box
+    Terminal(String),
+    /// A nonterminal, in a square box linking to its production if known
+    Nonterminal(String, Option<usize>),
+    Sequence(Vec<Diagram>),
+    /// Alternatives, the first along the line and the rest below it
+    Choice(Vec<Diagram>),
+    /// A diagram passed through one or more times, with a label telling how
+    /// many if not any number
+    Loop(Box<Diagram>, Option<String>),
+}
+
+impl Diagram {
+    /// The diagram of alternatives, skippable if one of them is empty
+    fn alternatives<'a, I>(alternatives: I, links: Option<&HashMap<&Term, usize>>) -> Diagram
+    where
+        I: IntoIterator<Item = &'a Expression>,
+    {
+        let mut optional = false;
+        let mut choices = vec![];
+        for expression in alternatives {
+            match Diagram::sequence(expression, links) {
+                Diagram::Skip => optional = true,
+                diagram => choices.push(diagram),
+            }
+        }
+        let choice = match choices.len() {
+            0 => return Diagram::Skip,
+            1 => choices.pop().expect("choice should have an alternative"),
+            _ => Diagram::Choice(choices),
+        };
+        if optional {
+            Diagram::optional(choice)
+        } else {
+            choice
+        }
+    }
+
+    fn sequence(expression: &Expression, links: Option<&HashMap<&Term, usize>>) -> Diagram {
+        let mut items = expression
+            .terms_iter()
+            .map(|term| Diagram::term(term, links))
+            .filter(|diagram| *diagram != Diagram::Skip)
+            .collect::<Vec<_>>();
+        match items.len() {
+            0 => Diagram::Skip,
+            1 => items.pop().expect("sequence should have an item"),
+            _ => Diagram::Sequence(items),
+        }
+    }
+
+    fn term(term: &Term, links: Option<&HashMap<&Term, usize>>) -> Diagram {
+        match *term {
+            Term::Terminal(ref t) if t.is_empty() => Diagram::Skip,
+            Term::Terminal(ref t) => Diagram::Terminal(t.clone()),
+            Term::Nonterminal(ref name) => {
+                let link = links.and_then(|links| links.get(term).cloned());
+                Diagram::Nonterminal(name.clone(), link)
+            }
+            Term::Class(_) | Term::Builtin(_) | Term::Regex(_) => {
+                Diagram::Terminal(term.to_string())
+            }
+            Term::Group(ref alternatives) => Diagram::alternatives(alternatives, links),
+            Term::Repeat(ref repeated, min, max) => {
+                let item = Diagram::term(repeated, links);
+                if item == Diagram::Skip || max == Some(0) {
+                    return Diagram::Skip;
+                }
+                let once = match (min, max) {
+                    (_, Some(1)) => item,
+                    (0, None) | (1, None) => Diagram::Loop(Box::new(item), None),
+                    _ => Diagram::Loop(Box::new(item), Some(quantifier(min, max))),
+                };
+                if min == 0 {
+                    Diagram::optional(once)
+                } else {
+                    once
+                }
+            }
+        }
+    }
+
+    /// A choice of skipping `diagram`, or of its alternatives
+    fn optional(diagram: Diagram) -> Diagram {
+        match diagram {
+            Diagram::Choice(mut items) => {
+                if items[0] != Diagram::Skip {
+                    items.insert(0, Diagram::Skip);
+                }
+                Diagram::Choice(items)
+            }
+            diagram => Diagram::Choice(vec![Diagram::Skip, diagram]),
+        }
+    }
+
+    fn text_width(text: &str) -> usize {
+        text.chars().count() * CHAR_WIDTH + 2 * GAP
+    }
+
+    fn width(&self) -> usize {
+        match *self {
+            Diagram::Skip => 0,
+            Diagram::Terminal(ref text) | Diagram::Nonterminal(ref text, _) => {
+                Diagram::text_width(text)
+            }
+            Diagram::Sequence(ref items) => {
+                items.iter().map(Diagram::width).sum::<usize>() + GAP * (items.len() - 1)
+            }
+            Diagram::Choice(ref items) => {
+                items.iter().map(Diagram::width).max().unwrap_or(0) + 4 * ARC
+            }
+            Diagram::Loop(ref item, ref label) => {
+                let label = label.as_ref().map_or(0, |label| Diagram::text_width(label));
+                item.width().max(label) + 2 * ARC
+            }
+        }
+    }
+
+    /// Height above the line
+    fn up(&self) -> usize {
+        match *self {
+            Diagram::Skip => 0,
+            Diagram::Terminal(_) | Diagram::Nonterminal(..) => BOX_HEIGHT / 2,
+            Diagram::Sequence(ref items) => items.iter().map(Diagram::up).max().unwrap_or(0),
+            Diagram::Choice(ref items) => items[0].up(),
+            Diagram::Loop(ref item, _) => item.up(),
+        }
+    }
+
+    /// Height below the line
+    fn down(&self) -> usize {
+        match *self {
+            Diagram::Skip => 0,
+            Diagram::Terminal(_) | Diagram::Nonterminal(..) => BOX_HEIGHT / 2,
+            Diagram::Sequence(ref items) => items.iter().map(Diagram::down).max().unwrap_or(0),
+            Diagram::Choice(ref items) => {
+                let last = items.last().expect("choice should have alternatives");
+                Diagram::offsets(items).last().cloned().unwrap_or(0) + last.down()
+            }
+            Diagram::Loop(ref item, ref label) => {
+                item.down() + 2 * ARC + if label.is_some() { 2 * GAP } else { 0 }
+            }
+        }
+    }
+
+    /// Offsets of the lines of alternatives below the first
+    fn offsets(items: &[Diagram]) -> Vec<usize> {
+        let mut offsets = vec![0];
+        for pair in items.windows(2) {
+            let previous = *offsets.last().expect("offsets should begin at 0");
+            let below = (pair[0].down() + GAP + pair[1].up()).max(2 * ARC);
+            offsets.push(previous + below);
+        }
+        offsets
+    }
+
+    /// Append the SVG elements of the diagram, starting at `x` on the line
+    /// at `y`, to `svg`
+    fn render(&self, x: usize, y: usize, svg: &mut String) {
+        match *self {
+            Diagram::Skip => (),
+            Diagram::Terminal(ref text) => Diagram::render_box(text, "terminal", None, x, y, svg),
+            Diagram::Nonterminal(ref name, link) => {
+                Diagram::render_box(name, "nonterminal", link, x, y, svg)
+            }
+            Diagram::Sequence(ref items) => {
+                let mut x = x;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        svg.push_str(&format!("<path d=\"M{} {}h{}\"/>\n", x, y, GAP));
+                        x += GAP;
+                    }
+                    item.render(x, y, svg);
+                    x += item.width();
+                }
+            }
+            Diagram::Choice(ref items) => {
+                let width = self.width();
+                let inner = width - 4 * ARC;
+                for (item, offset) in items.iter().zip(Diagram::offsets(items)) {
+                    let left = x + 2 * ARC + (inner - item.width()) / 2;
+                    let right = left + item.width();
+                    let line = y + offset;
+                    if offset == 0 {
+                        svg.push_str(&format!("<path d=\"M{} {}H{}\"/>\n", x, y, left));
+                        item.render(left, line, svg);
+                        svg.push_str(&format!("<path d=\"M{} {}H{}\"/>\n", right, y, x + width));
+                        continue;
+                    }
+                    svg.push_str(&format!(
+                        "<path d=\"M{} {}a{r} {r} 0 0 1 {r} {r}V{}a{r} {r} 0 0 0 {r} {r}H{}\"/>\n",
+                        x,
+                        y,
+                        line - ARC,
+                        left,
+                        r = ARC
+                    ));
+                    item.render(left, line, svg);
+                    svg.push_str(&format!(
+                        "<path d=\"M{} {}H{}a{r} {r} 0 0 0 {r} -{r}\
+                         V{}a{r} {r} 0 0 1 {r} -{r}\"/>\n",
+                        right,
+                        line,
+                        x + width - 2 * ARC,
+                        y + ARC,
+                        r = ARC
+                    ));
+                }
+            }
+            Diagram::Loop(ref item, ref label) => {
+                let width = self.width();
+                let left = x + (width - item.width()) / 2;
+                let right = left + item.width();
+                let back = y + item.down() + 2 * ARC;
+                svg.push_str(&format!("<path d=\"M{} {}H{}\"/>\n", x, y, left));
+                item.render(left, y, svg);
+                svg.push_str(&format!("<path d=\"M{} {}H{}\"/>\n", right, y, x + width));
+                svg.push_str(&format!(
+                    "<path d=\"M{} {}H{}a{r} {r} 0 0 1 {r} {r}V{}a{r} {r} 0 0 1 -{r} {r}\
+                     H{}a{r} {r} 0 0 1 -{r} -{r}V{}a{r} {r} 0 0 1 {r} -{r}\"/>\n",
+                    right,
+                    y,
+                    x + width - ARC,
+                    back - ARC,
+                    x + ARC,
+                    y + ARC,
+                    r = ARC
+                ));
+                if let Some(ref label) = *label {
+                    svg.push_str(&format!(
+                        "<text class=\"label\" x=\"{}\" y=\"{}\">{}</text>\n",
+                        x + width / 2,
+                        back + 2 * GAP - 4,
+                        escape(label)
+                    ));
+                }
+            }
+        }
+    }
+
+    fn render_box(
+        text: &str,
+        class: &str,
+        link: Option<usize>,
+        x: usize,
+        y: usize,
+        svg: &mut String,
+    ) {
+        let width = Diagram::text_width(text);
+        let radius = if class == "terminal" { BOX_HEIGHT / 2 } else { 0 };
+        if let Some(index) = link {
+            svg.push_str(&format!("<a href=\"#{}\">\n", anchor(index)));
+        }
+        svg.push_str(&format!(
+            "<rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>\n",
+            class,
+            x,
+            y - BOX_HEIGHT / 2,
+            width,
+            BOX_HEIGHT,
+            radius
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\">{}</text>\n",
+            x + width / 2,
+            y + 5,
+            escape(text)
+        ));
+        if link.is_some() {
+            svg.push_str("</a>\n");
+        }
+    }
+
+    /// The SVG document of the diagram between a start and an end mark,
+    /// with its own styles if `styled`
+    fn to_svg(&self, styled: bool) -> String {
+        let width = self.width() + 2 * PADDING + 2 * GAP;
+        let height = self.up() + self.down() + 2 * PADDING;
+        let y = PADDING + self.up();
+        let end = PADDING + GAP + self.width();
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" class=\"railroad\" \
+             width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+            w = width,
+            h = height
+        );
+        if styled {
+            svg.push_str(&format!("<style>\n{}\n</style>\n", STYLE));
+        }
+        svg.push_str(&format!(
+            "<path d=\"M{} {}v{}M{} {}h{}\"/>\n",
+            PADDING,
+            y - GAP,
+            2 * GAP,
+            PADDING,
+            y,
+            GAP
+        ));
+        self.render(PADDING + GAP, y, &mut svg);
+        svg.push_str(&format!(
+            "<path d=\"M{} {}h{}m0 -{}v{}\"/>\n",
+            end,
+            y,
+            GAP,
+            GAP,
+            2 * GAP
+        ));
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+impl Production {
+    /// Render the railroad diagram of the production as an SVG document.
+    /// Each alternative is a path through the diagram, reading terminals in
+    /// rounded boxes and nonterminals in square ones. An empty alternative,
+    /// such as `| ""`, makes the others optional, so it is drawn as a path
+    /// around them. Repeats loop back, labelled with how many times unless
+    /// any number.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Production;
+    ///
+    /// fn main() {
+    ///     let production = Production::from_str(
+    ///         "<number> ::= (\"+\" | \"-\")? DIGIT+ | \"\"",
+    ///     ).unwrap();
+    ///     let svg = production.to_railroad_svg();
+    ///     println!("{}", svg);
+    ///
+    ///     # assert!(svg.starts_with("<svg"));
+    ///     # assert!(svg.contains(">DIGIT</text>"));
+    /// }
+    /// ```
+    pub fn to_railroad_svg(&self) -> String {
+        Diagram::alternatives(self.rhs_iter(), None).to_svg(true)
+    }
+}
+
+impl Grammar {
+    /// Render the railroad diagrams of every production on an HTML page,
+    /// under headings of their nonterminals. Nonterminals in a diagram link
+    /// to the diagram of their production. See
+    /// `Production::to_railroad_svg`.
+    pub fn to_railroad_html(&self) -> String {
+        let indices = self.production_indices();
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Grammar</title>\n<style>\n{}\n</style>\n</head>\n<body>\n",
+            STYLE
+        );
+        for (index, production) in self.productions_iter().enumerate() {
+            let diagram = Diagram::alternatives(production.rhs_iter(), Some(&indices));
+            html.push_str(&format!(
+                "<section id=\"{}\">\n<h2>{}</h2>\n{}</section>\n",
+                anchor(index),
+                escape(&production.lhs.to_string()),
+                diagram.to_svg(false)
+            ));
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn terminal(text: &str) -> Diagram {
+        Diagram::Terminal(String::from(text))
+    }
+
+    fn diagram(production: &str) -> Diagram {
+        let production = Production::from_str(production).unwrap();
+        Diagram::alternatives(production.rhs_iter(), None)
+    }
+
+    #[test]
+    fn optional_alternatives() {
+        let expected = Diagram::Choice(vec![
+            Diagram::Skip,
+            Diagram::Sequence(vec![terminal("a"), Diagram::Nonterminal(String::from("b"), None)]),
+            terminal("c"),
+        ]);
+        assert_eq!(diagram("<s> ::= \"a\" <b> | \"\" | \"c\""), expected);
+        assert_eq!(diagram("<s> ::= \"\" | \"\" \"\""), Diagram::Skip);
+        assert_eq!(diagram("<s> ::= (\"a\" | \"\") \"\""), Diagram::optional(terminal("a")));
+    }
+
+    #[test]
+    fn repeats() {
+        let a = || Box::new(terminal("a"));
+        assert_eq!(diagram("<s> ::= \"a\"?"), Diagram::optional(terminal("a")));
+        assert_eq!(diagram("<s> ::= \"a\"*"), Diagram::optional(Diagram::Loop(a(), None)));
+        assert_eq!(diagram("<s> ::= \"a\"+"), Diagram::Loop(a(), None));
+        assert_eq!(diagram("<s> ::= \"a\"{1}"), terminal("a"));
+        assert_eq!(
+            diagram("<s> ::= \"a\"{2,4}"),
+            Diagram::Loop(a(), Some(String::from("{2,4}")))
+        );
+        assert_eq!(
+            diagram("<s> ::= [a-z]{0,3}"),
+            Diagram::optional(Diagram::Loop(
+                Box::new(terminal("[a-z]")),
+                Some(String::from("{0,3}"))
+            ))
+        );
+    }
+
+    #[test]
+    fn geometry() {
+        let choice = diagram("<s> ::= \"ab\" | <c> <d> | \"\"");
+        // a skip, then boxes 36 and 28 + 10 + 28 = 66 wide, stacked below
+        assert_eq!(choice.width(), 66 + 4 * ARC);
+        assert_eq!(choice.up(), 0);
+        assert_eq!(choice.down(), 21 + 32 + 11);
+        let svg = Production::from_str("<s> ::= \"a<b\" <c>").unwrap().to_railroad_svg();
+        assert!(svg.contains("width=\"142\" height=\"62\""));
+        assert!(svg.contains(">a&lt;b</text>"));
+        assert!(svg.contains("<style>"));
+        assert!(!svg.contains("<a href"));
+    }
+
+    #[test]
+    fn html_links() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <item> | <item> \",\" <list>
+            <item> ::= <undefined> | \"x\"",
+        ).unwrap();
+        let html = grammar.to_railroad_html();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<section id=\"production-1\">\n<h2>&lt;item&gt;</h2>\n<svg"));
+        assert_eq!(html.matches("<a href=\"#production-1\">").count(), 2);
+        assert_eq!(html.matches("<a href=\"#production-0\">").count(), 1);
+        assert!(html.contains(">undefined</text>"));
+        assert_eq!(html.matches("<style>").count(), 1);
+    }
+}
@@ -0,0 +1,237 @@
+use std::cmp;
+use grammar::Grammar;
+use parse_tree::{ParseTree, ParseTreeNode};
+use shortest::Shortest;
+use term::Term;
+use error::Error;
+
+/// A replacement which shortens the sentence: the path to a derivation,
+/// and the shortest derivation of the same nonterminal
+type Candidate<'gram> = (Vec<usize>, ParseTree<'gram>);
+
+/// Derivations of nonterminals `depth` levels below the root of `tree`,
+/// as the path to each with its lhs and the length in chars it derives
+fn level<'gram>(tree: &ParseTree<'gram>, depth: usize) -> Vec<(Vec<usize>, &'gram Term, usize)> {
+    let mut found = vec![];
+    let mut stack = vec![(tree, vec![])];
+    while let Some((node, path)) = stack.pop() {
+        if path.len() == depth {
+            found.push((path, node.lhs, node.flatten().chars().count()));
+            continue;
+        }
+        for (i, child) in node.children_iter().enumerate().rev() {
+            if let ParseTreeNode::Nonterminal(ref t) = *child {
+                let mut child_path = path.clone();
+                child_path.push(i);
+                stack.push((t, child_path));
+            }
+        }
+    }
+    found
+}
+
+/// Replace the derivations at the paths of `candidates` within `tree`
+fn replace<'gram>(tree: &ParseTree<'gram>, candidates: &[Candidate<'gram>]) -> ParseTree<'gram> {
+    let mut replaced = tree.clone();
+    for (path, replacement) in candidates {
+        *replaced
+            .subtree_mut(path)
+            .expect("candidate path should be in tree") = replacement.clone();
+    }
+    replaced
+}
+
+impl Grammar {
+    /// Shrink the derivation `tree` while `predicate` keeps holding for the
+    /// sentence it derives, for example while a sentence still crashes the
+    /// parser under test.
+    ///
+    /// This is hierarchical delta debugging: level by level from the root,
+    /// subsets of the derivations at a level are replaced with the shortest
+    /// derivation of the same nonterminal, then each remaining derivation
+    /// with a shorter one of the same nonterminal nested within it, until
+    /// no single replacement keeps `predicate` holding. Every sentence
+    /// tried, and so the result, is derived from self.
+    ///
+    /// Returns `Error::GenerateError` if `predicate` does not hold for `tree`.
+    pub fn reduce_tree<'gram, F>(
+        &'gram self,
+        tree: &ParseTree<'gram>,
+        mut predicate: F,
+    ) -> Result<ParseTree<'gram>, Error>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        if !predicate(&tree.flatten()) {
+            return Err(Error::GenerateError(String::from(
+                "Predicate does not hold for the sentence to reduce!",
+            )));
+        }
+
+        let shortest = Shortest::new(self);
+        let mut reduced = tree.clone();
+        let mut progress = true;
+        while progress {
+            progress = false;
+            let mut depth = 0;
+            loop {
+                let found = level(&reduced, depth);
+                if found.is_empty() {
+                    break;
+                }
+                let mut candidates = found
+                    .into_iter()
+                    .filter_map(|(path, lhs, length)| {
+                        let index = self.production_index(lhs)?;
+                        match shortest.length(index) {
+                            Some(l) if l < length => Some((path, shortest.tree(index)?)),
+                            _ => None,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
+                // try ever smaller subsets of the candidates, as ddmin does
+                let mut chunks = 2;
+                while !candidates.is_empty() {
+                    chunks = cmp::min(chunks, candidates.len());
+                    // rounded up, as there are candidates
+                    let size = (candidates.len() - 1) / chunks + 1;
+                    let mut replaced_any = false;
+                    let mut start = 0;
+                    while start < candidates.len() {
+                        let end = cmp::min(start + size, candidates.len());
+                        let trial = replace(&reduced, &candidates[start..end]);
+                        if predicate(&trial.flatten()) {
+                            reduced = trial;
+                            candidates.drain(start..end);
+                            replaced_any = true;
+                            progress = true;
+                        } else {
+                            start = end;
+                        }
+                    }
+                    if !replaced_any {
+                        if size == 1 {
+                            break;
+                        }
+                        chunks *= 2;
+                    }
+                }
+
+                // then try hoisting nested derivations of the same nonterminal
+                for (path, lhs, length) in level(&reduced, depth) {
+                    let hoisted = {
+                        let node = reduced.subtree(&path).expect("level path should be in tree");
+                        let mut nested = node.nonterminals()
+                            .into_iter()
+                            .skip(1)
+                            .filter(|t| t.lhs == lhs)
+                            .map(|t| (t.flatten().chars().count(), t))
+                            .filter(|&(l, _)| l < length)
+                            .collect::<Vec<_>>();
+                        nested.sort_by_key(|&(l, _)| l);
+                        nested
+                            .into_iter()
+                            .map(|(_, t)| replace(&reduced, &[(path.clone(), t.clone())]))
+                            .find(|trial| predicate(&trial.flatten()))
+                    };
+                    if let Some(trial) = hoisted {
+                        reduced = trial;
+                        progress = true;
+                    }
+                }
+
+                depth += 1;
+            }
+        }
+
+        Ok(reduced)
+    }
+
+    /// Parse `sentence` and shrink it while `predicate` keeps holding.
+    /// See `reduce_tree`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     let crashes = |s: &str| s.contains('G');
+    ///     let reduced = grammar.reduce("TACCAGATTACA", crashes).unwrap();
+    ///     println!("reduced to {}", reduced);
+    ///
+    ///     # assert_eq!(reduced, "GA");
+    /// }
+    /// ```
+    pub fn reduce<F>(&self, sentence: &str, predicate: F) -> Result<String, Error>
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let tree = self.parse_input(sentence)?;
+        Ok(self.reduce_tree(&tree, predicate)?.flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arithmetic() -> Grammar {
+        Grammar::from_str(
+            "<expr> ::= <term> | <expr> \"+\" <term>
+            <term> ::= <factor> | <term> \"*\" <factor>
+            <factor> ::= \"(\" <expr> \")\" | <number>
+            <number> ::= <digit> | <digit> <number>
+            <digit> ::= \"0\" | \"1\" | \"2\" | \"3\"",
+        ).unwrap()
+    }
+
+    #[test]
+    fn reduces_to_interesting_core() {
+        let grammar = arithmetic();
+        let sentence = "(12+3)*(301+(2*(1+1)))+123*(0)";
+        let reduced = grammar.reduce(sentence, |s| s.contains("*(")).unwrap();
+        assert_eq!(reduced, "2*(0)");
+    }
+
+    #[test]
+    fn reduced_stays_in_language() {
+        let grammar = arithmetic();
+        let sentence = "((1+2)*3)+(((0)))*21";
+        let mut tried = vec![];
+        let reduced = grammar
+            .reduce(sentence, |s| {
+                tried.push(String::from(s));
+                s.matches('(').count() >= 2
+            })
+            .unwrap();
+        assert_eq!(reduced, "((0))");
+        for s in tried {
+            assert!(grammar.recognizes(&s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn reduce_tree_keeps_lhs() {
+        let grammar = arithmetic();
+        let tree = grammar.parse_input("12*3").unwrap();
+        let reduced = grammar.reduce_tree(&tree, |_| true).unwrap();
+        assert_eq!(reduced.lhs, tree.lhs);
+        assert_eq!(reduced.flatten(), "0");
+    }
+
+    #[test]
+    fn reduce_predicate_fails() {
+        let grammar = arithmetic();
+        match grammar.reduce("1+1", |s| s.contains('*')) {
+            Err(Error::GenerateError(_)) => (),
+            e => panic!("should be Error::GenerateError: {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use automaton::Dfa;
+use error::Error;
+use grammar::Grammar;
+use term::Term;
+
+/// The automata of the regex terminals of a `Grammar`, each compiled once,
+/// along with the shortest text each matches
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) struct Regexes {
+    dfas: HashMap<String, Result<Dfa, Error>>,
+    shortest: HashMap<String, String>,
+}
+
+impl Regexes {
+    pub fn new(grammar: &Grammar) -> Regexes {
+        let mut regexes = Regexes {
+            dfas: HashMap::new(),
+            shortest: HashMap::new(),
+        };
+        for production in grammar.productions_iter() {
+            for expression in production.rhs_iter() {
+                for term in expression.terms_iter().flat_map(|term| term.leaves()) {
+                    if let Term::Regex(ref pattern) = *term {
+                        if regexes.dfas.contains_key(pattern) {
+                            continue;
+                        }
+                        let dfa = Dfa::from_regex(pattern);
+                        if let Some(shortest) = dfa.as_ref().ok().and_then(|dfa| dfa.shortest()) {
+                            regexes.shortest.insert(pattern.clone(), shortest);
+                        }
+                        regexes.dfas.insert(pattern.clone(), dfa);
+                    }
+                }
+            }
+        }
+        regexes
+    }
+
+    /// Get the automaton of `pattern`, or the error compiling it
+    pub fn dfa(&self, pattern: &str) -> Result<&Dfa, Error> {
+        match self.dfas.get(pattern) {
+            Some(dfa) => dfa.as_ref().map_err(|error| error.clone()),
+            None => Err(Error::GenerateError(format!(
+                "Regex /{}/ is not a term of the grammar",
+                pattern
+            ))),
+        }
+    }
+
+    /// Get the shortest text `pattern` matches, if it matches any
+    pub fn shortest(&self, pattern: &str) -> Option<&str> {
+        self.shortest.get(pattern).map(|shortest| shortest.as_str())
+    }
+}
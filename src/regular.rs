@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use automaton::Nfa;
+use grammar::Grammar;
+use term::Term;
+
+/// Where the recursive nonterminals of a group of mutually recursive
+/// productions appear in their alternatives
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum Linearity {
+    /// The productions do not recurse at all
+    None,
+    /// Always last, as in `<digits> ::= <digit> <digits> | <digit>`
+    Right,
+    /// Always first, as in `<digits> ::= <digits> <digit> | <digit>`
+    Left,
+    /// Anywhere else, or more than one per alternative
+    Nonlinear,
+}
+
+/// The recursion of each production of a `Grammar`. A production derives a
+/// regular language when every production it reaches recurses linearly,
+/// to the right or to the left within each group of mutually recursive
+/// productions. Any grammar which is not self-embedding can be brought to
+/// this form.
+struct Recursion<'gram> {
+    grammar: &'gram Grammar,
+    indices: HashMap<&'gram Term, usize>,
+    /// Per production, its group of mutually recursive productions
+    component: Vec<usize>,
+    components: Vec<Vec<usize>>,
+    linearity: Vec<Linearity>,
+}
+
+impl<'gram> Recursion<'gram> {
+    fn new(grammar: &'gram Grammar) -> Recursion<'gram> {
+        let indices = grammar.production_indices();
+        let graph = grammar.dependency_graph();
+        let components = graph.components().to_vec();
+        let component = (0..graph.nonterminals().len())
+            .map(|vertex| graph.component(vertex))
+            .collect();
+
+        let mut recursion = Recursion {
+            grammar,
+            indices,
+            component,
+            linearity: vec![],
+            components: vec![],
+        };
+        recursion.linearity = components
+            .iter()
+            .map(|members| recursion.linearity(members))
+            .collect();
+        recursion.components = components;
+        recursion
+    }
+
+    /// Position of each term of `index`'s alternatives within the group of `index`
+    fn recursive(&self, index: usize) -> Vec<Vec<usize>> {
+        self.grammar
+            .production(index)
+            .rhs_iter()
+            .map(|rhs| {
+                rhs.terms_iter()
+                    .enumerate()
+                    .filter(|&(_, term)| match self.indices.get(term) {
+                        Some(&n) => self.component[n] == self.component[index],
+                        None => false,
+                    })
+                    .map(|(position, _)| position)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn linearity(&self, members: &[usize]) -> Linearity {
+        let (mut right, mut left, mut recursive) = (true, true, false);
+        for &member in members {
+            let lengths = self.grammar
+                .production(member)
+                .rhs_iter()
+                .map(|rhs| rhs.terms_iter().count())
+                .collect::<Vec<_>>();
+            for (positions, length) in self.recursive(member).into_iter().zip(lengths) {
+                match positions[..] {
+                    [] => (),
+                    [position] => {
+                        recursive = true;
+                        right &= position + 1 == length;
+                        left &= position == 0;
+                    }
+                    _ => return Linearity::Nonlinear,
+                }
+            }
+        }
+        match (recursive, right, left) {
+            (false, _, _) => Linearity::None,
+            (true, true, _) => Linearity::Right,
+            (true, false, true) => Linearity::Left,
+            (true, false, false) => Linearity::Nonlinear,
+        }
+    }
+
+    /// Whether production `index` and every production it reaches recurse linearly
+    fn is_regular(&self, index: usize) -> bool {
+        let mut visited = vec![false; self.component.len()];
+        let mut pending = vec![index];
+        visited[index] = true;
+        while let Some(index) = pending.pop() {
+            if self.linearity[self.component[index]] == Linearity::Nonlinear {
+                return false;
+            }
+            for rhs in self.grammar.production(index).rhs_iter() {
+                for term in rhs.terms_iter() {
+                    if let Some(&n) = self.indices.get(term) {
+                        if !visited[n] {
+                            visited[n] = true;
+                            pending.push(n);
+                        }
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Add states to `nfa` reading a sentence of production `index` from
+    /// state `entry`, returning the state reached after it
+    fn fragment(&self, nfa: &mut Nfa, index: usize, entry: usize) -> usize {
+        let exit = nfa.add_state();
+        let component = self.component[index];
+        let members = &self.components[component];
+        let linearity = self.linearity[component];
+        if linearity == Linearity::None {
+            for rhs in self.grammar.production(index).rhs_iter() {
+                let terms = rhs.terms_iter().collect::<Vec<_>>();
+                let end = self.sequence(nfa, &terms, entry);
+                nfa.add_transition(end, None, exit);
+            }
+            return exit;
+        }
+
+        // a state per member of the group: for right recursion, the state
+        // to read the rest of a sentence from once that member is expanded,
+        // and for left recursion, the state reached once a sentence of that
+        // member has been read
+        let states = members
+            .iter()
+            .map(|&member| (member, nfa.add_state()))
+            .collect::<HashMap<_, _>>();
+        for &member in members {
+            let alternatives = self.grammar.production(member).rhs_iter();
+            for (rhs, positions) in alternatives.zip(self.recursive(member)) {
+                let terms = rhs.terms_iter().collect::<Vec<_>>();
+                match (linearity, positions.first()) {
+                    (Linearity::Right, Some(_)) => {
+                        let (last, rest) = terms.split_last().expect("recursive term should be last");
+                        let end = self.sequence(nfa, rest, states[&member]);
+                        nfa.add_transition(end, None, states[&self.indices[*last]]);
+                    }
+                    (Linearity::Right, None) => {
+                        let end = self.sequence(nfa, &terms, states[&member]);
+                        nfa.add_transition(end, None, exit);
+                    }
+                    (_, Some(_)) => {
+                        let (first, rest) = terms.split_first().expect("recursive term should be first");
+                        let end = self.sequence(nfa, rest, states[&self.indices[*first]]);
+                        nfa.add_transition(end, None, states[&member]);
+                    }
+                    (_, None) => {
+                        let end = self.sequence(nfa, &terms, entry);
+                        nfa.add_transition(end, None, states[&member]);
+                    }
+                }
+            }
+        }
+
+        if linearity == Linearity::Right {
+            nfa.add_transition(entry, None, states[&index]);
+        } else {
+            nfa.add_transition(states[&index], None, exit);
+        }
+        exit
+    }
+
+    /// Add states to `nfa` reading sentences of `terms` in turn from state
+    /// `entry`, returning the state reached after them
+    fn sequence(&self, nfa: &mut Nfa, terms: &[&Term], entry: usize) -> usize {
+        terms.iter().fold(entry, |state, &term| match self.indices.get(term) {
+            Some(&n) => self.fragment(nfa, n, state),
+            None => match *term {
+                Term::Terminal(ref t) => nfa.add_text(state, t),
+                Term::Nonterminal(_) => nfa.add_text(state, &term.to_string()),
+                // a regex failing to compile matches nothing, so leads nowhere
+                Term::Regex(ref pattern) => nfa.add_regex(state, pattern)
+                    .unwrap_or_else(|_| nfa.add_state()),
+                Term::Class(_) | Term::Builtin(_) => {
+                    let end = nfa.add_state();
+                    for range in term.class().into_iter().flat_map(|class| class.matched()) {
+                        nfa.add_transition(state, Some(range), end);
+                    }
+                    end
+                }
+                Term::Group(_) | Term::Repeat(..) => unreachable!("grammar should be desugared"),
+            },
+        })
+    }
+}
+
+impl Grammar {
+    /// Whether the sentences derived from `nonterm` form a regular language
+    /// which `to_nfa` can convert. This holds when every production reached
+    /// from `nonterm` recurses linearly: within each group of mutually
+    /// recursive productions, recursive nonterminals appear once per
+    /// alternative and always last, or always first. Groups and repeats
+    /// count as their desugared productions do; see `desugar`. `false` if
+    /// `nonterm` has no production.
+    pub fn is_regular(&self, nonterm: &Term) -> bool {
+        let grammar = self.desugar().explicit_layout();
+        match grammar.production_index(nonterm) {
+            Some(index) => Recursion::new(&grammar).is_regular(index),
+            None => false,
+        }
+    }
+
+    /// Get the nonterminals with a production which derive a regular
+    /// language, in order of their first production. See `is_regular`.
+    /// These are the token-level rules a lexer could match.
+    pub fn regular_nonterminals(&self) -> Vec<&Term> {
+        // desugared productions follow those of self, so keep their indices
+        let grammar = self.desugar().explicit_layout();
+        let recursion = Recursion::new(&grammar);
+        self.productions_iter()
+            .enumerate()
+            .filter(|&(index, production)| {
+                recursion.indices[&production.lhs] == index && recursion.is_regular(index)
+            })
+            .map(|(_, production)| &production.lhs)
+            .collect()
+    }
+
+    /// Convert the sentences derived from `nonterm` to an `Nfa` accepting
+    /// exactly them, or `None` if they are not regular by `is_regular`.
+    /// Nonterminals without a production are read as their own text, just
+    /// as generation emits them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::{Grammar, Term};
+    ///
+    /// fn main() {
+    ///     let grammar = Grammar::from_str(
+    ///         "<number> ::= <digits> | <digits> \".\" <digits>
+    ///         <digits> ::= <digit> | <digit> <digits>
+    ///         <digit> ::= \"0\" | \"1\" | \"2\" | \"3\" | \"4\"
+    ///             | \"5\" | \"6\" | \"7\" | \"8\" | \"9\"",
+    ///     ).unwrap();
+    ///     let number = Term::Nonterminal(String::from("number"));
+    ///
+    ///     let nfa = grammar.to_nfa(&number).unwrap();
+    ///     assert!(nfa.accepts("3.14"));
+    ///     assert!(!nfa.accepts("3."));
+    ///     assert_eq!(nfa.to_regex(), "[0-9]+(\\.[0-9]+)?");
+    /// }
+    /// ```
+    pub fn to_nfa(&self, nonterm: &Term) -> Option<Nfa> {
+        let grammar = self.desugar().explicit_layout();
+        let index = grammar.production_index(nonterm)?;
+        let recursion = Recursion::new(&grammar);
+        if !recursion.is_regular(index) {
+            return None;
+        }
+        let mut nfa = Nfa::new();
+        let end = recursion.fragment(&mut nfa, index, 0);
+        nfa.set_accepting(end);
+        Some(nfa)
+    }
+
+    /// Write a regular expression matching the sentences derived from
+    /// `nonterm`, or `None` if they are not regular by `is_regular`.
+    /// See `Dfa::to_regex` for its syntax.
+    pub fn to_regex(&self, nonterm: &Term) -> Option<String> {
+        self.to_nfa(nonterm).map(|nfa| nfa.to_regex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use generate::Generator;
+
+    fn nonterminal(name: &str) -> Term {
+        Term::Nonterminal(String::from(name))
+    }
+
+    #[test]
+    fn detect_regular() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <list> | <nested>
+            <list> ::= <item> \",\" <list> | <item>
+            <item> ::= <item> \"x\" | \"y\" | <undefined>
+            <nested> ::= \"(\" <nested> \")\" | \"\"
+            <even> ::= \"a\" <odd> | \"\"
+            <odd> ::= \"a\" <even>
+            <mixed> ::= \"a\" <other> | \"\"
+            <other> ::= <mixed> \"b\"",
+        ).unwrap();
+
+        assert!(grammar.is_regular(&nonterminal("list")));
+        assert!(grammar.is_regular(&nonterminal("item")));
+        assert!(grammar.is_regular(&nonterminal("even")));
+        assert!(!grammar.is_regular(&nonterminal("nested")));
+        assert!(!grammar.is_regular(&nonterminal("s")));
+        assert!(!grammar.is_regular(&nonterminal("mixed")));
+        assert!(!grammar.is_regular(&nonterminal("undefined")));
+
+        let regular = grammar
+            .regular_nonterminals()
+            .into_iter()
+            .map(|term| term.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(regular, vec!["<list>", "<item>", "<even>", "<odd>"]);
+    }
+
+    #[test]
+    fn convert_regular() {
+        let grammar = Grammar::from_str(
+            "<list> ::= <item> \",\" <list> | <item>
+            <item> ::= <item> \"x\" | \"y\" | <undefined>
+            <even> ::= \"a\" <odd> | \"\"
+            <odd> ::= \"a\" <even>",
+        ).unwrap();
+
+        let list = grammar.to_nfa(&nonterminal("list")).unwrap();
+        for &input in &["y", "yxx,<undefined>x,y"] {
+            assert!(list.accepts(input), "{}", input);
+        }
+        for &input in &["", "x", "y,", "yy"] {
+            assert!(!list.accepts(input), "{}", input);
+        }
+        let dfa = list.to_dfa();
+        assert!(dfa.accepts("y,yx"));
+        assert!(!dfa.accepts("y,,y"));
+
+        assert_eq!(grammar.to_regex(&nonterminal("even")).unwrap(), "(aa)*");
+        assert_eq!(grammar.to_regex(&nonterminal("item")).unwrap(), "(y|<undefined>)x*");
+    }
+
+    #[test]
+    fn convert_generated() {
+        let grammar = Grammar::from_str(
+            "<id> ::= <letter> <rest>
+            <rest> ::= <rest> <letter> | <rest> <digit> | \"\"
+            <letter> ::= \"a\" | \"b\" | \"_\"
+            <digit> ::= \"0\" | \"1\"",
+        ).unwrap();
+        let dfa = grammar.to_nfa(&nonterminal("id")).unwrap().to_dfa();
+        let generator = Generator::new(&grammar);
+        for seed in 0..20 {
+            let sentence = generator.generate_from_seed(seed).unwrap();
+            assert!(dfa.accepts(&sentence), "{}", sentence);
+        }
+        assert!(!dfa.accepts("0a"));
+        assert_eq!(grammar.to_regex(&nonterminal("id")).unwrap(), "[_ab][01_ab]*");
+    }
+
+    #[test]
+    fn convert_repeats() {
+        let grammar = Grammar::from_str(
+            "<id> ::= (\"a\" | \"_\") (\"a\" | DIGIT)*
+            <nested> ::= (\"(\" <nested> \")\")?",
+        ).unwrap();
+        assert_eq!(grammar.regular_nonterminals(), vec![&nonterminal("id")]);
+        assert_eq!(grammar.to_regex(&nonterminal("id")).unwrap(), "[_a][0-9a]*");
+        assert!(grammar.to_nfa(&nonterminal("nested")).is_none());
+    }
+}
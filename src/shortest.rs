@@ -0,0 +1,569 @@
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use expression::Expression;
+use grammar::Grammar;
+use parse_tree::{ParseTree, ParseTreeNode};
+use regex::Regexes;
+use term::Term;
+
+/// The smallest derivations of a nonterminal of a `Grammar`
+#[derive(PartialEq, Debug, Clone)]
+pub struct MinimalDerivation<'gram> {
+    pub lhs: &'gram Term,
+    /// Fewest levels of nonterminals in a derivation of `lhs`, so 1 when
+    /// an alternative of `lhs` has only terminals. `None` if `lhs` derives
+    /// no sentence.
+    pub height: Option<usize>,
+    /// Derivation of the shortest sentence `lhs` derives, counted in chars.
+    /// `None` if `lhs` derives no sentence.
+    pub shortest: Option<ParseTree<'gram>>,
+}
+
+impl<'gram> MinimalDerivation<'gram> {
+    /// Get the shortest sentence `lhs` derives, if any
+    pub fn sentence(&self) -> Option<String> {
+        self.shortest.as_ref().map(|tree| tree.flatten())
+    }
+
+    /// Whether `lhs` derives any sentence at all
+    pub fn is_productive(&self) -> bool {
+        self.height.is_some()
+    }
+}
+
+impl<'gram> fmt::Display for MinimalDerivation<'gram> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.height, self.sentence()) {
+            (Some(height), Some(sentence)) => write!(
+                f,
+                "{} height {} shortest {}",
+                self.lhs,
+                height,
+                Term::Terminal(sentence)
+            ),
+            _ => write!(f, "{} derives nothing", self.lhs),
+        }
+    }
+}
+
+/// Length in chars of the shortest text a term without a production stands
+/// for, or `None` for an empty class or a regex matching nothing, which
+/// stand for no text at all
+fn length(regexes: &Regexes, term: &Term) -> Option<usize> {
+    match *term {
+        Term::Terminal(ref t) => Some(t.chars().count()),
+        Term::Nonterminal(_) => Some(term.to_string().chars().count()),
+        Term::Class(_) | Term::Builtin(_) => term.class()
+            .filter(|class| !class.is_empty())
+            .map(|_| 1),
+        Term::Regex(ref pattern) => regexes.shortest(pattern).map(|text| text.chars().count()),
+        Term::Group(_) | Term::Repeat(..) => unreachable!("groups and repeats cost their terms"),
+    }
+}
+
+/// Costs of terms, given the costs of productions. A nonterminal with a
+/// production costs that production, a group its cheapest alternative, a
+/// repeat its minimum number of repetitions, and other terms `terminal`.
+/// Under a layout, the whitespace nonterminal between terms and between
+/// repetitions costs as any other. `None` if the term derives no text.
+struct Cost<'a, 'gram: 'a> {
+    indices: &'a HashMap<&'gram Term, usize>,
+    production: &'a dyn Fn(usize) -> Option<usize>,
+    terminal: &'a dyn Fn(&Term) -> Option<usize>,
+    combine: &'a dyn Fn(usize, usize) -> usize,
+}
+
+impl<'a, 'gram> Cost<'a, 'gram> {
+    /// Cost of `terms` in sequence, separated by the whitespace nonterminal
+    /// `layout` if any, their costs folded with `combine`
+    fn terms<'t, I>(&self, terms: I, layout: Option<&Term>) -> Option<usize>
+    where
+        I: Iterator<Item = &'t Term>,
+    {
+        let whitespace = self.whitespace(layout)?;
+        terms.enumerate().try_fold(0usize, |cost, (i, term)| {
+            let cost = match whitespace {
+                Some(whitespace) if i > 0 => (self.combine)(cost, whitespace),
+                _ => cost,
+            };
+            Some((self.combine)(cost, self.term(term, layout)?))
+        })
+    }
+
+    /// Cost of the whitespace nonterminal `layout` if any, or `None` if it
+    /// derives no text
+    fn whitespace(&self, layout: Option<&Term>) -> Option<Option<usize>> {
+        match layout {
+            Some(whitespace) => self.term(whitespace, None).map(Some),
+            None => Some(None),
+        }
+    }
+
+    fn term(&self, term: &Term, layout: Option<&Term>) -> Option<usize> {
+        match *term {
+            Term::Group(ref alternatives) => {
+                self.cheapest(alternatives, layout).map(|(_, cost)| cost)
+            }
+            Term::Repeat(_, 0, _) => Some(0),
+            Term::Repeat(ref repeated, min, _) => {
+                let cost = self.term(repeated, layout)?;
+                let between = match self.whitespace(layout)? {
+                    Some(whitespace) => (self.combine)(whitespace, cost),
+                    None => cost,
+                };
+                Some((1..min).fold(cost, |total, _| (self.combine)(total, between)))
+            }
+            _ => match self.indices.get(term) {
+                Some(&n) => (self.production)(n),
+                None => (self.terminal)(term),
+            },
+        }
+    }
+
+    /// The cheapest of `alternatives`, laid out by `layout`, with its cost,
+    /// if any derives text
+    fn cheapest(
+        &self,
+        alternatives: &[Expression],
+        layout: Option<&Term>,
+    ) -> Option<(usize, usize)> {
+        alternatives
+            .iter()
+            .enumerate()
+            .filter_map(|(e, rhs)| self.terms(rhs.terms_iter(), layout).map(|cost| (e, cost)))
+            .min_by_key(|&(_, cost)| cost)
+    }
+}
+
+/// For each production, the alternative minimizing a cost, along with that
+/// cost, if the production derives any sentence. An alternative costs its
+/// terms' costs folded with `combine`, then passed through `finish`, and
+/// terms without a production cost `terminal`, if they derive any text.
+///
+/// This is Knuth's generalization of Dijkstra's algorithm. Productions are
+/// settled in order of cost, each by an alternative using only already
+/// settled productions, so minimal derivations never cycle. `combine` and
+/// `finish` must not decrease costs, as with sum or max.
+fn settle<T, C, F>(
+    grammar: &Grammar,
+    terminal: T,
+    combine: C,
+    finish: F,
+) -> Vec<Option<(usize, usize)>>
+where
+    T: Fn(&Term) -> Option<usize>,
+    C: Fn(usize, usize) -> usize,
+    F: Fn(usize) -> usize,
+{
+    let indices = grammar.production_indices();
+    let count = grammar.productions_iter().count();
+    let mut settled: Vec<Option<(usize, usize)>> = vec![None; count];
+
+    loop {
+        let mut best: Option<(usize, usize, usize)> = None;
+        {
+            let cost = Cost {
+                indices: &indices,
+                production: &|n| settled[n].map(|(_, cost)| cost),
+                terminal: &terminal,
+                combine: &combine,
+            };
+            for (index, production) in grammar.productions_iter().enumerate() {
+                if settled[index].is_some() {
+                    continue;
+                }
+                let layout = grammar.layout_between(index);
+                for (expression, rhs) in production.rhs_iter().enumerate() {
+                    match (cost.terms(rhs.terms_iter(), layout).map(&finish), best) {
+                        (Some(cost), Some((_, _, c))) if cost >= c => (),
+                        (Some(cost), _) => best = Some((index, expression, cost)),
+                        (None, _) => (),
+                    }
+                }
+            }
+        }
+
+        match best {
+            Some((index, expression, cost)) => settled[index] = Some((expression, cost)),
+            None => return settled,
+        }
+    }
+}
+
+/// A term left to derive, and the whitespace nonterminal of its production
+type Pending<'gram> = (&'gram Term, Option<&'gram Term>);
+
+/// Push `terms` to `pending`, with the whitespace nonterminal `layout`
+/// between them if any, last first so they are popped in order
+fn schedule<'gram, I>(pending: &mut Vec<Pending<'gram>>, terms: I, layout: Option<&'gram Term>)
+where
+    I: Iterator<Item = &'gram Term>,
+{
+    let start = pending.len();
+    for (i, term) in terms.enumerate() {
+        if let (Some(whitespace), true) = (layout, i > 0) {
+            pending.push((whitespace, None));
+        }
+        pending.push((term, layout));
+    }
+    pending[start..].reverse();
+}
+
+/// The alternatives of each production deriving its shortest sentence,
+/// and beginning its lowest derivation
+#[derive(PartialEq, Debug, Clone)]
+pub(crate) struct Shortest<'gram> {
+    grammar: &'gram Grammar,
+    indices: HashMap<&'gram Term, usize>,
+    /// Per production, the alternative and the length in chars of the
+    /// sentence it derives, if the production derives any
+    lengths: Vec<Option<(usize, usize)>>,
+    /// Per production, the alternative and the height of the derivation it
+    /// begins, if the production derives any
+    heights: Vec<Option<(usize, usize)>>,
+    /// Per production, the alternatives able to complete a derivation
+    productive: Vec<Vec<usize>>,
+    regexes: Regexes,
+}
+
+impl<'gram> Shortest<'gram> {
+    pub fn new(grammar: &'gram Grammar) -> Shortest<'gram> {
+        let regexes = Regexes::new(grammar);
+        let (lengths, heights) = {
+            let length = |term: &Term| length(&regexes, term);
+            let lengths = settle(grammar, length, |a, b| a.saturating_add(b), |l| l);
+            let heights = settle(grammar, |term| length(term).map(|_| 0), cmp::max, |h| h + 1);
+            (lengths, heights)
+        };
+
+        let mut shortest = Shortest {
+            grammar,
+            indices: grammar.production_indices(),
+            lengths,
+            heights,
+            productive: vec![],
+            regexes,
+        };
+        shortest.productive = grammar
+            .productions_iter()
+            .enumerate()
+            .map(|(index, production)| {
+                let layout = grammar.layout_between(index);
+                shortest.productive_alternatives(production.rhs_iter(), layout)
+            })
+            .collect();
+        shortest
+    }
+
+    /// Cost terms by the lengths in chars of the text they derive
+    fn with_lengths<R, F: FnOnce(&Cost) -> R>(&self, f: F) -> R {
+        f(&Cost {
+            indices: &self.indices,
+            production: &|n| self.length(n),
+            terminal: &|term| length(&self.regexes, term),
+            combine: &|a, b| a.saturating_add(b),
+        })
+    }
+
+    /// Cost terms by the heights of the derivations they begin
+    fn with_heights<R, F: FnOnce(&Cost) -> R>(&self, f: F) -> R {
+        f(&Cost {
+            indices: &self.indices,
+            production: &|n| self.height(n),
+            terminal: &|term| length(&self.regexes, term).map(|_| 0),
+            combine: &cmp::max,
+        })
+    }
+
+    /// Get the compiled regex terminals of the grammar
+    pub fn regexes(&self) -> &Regexes {
+        &self.regexes
+    }
+
+    /// Length in chars of the shortest sentence production `index` derives
+    pub fn length(&self, index: usize) -> Option<usize> {
+        self.lengths[index].map(|(_, length)| length)
+    }
+
+    /// Height of the lowest derivation production `index` begins
+    pub fn height(&self, index: usize) -> Option<usize> {
+        self.heights[index].map(|(_, height)| height)
+    }
+
+    /// The alternative of production `index` beginning its lowest derivation
+    pub fn lowest(&self, index: usize) -> Option<usize> {
+        self.heights[index].map(|(expression, _)| expression)
+    }
+
+    /// The alternatives of production `index` able to complete a derivation
+    pub fn productive(&self, index: usize) -> &[usize] {
+        &self.productive[index]
+    }
+
+    /// The alternative of a group laid out by `layout` beginning its lowest
+    /// derivation
+    pub fn lowest_alternative(
+        &self,
+        alternatives: &[Expression],
+        layout: Option<&Term>,
+    ) -> Option<usize> {
+        self.with_heights(|cost| cost.cheapest(alternatives, layout).map(|(e, _)| e))
+    }
+
+    /// The alternatives of a group laid out by `layout` able to complete a
+    /// derivation
+    pub fn productive_alternatives<'e, I>(
+        &self,
+        alternatives: I,
+        layout: Option<&Term>,
+    ) -> Vec<usize>
+    where
+        I: IntoIterator<Item = &'e Expression>,
+    {
+        self.with_heights(|cost| {
+            alternatives
+                .into_iter()
+                .enumerate()
+                .filter(|&(_, rhs)| cost.terms(rhs.terms_iter(), layout).is_some())
+                .map(|(e, _)| e)
+                .collect()
+        })
+    }
+
+    /// The derivation of production `index` begun: its tree with no children
+    /// yet, and the terms of the alternative deriving its shortest sentence
+    /// left to derive
+    fn begin(&self, index: usize) -> Option<(ParseTree<'gram>, Vec<Pending<'gram>>)> {
+        let (expression, _) = self.lengths[index]?;
+        let production = self.grammar.production(index);
+        let rhs = production
+            .rhs_iter()
+            .nth(expression)
+            .expect("chosen alternative should be in production");
+
+        let mut pending = vec![];
+        schedule(&mut pending, rhs.terms_iter(), self.grammar.layout_between(index));
+        Some((ParseTree::new(&production.lhs, rhs, vec![]), pending))
+    }
+
+    /// The derivation of the shortest sentence production `index` derives.
+    /// Derivations are as deep as the shortest sentence is long, so each
+    /// nonterminal is derived in turn on a stack rather than within another.
+    /// Groups and repeats derive in place, rather than as a child of their
+    /// own, and share the layout of their production.
+    pub fn tree(&self, index: usize) -> Option<ParseTree<'gram>> {
+        let mut stack = vec![self.begin(index)?];
+        loop {
+            let next = stack.last_mut().expect("stack should not be empty").1.pop();
+            let (term, layout) = match next {
+                Some(next) => next,
+                None => {
+                    let (tree, _) = stack.pop().expect("stack should not be empty");
+                    match stack.last_mut() {
+                        Some(parent) => parent.0.push_child(ParseTreeNode::Nonterminal(tree)),
+                        None => return Some(tree),
+                    }
+                    continue;
+                }
+            };
+
+            let child = match *term {
+                Term::Terminal(ref t) => ParseTreeNode::Terminal(t.clone()),
+                Term::Nonterminal(_) => match self.indices.get(term) {
+                    Some(&n) => {
+                        stack.push(
+                            self.begin(n).expect("settled alternative should use settled productions"),
+                        );
+                        continue;
+                    }
+                    None => ParseTreeNode::Terminal(term.to_string()),
+                },
+                Term::Class(_) | Term::Builtin(_) => ParseTreeNode::Terminal(
+                    term.class()
+                        .and_then(|class| class.representative())
+                        .expect("settled alternative should use non-empty classes")
+                        .to_string(),
+                ),
+                Term::Regex(ref pattern) => ParseTreeNode::Terminal(String::from(
+                    self.regexes
+                        .shortest(pattern)
+                        .expect("settled alternative should use matching regexes"),
+                )),
+                Term::Group(ref alternatives) => {
+                    let (e, _) = self.with_lengths(|cost| cost.cheapest(alternatives, layout))
+                        .expect("settled alternative should use productive groups");
+                    let pending = &mut stack.last_mut().expect("stack should not be empty").1;
+                    schedule(pending, alternatives[e].terms_iter(), layout);
+                    continue;
+                }
+                Term::Repeat(ref repeated, min, _) => {
+                    let pending = &mut stack.last_mut().expect("stack should not be empty").1;
+                    schedule(pending, (0..min).map(|_| &**repeated), layout);
+                    continue;
+                }
+            };
+            stack.last_mut().expect("stack should not be empty").0.push_child(child);
+        }
+    }
+
+    /// The smallest derivations of production `index`
+    pub fn minimal(&self, index: usize) -> MinimalDerivation<'gram> {
+        MinimalDerivation {
+            lhs: &self.grammar.production(index).lhs,
+            height: self.height(index),
+            shortest: self.tree(index),
+        }
+    }
+}
+
+impl Grammar {
+    /// Find the smallest derivations of each nonterminal with a production,
+    /// in order of their first production: the minimum derivation height,
+    /// and the shortest sentence derived. A nonterminal deriving no sentence
+    /// at all, such as `<PATTERN>` in `<PATTERN> ::= <PATTERN>`, has neither.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// extern crate bnf;
+    /// use bnf::Grammar;
+    ///
+    /// fn main() {
+    ///     let input =
+    ///         "<dna> ::= <base> | <base> <dna>
+    ///         <base> ::= \"A\" | \"C\" | \"G\" | \"T\"";
+    ///     let grammar = Grammar::from_str(input).unwrap();
+    ///     for minimal in grammar.minimal_derivations() {
+    ///         println!("{}", minimal);
+    ///     }
+    ///
+    ///     # let minimal = grammar.minimal_derivations();
+    ///     # assert_eq!(minimal[0].height, Some(2));
+    ///     # assert_eq!(minimal[0].sentence(), Some(String::from("A")));
+    /// }
+    /// ```
+    pub fn minimal_derivations(&self) -> Vec<MinimalDerivation<'_>> {
+        let shortest = Shortest::new(self);
+        let indices = self.production_indices();
+        self.productions_iter()
+            .enumerate()
+            .filter(|&(index, production)| indices[&production.lhs] == index)
+            .map(|(index, _)| shortest.minimal(index))
+            .collect()
+    }
+
+    /// Find the smallest derivations of `nonterm`, or `None` if it has no production.
+    /// See `minimal_derivations`.
+    pub fn minimal_derivation(&self, nonterm: &Term) -> Option<MinimalDerivation<'_>> {
+        let index = self.production_index(nonterm)?;
+        Some(Shortest::new(self).minimal(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn shortest_sentences() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <s> <s> | <long> | <loop>
+            <long> ::= \"abc\" | <short> <short>
+            <short> ::= \"x\" | <short>
+            <loop> ::= <loop> \"y\"",
+        ).unwrap();
+        let shortest = Shortest::new(&grammar);
+
+        assert_eq!(shortest.length(0), Some(2));
+        assert_eq!(shortest.tree(0).unwrap().flatten(), "xx");
+        assert_eq!(shortest.length(2), Some(1));
+        assert_eq!(shortest.length(3), None);
+        assert!(shortest.tree(3).is_none());
+        assert_eq!(shortest.productive(0), &[0, 1]);
+        assert!(shortest.productive(3).is_empty());
+    }
+
+    #[test]
+    fn lowest_derivations() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <a> <b> | <long>
+            <a> ::= <b> | <b> <b> <b>
+            <b> ::= \"b\" <undefined>
+            <long> ::= \"a terminal longer than any other\"",
+        ).unwrap();
+        let shortest = Shortest::new(&grammar);
+
+        assert_eq!(shortest.height(2), Some(1));
+        assert_eq!(shortest.height(1), Some(2));
+        assert_eq!(shortest.height(0), Some(2));
+        assert_eq!(shortest.lowest(0), Some(1));
+        assert_eq!(shortest.tree(0).unwrap().flatten(), "b<undefined>b<undefined>");
+    }
+
+    #[test]
+    fn shortest_groups_and_repeats() {
+        let grammar = Grammar::from_str(
+            "<s> ::= \"abc\"+ | (\"dd\" | <t>){2}
+            <t> ::= \"t\" | (<t> \"t\")*
+            <y> ::= <y>+ | (<y> | \"x\"*) \"y\"
+            <loop> ::= <loop>{1,2} | (\"x\" | <loop>) <loop>",
+        ).unwrap();
+        let shortest = Shortest::new(&grammar);
+
+        assert_eq!(shortest.length(0), Some(0));
+        assert_eq!(shortest.tree(0).unwrap().flatten(), "");
+        assert_eq!(shortest.length(2), Some(1));
+        assert_eq!(shortest.tree(2).unwrap().flatten(), "y");
+        assert_eq!(shortest.height(2), Some(1));
+        assert_eq!(shortest.productive(2), &[0, 1]);
+        assert_eq!(shortest.length(3), None);
+        assert!(shortest.productive(3).is_empty());
+    }
+
+    #[test]
+    fn minimal_derivations() {
+        let grammar = Grammar::from_str(
+            "<s> ::= <s> \"s\" | <t>
+            <t> ::= \"t\" | \"tt\"
+            <t> ::= \"\"
+            <loop> ::= <loop>",
+        ).unwrap();
+        let minimal = grammar.minimal_derivations();
+
+        assert_eq!(minimal.len(), 3);
+        assert_eq!(minimal[0].height, Some(2));
+        assert_eq!(minimal[0].sentence(), Some(String::from("t")));
+        assert_eq!(minimal[0].to_string(), "<s> height 2 shortest \"t\"");
+        assert!(!minimal[2].is_productive());
+        assert_eq!(minimal[2].to_string(), "<loop> derives nothing");
+
+        let t = Term::Nonterminal(String::from("t"));
+        assert_eq!(grammar.minimal_derivation(&t).unwrap().height, Some(1));
+        assert!(grammar.minimal_derivation(&Term::Nonterminal(String::from("u"))).is_none());
+    }
+
+    #[test]
+    fn deep_minimal_derivation() {
+        // a chain of productions, derived on a stack too small to derive
+        // each production within the last
+        let depth = 1_000;
+        let mut input = (1..depth)
+            .map(|i| format!("<n{}> ::= \"a\" <n{}>\n", i, i + 1))
+            .collect::<String>();
+        input.push_str(&format!("<n{}> ::= \"a\"", depth));
+        let derive = move || {
+            let grammar = Grammar::from_str(&input).unwrap();
+            let minimal = grammar.minimal_derivation(&Term::Nonterminal(String::from("n1")));
+            minimal.and_then(|minimal| minimal.sentence())
+        };
+        let sentence = thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(derive)
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(sentence, Some("a".repeat(depth)));
+    }
+}
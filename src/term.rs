@@ -2,17 +2,40 @@ use std::fmt;
 use std::str::FromStr;
 use nom::IResult;
 use parsers;
+use class::{Builtin, CharClass};
+use expression::Expression;
 use error::Error;
 
-/// A Term can represent a Terminal or Nonterminal node
-#[derive(PartialEq, Debug, Clone)]
+/// A Term can represent a Terminal or Nonterminal node, a character
+/// class matching any one char of it, such as `[a-z0-9_]` or the builtin
+/// `DIGIT`, or a regex terminal matching text as a whole, such as
+/// `/[0-9]+(\.[0-9]+)?/`. See `Nfa::from_regex` for the regex syntax.
+///
+/// Terms may also group alternatives in parentheses, as in `("+" | "-")`,
+/// and repeat another term: `?` for zero or one times, `*` for zero or more,
+/// `+` for one or more, `{m}` for exactly m, `{m,}` for at least m, and
+/// `{m,n}` for between m and n. A repeat of a repeat is grouped, as in
+/// `(<a>+)*`, as quantifiers do not stack. See `Grammar::desugar` to lower these into
+/// plain productions.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", content = "value"))]
 pub enum Term {
     Terminal(String),
     Nonterminal(String),
+    Class(CharClass),
+    Builtin(Builtin),
+    Regex(String),
+    /// Alternatives matched in place of the group
+    Group(Vec<Expression>),
+    /// A term repeated a minimum number of times, and at most a maximum
+    /// number of times if there is one
+    Repeat(Box<Term>, usize, Option<usize>),
 }
 
 impl Term {
     // Get `Term` by parsing a string
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, Error> {
         match parsers::term_complete(s.as_bytes()) {
             IResult::Done(_, o) => Ok(o),
@@ -20,6 +43,48 @@ impl Term {
             IResult::Error(e) => Err(Error::from(e)),
         }
     }
+
+    /// Get the chars a class or builtin matches, one at a time
+    pub(crate) fn class(&self) -> Option<CharClass> {
+        match *self {
+            Term::Class(ref class) => Some(class.clone()),
+            Term::Builtin(builtin) => Some(builtin.class()),
+            _ => None,
+        }
+    }
+
+    /// The terms nested within groups and repeats of this term, or the
+    /// term itself if it is neither
+    pub(crate) fn leaves(&self) -> Vec<&Term> {
+        let mut leaves = vec![];
+        let mut stack = vec![self];
+        while let Some(term) = stack.pop() {
+            match *term {
+                Term::Group(ref alternatives) => {
+                    for expression in alternatives.iter().rev() {
+                        let terms = expression.terms_iter().collect::<Vec<_>>();
+                        stack.extend(terms.into_iter().rev());
+                    }
+                }
+                Term::Repeat(ref term, _, _) => stack.push(term),
+                _ => leaves.push(term),
+            }
+        }
+        leaves
+    }
+}
+
+/// The quantifier written after a term to repeat it between `min` and
+/// `max` times
+pub(crate) fn quantifier(min: usize, max: Option<usize>) -> String {
+    match (min, max) {
+        (0, Some(1)) => String::from("?"),
+        (0, None) => String::from("*"),
+        (1, None) => String::from("+"),
+        (min, None) => format!("{{{},}}", min),
+        (min, Some(max)) if min == max => format!("{{{}}}", min),
+        (min, Some(max)) => format!("{{{},{}}}", min, max),
+    }
 }
 
 impl FromStr for Term {
@@ -40,6 +105,33 @@ impl fmt::Display for Term {
                 }
             }
             Term::Nonterminal(ref s) => write!(f, "<{}>", s),
+            Term::Class(ref class) => write!(f, "{}", class),
+            Term::Builtin(builtin) => write!(f, "{}", builtin),
+            Term::Regex(ref pattern) => {
+                write!(f, "/")?;
+                let mut escaped = false;
+                for c in pattern.chars() {
+                    if c == '/' && !escaped {
+                        write!(f, "\\")?;
+                    }
+                    escaped = c == '\\' && !escaped;
+                    write!(f, "{}", c)?;
+                }
+                write!(f, "/")
+            }
+            Term::Group(ref alternatives) => {
+                let display = alternatives
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                write!(f, "({})", display)
+            }
+            // quantifiers do not stack, so a repeated repeat is grouped
+            Term::Repeat(ref term, min, max) => match **term {
+                Term::Repeat(..) => write!(f, "({}){}", term, quantifier(min, max)),
+                _ => write!(f, "{}{}", term, quantifier(min, max)),
+            },
         }
     }
 }
@@ -53,21 +145,51 @@ mod tests {
 
     impl Arbitrary for Term {
         fn arbitrary<G: Gen>(g: &mut G) -> Self {
-            let mut term = String::arbitrary(g);
-            if bool::arbitrary(g) {
-                term = term.chars().filter(|&c| (c != '>')).collect();
-                Term::Nonterminal(term)
+            if g.gen_weighted_bool(16) {
+                let alternatives = (0..g.gen_range(1, 3))
+                    .map(|_| Expression::from_parts((0..g.gen_range(1, 3)).map(|_| leaf(g)).collect()))
+                    .collect();
+                Term::Group(alternatives)
+            } else if g.gen_weighted_bool(16) {
+                let min = g.gen_range(0, 3);
+                let max = match g.gen_range(0, 3) {
+                    0 => None,
+                    extra => Some(min + extra - 1),
+                };
+                Term::Repeat(Box::new(leaf(g)), min, max)
             } else {
-                if term.contains('"') {
-                    term = term.chars().filter(|&c| c != '\'').collect();
-                } else if term.contains('\'') {
-                    term = term.chars().filter(|&c| c != '"').collect();
-                }
-                Term::Terminal(term)
+                leaf(g)
             }
         }
     }
 
+    /// A term which is neither a group nor a repeat
+    fn leaf<G: Gen>(g: &mut G) -> Term {
+        let mut term = String::arbitrary(g);
+        if g.gen_weighted_bool(8) {
+            term = term.chars().filter(|c| c.is_alphanumeric()).collect();
+            Term::Regex(term)
+        } else if g.gen_weighted_bool(8) {
+            Term::Builtin(*g.choose(&Builtin::all()).unwrap())
+        } else if g.gen_weighted_bool(4) {
+            let ranges = Vec::<(char, char)>::arbitrary(g)
+                .into_iter()
+                .map(|(a, b)| (a.min(b), a.max(b)))
+                .collect();
+            Term::Class(CharClass::new(ranges, bool::arbitrary(g)))
+        } else if bool::arbitrary(g) {
+            term = term.chars().filter(|&c| c != '>').collect();
+            Term::Nonterminal(term)
+        } else {
+            if term.contains('"') {
+                term = term.chars().filter(|&c| c != '\'').collect();
+            } else if term.contains('\'') {
+                term = term.chars().filter(|&c| c != '"').collect();
+            }
+            Term::Terminal(term)
+        }
+    }
+
     fn prop_to_string_and_back(term: Term) -> TestResult {
         let to_string = term.to_string();
         let from_str = Term::from_str(&to_string);
@@ -82,6 +204,48 @@ mod tests {
         QuickCheck::new().quickcheck(prop_to_string_and_back as fn(Term) -> TestResult)
     }
 
+    #[cfg(feature = "serde")]
+    fn prop_serde_round_trip(term: Term) -> TestResult {
+        let json = ::serde_json::to_string(&term).expect("term should serialize");
+        match ::serde_json::from_str::<Term>(&json) {
+            Ok(from_json) => TestResult::from_bool(from_json == term),
+            Err(e) => TestResult::error(format!("{} to JSON and back should be safe: {}", json, e)),
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        QuickCheck::new().quickcheck(prop_serde_round_trip as fn(Term) -> TestResult)
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_shape() {
+        let term = Term::from_str("(\"-\" [0-9] | DIGIT /x+/ <n>){1,}").unwrap();
+        let json = ::serde_json::to_value(&term).unwrap();
+        let expected = json!({
+            "type": "Repeat",
+            "value": [
+                {"type": "Group", "value": [
+                    {"terms": [
+                        {"type": "Terminal", "value": "-"},
+                        {"type": "Class", "value": {"ranges": [["0", "9"]], "negated": false}},
+                    ]},
+                    {"terms": [
+                        {"type": "Builtin", "value": "DIGIT"},
+                        {"type": "Regex", "value": "x+"},
+                        {"type": "Nonterminal", "value": "n"},
+                    ]},
+                ]},
+                1,
+                null,
+            ],
+        });
+        assert_eq!(json, expected);
+        assert_eq!(::serde_json::from_value::<Term>(expected).unwrap(), term);
+    }
+
     #[test]
     fn parse_complete() {
         assert_eq!(
@@ -156,6 +320,64 @@ mod tests {
         assert_eq!(Ok(Term::Terminal(String::from("'"))), quote_term);
     }
 
+    #[test]
+    fn parse_class_term() {
+        let class = Term::from_str("[^a-z\\]_]").unwrap();
+        let expected = CharClass::new(vec![('a', 'z'), (']', ']'), ('_', '_')], true);
+        assert_eq!(class, Term::Class(expected));
+        assert_eq!(class.to_string(), "[^a-z\\]_]");
+        assert_eq!(Term::from_str("[]"), Ok(Term::Class(CharClass::new(vec![], false))));
+        assert!(Term::from_str("[z-a]").is_err());
+        assert!(Term::from_str("[a-z").is_err());
+    }
+
+    #[test]
+    fn parse_builtin_term() {
+        assert_eq!(Term::from_str("DIGIT"), Ok(Term::Builtin(Builtin::Digit)));
+        assert_eq!(Term::from_str("NEWLINE"), Ok(Term::Builtin(Builtin::Newline)));
+        assert_eq!(Term::Builtin(Builtin::Whitespace).to_string(), "WHITESPACE");
+        assert!(Term::from_str("DIGITS").is_err());
+        assert!(Term::from_str("digit").is_err());
+    }
+
+    #[test]
+    fn parse_regex_term() {
+        let regex = Term::from_str("/[0-9]+(\\.[0-9]+)?/").unwrap();
+        assert_eq!(regex, Term::Regex(String::from("[0-9]+(\\.[0-9]+)?")));
+        assert_eq!(regex.to_string(), "/[0-9]+(\\.[0-9]+)?/");
+        let slash = Term::from_str("/a\\/b/").unwrap();
+        assert_eq!(slash, Term::Regex(String::from("a\\/b")));
+        assert_eq!(Term::Regex(String::from("a/b")).to_string(), "/a\\/b/");
+        assert_eq!(Term::from_str("//"), Ok(Term::Regex(String::new())));
+        assert!(Term::from_str("/a(/").is_err());
+        assert!(Term::from_str("/a").is_err());
+    }
+
+    #[test]
+    fn parse_group_and_repeat_terms() {
+        let terminal = |t: &str| Term::Terminal(String::from(t));
+        let sign = Term::Group(vec![
+            Expression::from_parts(vec![terminal("+")]),
+            Expression::from_parts(vec![terminal("-"), terminal("-")]),
+        ]);
+        let optional = Term::from_str("(\"+\" | \"-\" \"-\")?").unwrap();
+        assert_eq!(optional, Term::Repeat(Box::new(sign.clone()), 0, Some(1)));
+        assert_eq!(optional.to_string(), "(\"+\" | \"-\" \"-\")?");
+        assert_eq!(Term::from_str("DIGIT{1,3}").unwrap().to_string(), "DIGIT{1,3}");
+        assert_eq!(Term::from_str("\"a\"{0,1}").unwrap().to_string(), "\"a\"?");
+        assert_eq!(Term::from_str("\"a\"{2,}").unwrap().to_string(), "\"a\"{2,}");
+        assert_eq!(Term::from_str("\"a\"{2}").unwrap().to_string(), "\"a\"{2}");
+        assert_eq!(Term::from_str("(<a>+)*").unwrap().to_string(), "(<a>+)*");
+        let nested = Term::Repeat(Box::new(Term::from_str("<a>+").unwrap()), 0, None);
+        assert_eq!(nested.to_string(), "(<a>+)*");
+        assert!(Term::from_str("<a>+*").is_err());
+        assert!(Term::from_str("\"x\"**").is_err());
+        assert!(Term::from_str("\"a\"{2,1}").is_err());
+        assert!(Term::from_str("\"a\"{,1}").is_err());
+        assert!(Term::from_str("()").is_err());
+        assert!(Term::from_str("(\"a\" |)").is_err());
+    }
+
     #[test]
     fn quote_term_to_string_and_back() {
         let quote = Term::Terminal(String::from("\""));
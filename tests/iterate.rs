@@ -12,7 +12,7 @@ fn iterate_grammar() {
 
     let left_hand_terms: Vec<&Term> = dna_grammar
         .productions_iter()
-        .map(|ref prod| &prod.lhs)
+        .map(|prod| &prod.lhs)
         .collect();
 
     // should be as many left hand terms as productions
@@ -28,7 +28,7 @@ fn iterate_grammar() {
         .collect();
 
     // check nonterminals are in left and right hand terms collection
-    for term in ["dna", "base"].into_iter() {
+    for term in ["dna", "base"].iter() {
         let term_string = String::from(*term);
         let expected_nonterminal = Term::Nonterminal(term_string);
 
@@ -52,7 +52,7 @@ fn iterate_grammar() {
         .collect();
 
     // check terminals are only on right hand side
-    for term in ["A", "C", "G", "T"].into_iter() {
+    for term in ["A", "C", "G", "T"].iter() {
         let term_string = String::from(*term);
         let expected_terminal = Term::Terminal(term_string);
 
@@ -78,10 +78,7 @@ fn mutably_iterate_grammar() {
             .productions_iter_mut()
             .flat_map(|prod| prod.rhs_iter_mut())
             .flat_map(|expr| expr.terms_iter_mut())
-            .filter(|&&mut ref term| match *term {
-                Term::Terminal(_) => true,
-                _ => false,
-            });
+            .filter(|&&mut ref term| matches!(*term, Term::Terminal(_)));
 
         // transform all terminals to "Z"
         for term in terminals {
@@ -94,12 +91,9 @@ fn mutably_iterate_grammar() {
         .productions_iter()
         .flat_map(|prod| prod.rhs_iter())
         .flat_map(|expr| expr.terms_iter())
-        .filter(|&term| match *term {
-            Term::Terminal(_) => true,
-            _ => false,
-        })
+        .filter(|&term| matches!(*term, Term::Terminal(_)))
         .all(|term| match *term {
-            Term::Terminal(ref s) => *s == String::from("Z"),
+            Term::Terminal(ref s) => s == "Z",
             _ => false,
         });
 